@@ -1,32 +1,99 @@
-use bincode::config::Configuration;
 use criterion::{Criterion, criterion_group, criterion_main};
 use order_propagation::{
+    codec::{BincodeCodec, BorshCodec, Codec, FixedCodec, PostcardCodec},
+    compression::Compression,
+    hlc::HybridClock,
+    market::MarketRegistry,
     network::NodeId,
+    order::Order,
     packet::{GossipPacket, PacketId},
 };
 use std::hint::black_box;
 
-fn bincode_codec(packet: GossipPacket, config: Configuration) -> GossipPacket {
-    let serialized = packet.bincode_serialize(config);
-    serialized.bincode_deserialize(config)
+fn round_trip<C: Codec>(codec: &C, packet: GossipPacket) -> GossipPacket {
+    let serialized = codec.encode(&packet);
+    codec.decode(&serialized).unwrap()
 }
 
-fn borsh_codec(packet: GossipPacket) -> GossipPacket {
-    let serialized = packet.borsh_serialize();
-    serialized.borsh_deserialize()
+fn bench_codec<C: Codec>(c: &mut Criterion, name: &str, codec: C, packet: &GossipPacket) {
+    println!(
+        "{name}: serialized packet size = {} bytes",
+        codec.encode(packet).as_bytes().len()
+    );
+
+    c.bench_function(name, |b| {
+        b.iter(|| round_trip(&codec, black_box(packet.clone())))
+    });
 }
 
-fn criterion_benchmark(c: &mut Criterion) {
-    let packet = GossipPacket::new_with_random_order(PacketId::new(1), NodeId::new(1), 1);
-    let bincode_config: Configuration = bincode::config::standard();
+/// Number of orders sharing a packet in the compression benchmarks
+const BATCH_SIZE: u64 = 32;
 
-    c.bench_function("bincode_codec", |b| {
-        b.iter(|| bincode_codec(black_box(packet.clone()), bincode_config))
-    });
-    c.bench_function("borsh_codec", |b| {
-        b.iter(|| borsh_codec(black_box(packet.clone())))
+/// Encodes a batch of orders, compresses it, then decompresses and decodes it
+fn bench_batch(
+    c: &mut Criterion,
+    name: &str,
+    orders: &[Order],
+    compression: Compression,
+    encode: impl Fn(&[Order]) -> Vec<u8>,
+    decode: impl Fn(&[u8]) -> Vec<Order>,
+) {
+    let encoded = encode(orders);
+    let (used, compressed) = compression.compress(&encoded, 0);
+    println!(
+        "{name}: batch of {BATCH_SIZE} orders = {} bytes, {:?} = {} bytes",
+        encoded.len(),
+        used,
+        compressed.len()
+    );
+
+    c.bench_function(name, |b| {
+        b.iter(|| {
+            let (used, compressed) = compression.compress(&encode(black_box(orders)), 0);
+            decode(&used.decompress(&compressed).unwrap())
+        })
     });
 }
 
+fn criterion_benchmark(c: &mut Criterion) {
+    let packet = GossipPacket::new_with_random_order(PacketId::new(1), NodeId::new(1), 1)
+        .with_timestamp(HybridClock::new().now());
+
+    bench_codec(c, "bincode_codec", BincodeCodec::default(), &packet);
+    bench_codec(c, "borsh_codec", BorshCodec, &packet);
+    bench_codec(c, "postcard_codec", PostcardCodec, &packet);
+    bench_codec(c, "fixed_codec", FixedCodec, &packet);
+
+    // Orders of a single trader, with consecutive ids
+    let markets = MarketRegistry::default();
+    let orders: Vec<Order> = (0..BATCH_SIZE)
+        .map(|id| Order {
+            id,
+            ..markets.random_order()
+        })
+        .collect();
+    let bincode_config = bincode::config::standard();
+
+    for compression in [Compression::None, Compression::Lz4, Compression::Zstd] {
+        let suffix = format!("{compression:?}").to_lowercase();
+        bench_batch(
+            c,
+            &format!("borsh_batch_{suffix}"),
+            &orders,
+            compression,
+            |orders| borsh::to_vec(orders).unwrap(),
+            |bytes| borsh::from_slice(bytes).unwrap(),
+        );
+        bench_batch(
+            c,
+            &format!("bincode_batch_{suffix}"),
+            &orders,
+            compression,
+            |orders| bincode::encode_to_vec(orders, bincode_config).unwrap(),
+            |bytes| bincode::decode_from_slice(bytes, bincode_config).unwrap().0,
+        );
+    }
+}
+
 criterion_group!(benches, criterion_benchmark);
 criterion_main!(benches);
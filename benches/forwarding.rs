@@ -0,0 +1,83 @@
+use criterion::{BatchSize, Criterion, criterion_group, criterion_main};
+use order_propagation::{
+    codec::{BorshCodec, Codec},
+    network::NodeId,
+    packet::{GossipPacket, PacketId, SerialiedPacket},
+    signing,
+};
+use std::hint::black_box;
+
+/// Number of neighbors a packet is forwarded to, as in the default config
+const NUM_PEERS: usize = 8;
+
+/// Decodes the whole packet and encodes a new one for every neighbor
+fn decode_and_reencode(received: SerialiedPacket, node_id: &NodeId) -> Vec<SerialiedPacket> {
+    let packet = BorshCodec.decode(&received).unwrap();
+    let packet_to_send = GossipPacket::new(
+        packet.id,
+        node_id.clone(),
+        packet.ttl.saturating_sub(1),
+        packet.action,
+    )
+    .with_timestamp(packet.timestamp);
+
+    (0..NUM_PEERS)
+        .map(|_| BorshCodec.encode(&packet_to_send))
+        .collect()
+}
+
+/// Reads the header, patches it in place and shares the buffer between neighbors
+fn patch_header(received: SerialiedPacket, node_id: &NodeId) -> Vec<SerialiedPacket> {
+    let ttl = received.ttl();
+    let packet_to_send = received.forwarded(node_id, ttl.saturating_sub(1));
+
+    (0..NUM_PEERS).map(|_| packet_to_send.clone()).collect()
+}
+
+/// Same as `patch_header`, after checking the originator's signature
+fn verify_and_patch_header(received: SerialiedPacket, node_id: &NodeId) -> Vec<SerialiedPacket> {
+    signing::verify(&received).unwrap();
+    patch_header(received, node_id)
+}
+
+fn criterion_benchmark(c: &mut Criterion) {
+    let packet = GossipPacket::new_with_random_order(PacketId::new(1), NodeId::new(1), 10);
+    let bytes = BorshCodec.encode(&packet).as_bytes().to_vec();
+    let node_id = NodeId::new(2);
+
+    // Every iteration gets its own buffer, as a packet freshly read from a socket
+    let received = || SerialiedPacket::from(bytes.clone());
+
+    c.bench_function("forward_decode_and_reencode", |b| {
+        b.iter_batched(
+            received,
+            |packet| decode_and_reencode(black_box(packet), &node_id),
+            BatchSize::SmallInput,
+        )
+    });
+    c.bench_function("forward_patch_header", |b| {
+        b.iter_batched(
+            received,
+            |packet| patch_header(black_box(packet), &node_id),
+            BatchSize::SmallInput,
+        )
+    });
+
+    let key = signing::generate_trader_key();
+    let signed_bytes = BorshCodec.encode_signed(&packet, &key).as_bytes().to_vec();
+    println!(
+        "signed packet size = {} bytes (unsigned: {} bytes)",
+        signed_bytes.len(),
+        bytes.len()
+    );
+    c.bench_function("forward_verify_and_patch_header", |b| {
+        b.iter_batched(
+            || SerialiedPacket::from(signed_bytes.clone()),
+            |packet| verify_and_patch_header(black_box(packet), &node_id),
+            BatchSize::SmallInput,
+        )
+    });
+}
+
+criterion_group!(benches, criterion_benchmark);
+criterion_main!(benches);
@@ -0,0 +1,545 @@
+use std::{
+    collections::{BTreeMap, HashMap, VecDeque},
+    hash::{Hash, Hasher},
+};
+
+use crate::{
+    fixed_point::{Price, Quantity},
+    order::{MarketId, Order, OrderType, Side, TimeInForce},
+};
+
+/// Trade between an order resting in the book (maker) and an incoming one (taker)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Fill {
+    pub market: MarketId,
+    pub maker_order_id: u64,
+    pub taker_order_id: u64,
+    /// Price of the maker's level
+    pub price: Price,
+    pub quantity: Quantity,
+}
+
+/// Total quantity resting at a price
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Level {
+    pub price: Price,
+    pub quantity: Quantity,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct TopOfBook {
+    pub best_bid: Option<Level>,
+    pub best_ask: Option<Level>,
+}
+
+impl TopOfBook {
+    /// Gap between the best ask and the best bid, if the book has both sides
+    pub fn spread(&self) -> Option<Price> {
+        self.best_ask?.price.checked_sub(self.best_bid?.price)
+    }
+}
+
+/// Best levels of each side, best first
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Depth {
+    pub bids: Vec<Level>,
+    pub asks: Vec<Level>,
+}
+
+/// Limit order book of a market with price-time priority: incoming orders trade against the best
+/// price first, and against the oldest order of a price level first.
+///
+/// Orders are expected to follow their market's rules (see [`crate::market::MarketRegistry`]).
+#[derive(Debug, Clone)]
+pub struct OrderBook {
+    market: MarketId,
+    /// Resting orders with their remaining quantity, by price level, oldest first
+    bids: BTreeMap<Price, VecDeque<Order>>,
+    asks: BTreeMap<Price, VecDeque<Order>>,
+    /// Side and level of each resting order
+    resting: HashMap<u64, (Side, Price)>,
+    /// Stop orders waiting for their trigger, oldest first
+    stops: Vec<Order>,
+    /// Price of the last trade, which triggers the stops
+    last_price: Option<Price>,
+}
+
+impl OrderBook {
+    pub fn new(market: MarketId) -> Self {
+        Self {
+            market,
+            bids: BTreeMap::new(),
+            asks: BTreeMap::new(),
+            resting: HashMap::new(),
+            stops: Vec::new(),
+            last_price: None,
+        }
+    }
+
+    pub fn market(&self) -> MarketId {
+        self.market
+    }
+
+    pub fn last_price(&self) -> Option<Price> {
+        self.last_price
+    }
+
+    /// Matches an incoming order and returns the resulting fills. Its remaining quantity rests in
+    /// the book if it is a good-till-cancel or post-only limit order. Stop orders wait until the market trades
+    /// at their trigger, and the trades of an order can trigger stops in turn.
+    pub fn submit(&mut self, order: Order) -> Vec<Fill> {
+        let mut fills = Vec::new();
+        self.process(order, &mut fills);
+
+        while let Some(index) = self.stops.iter().position(|stop| self.is_triggered(stop)) {
+            let stop = self.stops.remove(index);
+            self.process(stop, &mut fills);
+        }
+
+        fills
+    }
+
+    /// Removes a resting or stop order, returns it with its remaining quantity
+    pub fn cancel(&mut self, order_id: u64) -> Option<Order> {
+        if let Some((side, price)) = self.resting.remove(&order_id) {
+            let levels = self.levels_mut(side);
+            let queue = levels.get_mut(&price).expect("Resting order without level");
+            let index = queue
+                .iter()
+                .position(|order| order.id == order_id)
+                .expect("Resting order not in its level");
+            let order = queue.remove(index);
+            if queue.is_empty() {
+                levels.remove(&price);
+            }
+            return order;
+        }
+
+        let index = self.stops.iter().position(|stop| stop.id == order_id)?;
+        Some(self.stops.remove(index))
+    }
+
+    /// Replaces the price and remaining quantity of a resting or stop order with the ones of
+    /// `amended`. Reducing the quantity keeps the order's priority, any other change loses it and
+    /// can make the order trade. Returns `None` if the order isn't in the book.
+    pub fn amend(&mut self, amended: Order) -> Option<Vec<Fill>> {
+        if let Some(stop) = self.stops.iter_mut().find(|stop| stop.id == amended.id) {
+            *stop = amended;
+            return Some(Vec::new());
+        }
+
+        let (side, price) = *self.resting.get(&amended.id)?;
+        if amended.price == price {
+            let order = self
+                .levels_mut(side)
+                .get_mut(&price)
+                .and_then(|queue| queue.iter_mut().find(|order| order.id == amended.id))
+                .expect("Resting order not in its level");
+            if amended.quantity <= order.quantity {
+                order.quantity = amended.quantity;
+                return Some(Vec::new());
+            }
+        }
+
+        self.cancel(amended.id);
+        Some(self.submit(amended))
+    }
+
+    /// Resting or stop order, with its remaining quantity
+    pub fn get(&self, order_id: u64) -> Option<&Order> {
+        match self.resting.get(&order_id) {
+            Some((side, price)) => self.levels(*side)[price]
+                .iter()
+                .find(|order| order.id == order_id),
+            None => self.stops.iter().find(|stop| stop.id == order_id),
+        }
+    }
+
+    pub fn top_of_book(&self) -> TopOfBook {
+        let depth = self.depth(1);
+        TopOfBook {
+            best_bid: depth.bids.first().copied(),
+            best_ask: depth.asks.first().copied(),
+        }
+    }
+
+    /// Up to `num_levels` levels of each side
+    pub fn depth(&self, num_levels: usize) -> Depth {
+        let level = |(price, queue): (&Price, &VecDeque<Order>)| Level {
+            price: *price,
+            quantity: Quantity::from_lots(queue.iter().map(|order| order.quantity.lots()).sum()),
+        };
+        Depth {
+            bids: self.bids.iter().rev().take(num_levels).map(level).collect(),
+            asks: self.asks.iter().take(num_levels).map(level).collect(),
+        }
+    }
+
+    fn process(&mut self, mut order: Order, fills: &mut Vec<Fill>) {
+        if order.order_type.trigger().is_some() {
+            if !self.is_triggered(&order) {
+                self.stops.push(order);
+                return;
+            }
+            // A triggered stop is a market order, a triggered stop-limit a limit order
+            order.order_type = if order.order_type.has_price() {
+                OrderType::Limit
+            } else {
+                OrderType::Market
+            };
+        }
+
+        // Market and stop orders take any price
+        let limit = order.order_type.has_price().then_some(order.price);
+        match order.time_in_force {
+            TimeInForce::PostOnly if self.best_crossing(order.side, limit).is_some() => return,
+            TimeInForce::FillOrKill if self.available(order.side, limit) < order.quantity => {
+                return;
+            }
+            _ => {}
+        }
+
+        let remaining = self.match_order(&order, limit, fills);
+        let rests = matches!(
+            order.time_in_force,
+            TimeInForce::GoodTillCancel | TimeInForce::PostOnly
+        );
+        if remaining.lots() > 0 && rests && limit.is_some() {
+            self.resting.insert(order.id, (order.side, order.price));
+            self.levels_mut(order.side)
+                .entry(order.price)
+                .or_default()
+                .push_back(Order {
+                    quantity: remaining,
+                    ..order
+                });
+        }
+    }
+
+    /// Trades `order` against the opposite side, returns its remaining quantity
+    fn match_order(
+        &mut self,
+        order: &Order,
+        limit: Option<Price>,
+        fills: &mut Vec<Fill>,
+    ) -> Quantity {
+        let mut remaining = order.quantity;
+
+        while remaining.lots() > 0 {
+            let Some(price) = self.best_crossing(order.side, limit) else {
+                break;
+            };
+            let (levels, resting) = match order.side {
+                Side::Bid => (&mut self.asks, &mut self.resting),
+                Side::Ask => (&mut self.bids, &mut self.resting),
+            };
+            let queue = levels.get_mut(&price).unwrap();
+
+            while let Some(maker) = queue.front_mut() {
+                let quantity = remaining.min(maker.quantity);
+                fills.push(Fill {
+                    market: self.market,
+                    maker_order_id: maker.id,
+                    taker_order_id: order.id,
+                    price,
+                    quantity,
+                });
+                maker.quantity = maker.quantity.checked_sub(quantity).unwrap();
+                remaining = remaining.checked_sub(quantity).unwrap();
+
+                if maker.quantity.lots() == 0 {
+                    resting.remove(&maker.id);
+                    queue.pop_front();
+                }
+                if remaining.lots() == 0 {
+                    break;
+                }
+            }
+            if queue.is_empty() {
+                levels.remove(&price);
+            }
+            self.last_price = Some(price);
+        }
+
+        remaining
+    }
+
+    /// Best opposite price an order of `side` can trade at
+    fn best_crossing(&self, side: Side, limit: Option<Price>) -> Option<Price> {
+        let best = match side {
+            Side::Bid => self.asks.keys().next(),
+            Side::Ask => self.bids.keys().next_back(),
+        }
+        .copied()?;
+        crosses(side, limit, best).then_some(best)
+    }
+
+    /// Opposite quantity an order of `side` can trade against
+    fn available(&self, side: Side, limit: Option<Price>) -> Quantity {
+        let levels: Box<dyn Iterator<Item = (&Price, &VecDeque<Order>)>> = match side {
+            Side::Bid => Box::new(self.asks.iter()),
+            Side::Ask => Box::new(self.bids.iter().rev()),
+        };
+        let lots = levels
+            .take_while(|(price, _)| crosses(side, limit, **price))
+            .flat_map(|(_, queue)| queue)
+            .map(|order| order.quantity.lots())
+            .sum();
+        Quantity::from_lots(lots)
+    }
+
+    /// Whether the last trade reached a stop order's trigger: at or above it for a bid, at or
+    /// below it for an ask
+    fn is_triggered(&self, stop: &Order) -> bool {
+        match (stop.order_type.trigger(), self.last_price) {
+            (Some(trigger), Some(last)) => match stop.side {
+                Side::Bid => last >= trigger,
+                Side::Ask => last <= trigger,
+            },
+            _ => false,
+        }
+    }
+
+    fn levels(&self, side: Side) -> &BTreeMap<Price, VecDeque<Order>> {
+        match side {
+            Side::Bid => &self.bids,
+            Side::Ask => &self.asks,
+        }
+    }
+
+    fn levels_mut(&mut self, side: Side) -> &mut BTreeMap<Price, VecDeque<Order>> {
+        match side {
+            Side::Bid => &mut self.bids,
+            Side::Ask => &mut self.asks,
+        }
+    }
+}
+
+/// Two books hash the same when they hold the same orders in the same sequence, with the same
+/// remaining quantities, stops and last price
+impl Hash for OrderBook {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.market.hash(state);
+        self.bids.hash(state);
+        self.asks.hash(state);
+        self.stops.hash(state);
+        self.last_price.hash(state);
+    }
+}
+
+/// Whether an order of `side` with the given limit can trade at `price`
+fn crosses(side: Side, limit: Option<Price>, price: Price) -> bool {
+    match (side, limit) {
+        (_, None) => true,
+        (Side::Bid, Some(limit)) => price <= limit,
+        (Side::Ask, Some(limit)) => price >= limit,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MARKET: MarketId = MarketId::new(0);
+
+    fn order(id: u64, side: Side, price: u64, quantity: u64) -> Order {
+        Order::new(
+            id,
+            MARKET,
+            side,
+            Price::from_ticks(price),
+            Quantity::from_lots(quantity),
+        )
+    }
+
+    fn level(price: u64, quantity: u64) -> Level {
+        Level {
+            price: Price::from_ticks(price),
+            quantity: Quantity::from_lots(quantity),
+        }
+    }
+
+    fn fill(maker: u64, taker: u64, price: u64, quantity: u64) -> Fill {
+        Fill {
+            market: MARKET,
+            maker_order_id: maker,
+            taker_order_id: taker,
+            price: Price::from_ticks(price),
+            quantity: Quantity::from_lots(quantity),
+        }
+    }
+
+    /// Asks of 5 lots at 101 (orders 1 then 2) and 102 (order 3), a bid of 5 lots at 99 (order 4)
+    fn sample_book() -> OrderBook {
+        let mut book = OrderBook::new(MARKET);
+        for order in [
+            order(1, Side::Ask, 101, 2),
+            order(2, Side::Ask, 101, 3),
+            order(3, Side::Ask, 102, 5),
+            order(4, Side::Bid, 99, 5),
+        ] {
+            assert_eq!(book.submit(order), vec![]);
+        }
+        book
+    }
+
+    #[test]
+    fn test_price_time_priority() {
+        let mut book = sample_book();
+        assert_eq!(
+            book.top_of_book(),
+            TopOfBook {
+                best_bid: Some(level(99, 5)),
+                best_ask: Some(level(101, 5)),
+            }
+        );
+
+        // Best price first, oldest first within a level, at the maker's price
+        assert_eq!(
+            book.submit(order(5, Side::Bid, 102, 6)),
+            vec![fill(1, 5, 101, 2), fill(2, 5, 101, 3), fill(3, 5, 102, 1)]
+        );
+        assert_eq!(book.last_price(), Some(Price::from_ticks(102)));
+        assert_eq!(
+            book.depth(5),
+            Depth {
+                bids: vec![level(99, 5)],
+                asks: vec![level(102, 4)],
+            }
+        );
+
+        // What doesn't trade rests
+        assert_eq!(
+            book.submit(order(6, Side::Ask, 98, 7)),
+            vec![fill(4, 6, 99, 5)]
+        );
+        assert_eq!(book.get(6).unwrap().quantity, Quantity::from_lots(2));
+        assert_eq!(
+            book.depth(5),
+            Depth {
+                bids: vec![],
+                asks: vec![level(98, 2), level(102, 4)],
+            }
+        );
+
+        assert_eq!(book.cancel(6).unwrap().quantity, Quantity::from_lots(2));
+        assert_eq!(book.cancel(6), None);
+        assert_eq!(book.top_of_book().best_ask, Some(level(102, 4)));
+    }
+
+    #[test]
+    fn test_time_in_force() {
+        // Immediate or cancel: the rest is dropped
+        let mut book = sample_book();
+        let ioc = order(5, Side::Bid, 101, 10)
+            .with_type(OrderType::Limit, TimeInForce::ImmediateOrCancel);
+        assert_eq!(
+            book.submit(ioc),
+            vec![fill(1, 5, 101, 2), fill(2, 5, 101, 3)]
+        );
+        assert_eq!(book.get(5), None);
+        assert_eq!(book.top_of_book().best_bid, Some(level(99, 5)));
+
+        // Fill or kill: all or nothing
+        let mut book = sample_book();
+        let fok = |quantity| {
+            order(5, Side::Bid, 101, quantity).with_type(OrderType::Limit, TimeInForce::FillOrKill)
+        };
+        assert_eq!(book.submit(fok(6)), vec![]);
+        assert_eq!(book.depth(5), sample_book().depth(5));
+        assert_eq!(book.submit(fok(5)).len(), 2);
+
+        // Post only: cancelled if it would trade
+        let mut book = sample_book();
+        let post_only = |price| {
+            order(5, Side::Bid, price, 1).with_type(OrderType::Limit, TimeInForce::PostOnly)
+        };
+        assert_eq!(book.submit(post_only(101)), vec![]);
+        assert_eq!(book.get(5), None);
+        assert_eq!(book.submit(post_only(100)), vec![]);
+        assert_eq!(book.top_of_book().best_bid, Some(level(100, 1)));
+
+        // Market: any price, never rests
+        let mut book = sample_book();
+        let market =
+            order(5, Side::Ask, 0, 8).with_type(OrderType::Market, TimeInForce::ImmediateOrCancel);
+        assert_eq!(book.submit(market), vec![fill(4, 5, 99, 5)]);
+        assert_eq!(book.depth(5).bids, vec![]);
+        assert_eq!(book.get(5), None);
+    }
+
+    #[test]
+    fn test_stops() {
+        let mut book = sample_book();
+        let stop = order(5, Side::Bid, 0, 4).with_type(
+            OrderType::Stop {
+                trigger: Price::from_ticks(101),
+            },
+            TimeInForce::ImmediateOrCancel,
+        );
+        let stop_limit = order(6, Side::Ask, 98, 6).with_type(
+            OrderType::StopLimit {
+                trigger: Price::from_ticks(99),
+            },
+            TimeInForce::GoodTillCancel,
+        );
+
+        // Nothing traded yet
+        assert_eq!(book.submit(stop), vec![]);
+        assert_eq!(book.submit(stop_limit), vec![]);
+        assert!(book.get(5).is_some());
+
+        // A trade at 101 triggers the stop, which buys what is left at 101 then at 102
+        assert_eq!(
+            book.submit(order(7, Side::Bid, 101, 1)),
+            vec![fill(1, 7, 101, 1), fill(1, 5, 101, 1), fill(2, 5, 101, 3),]
+        );
+        assert_eq!(book.get(5), None);
+
+        // A trade at 99 triggers the stop-limit, whose rest stays in the book as a limit order
+        assert_eq!(
+            book.submit(order(8, Side::Ask, 99, 1)),
+            vec![fill(4, 8, 99, 1), fill(4, 6, 99, 4)]
+        );
+        let rest = book.get(6).unwrap();
+        assert_eq!(rest.order_type, OrderType::Limit);
+        assert_eq!(rest.quantity, Quantity::from_lots(2));
+        assert_eq!(book.top_of_book().best_ask, Some(level(98, 2)));
+    }
+
+    #[test]
+    fn test_amend() {
+        let amended = |book: &OrderBook, id, price, quantity| Order {
+            price: Price::from_ticks(price),
+            quantity: Quantity::from_lots(quantity),
+            ..book.get(id).unwrap().clone()
+        };
+
+        // Reducing the quantity keeps the priority
+        let mut book = sample_book();
+        assert_eq!(book.amend(amended(&book, 1, 101, 1)), Some(vec![]));
+        assert_eq!(
+            book.submit(order(5, Side::Bid, 101, 1)),
+            vec![fill(1, 5, 101, 1)]
+        );
+
+        // Increasing it loses the priority
+        let mut book = sample_book();
+        assert_eq!(book.amend(amended(&book, 1, 101, 4)), Some(vec![]));
+        assert_eq!(
+            book.submit(order(5, Side::Bid, 101, 1)),
+            vec![fill(2, 5, 101, 1)]
+        );
+
+        // A new price can make it trade
+        assert_eq!(
+            book.amend(amended(&book, 4, 101, 5)),
+            Some(vec![fill(2, 4, 101, 2), fill(1, 4, 101, 3)])
+        );
+        let filled = Order {
+            id: 2,
+            ..book.get(1).unwrap().clone()
+        };
+        assert_eq!(book.amend(filled), None);
+    }
+}
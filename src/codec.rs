@@ -0,0 +1,501 @@
+use std::fmt;
+
+use ed25519_dalek::SigningKey;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    compression::DecompressError,
+    envelope::{EnvelopeError, MessageType},
+    fixed_point::{Price, Quantity},
+    hlc::Timestamp,
+    order::{Action, MarketId, Order, OrderType, Side, TimeInForce},
+    packet::{EncodeOptions, GossipPacket, SerialiedPacket},
+};
+
+/// Wire format of the actions carried by the packets. The packet header has a fixed layout
+/// whatever the codec (see [`SerialiedPacket`]), so only the action payload is up to the codec.
+/// The payload is the action preceded by the timestamp it was stamped with when injected, so that
+/// the timestamp is signed along with the action.
+pub trait Codec: Clone + Send + Sync + 'static {
+    fn encode_action(&self, timestamp: Timestamp, action: &Action) -> Vec<u8>;
+
+    fn decode_action(&self, payload: &[u8]) -> Result<(Timestamp, Action), DecodeError>;
+
+    fn encode(&self, packet: &GossipPacket) -> SerialiedPacket {
+        self.encode_with(packet, &EncodeOptions::default())
+    }
+
+    /// Same as [`Codec::encode`], with the order signed by the trader's `key`
+    fn encode_signed(&self, packet: &GossipPacket, key: &SigningKey) -> SerialiedPacket {
+        let options = EncodeOptions {
+            trader_key: Some(key),
+            ..Default::default()
+        };
+        self.encode_with(packet, &options)
+    }
+
+    /// Same as [`Codec::encode`], with the order compressed and signed as set in `options`
+    fn encode_with(&self, packet: &GossipPacket, options: &EncodeOptions) -> SerialiedPacket {
+        SerialiedPacket::with_options(
+            packet.id,
+            &packet.source_id,
+            packet.ttl,
+            &self.encode_action(packet.timestamp, &packet.action),
+            options,
+        )
+    }
+
+    /// Validates the envelope and decodes the whole packet
+    fn decode(&self, packet: &SerialiedPacket) -> Result<GossipPacket, DecodeError> {
+        let envelope = packet.envelope()?;
+        if envelope.message_type != MessageType::GossipOrder {
+            return Err(DecodeError::UnexpectedMessageType(envelope.message_type));
+        }
+
+        let (timestamp, action) = self.decode_action(&packet.action_bytes()?)?;
+        Ok(
+            GossipPacket::new(packet.id(), packet.source_id(), packet.ttl(), action)
+                .with_timestamp(timestamp),
+        )
+    }
+}
+
+/// Why a packet couldn't be decoded
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DecodeError {
+    /// Invalid envelope, e.g. corrupted or from an incompatible version
+    Envelope(EnvelopeError),
+    /// Valid envelope, but it doesn't carry a gossip order
+    UnexpectedMessageType(MessageType),
+    /// The order is flagged as compressed but can't be decompressed
+    Decompress(DecompressError),
+    /// The action payload is invalid for the codec
+    Payload(String),
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DecodeError::Envelope(e) => write!(f, "invalid envelope: {e}"),
+            DecodeError::UnexpectedMessageType(message_type) => {
+                write!(f, "expected a gossip order, got {message_type:?}")
+            }
+            DecodeError::Decompress(e) => write!(f, "{e}"),
+            DecodeError::Payload(e) => write!(f, "invalid action payload: {e}"),
+        }
+    }
+}
+
+impl std::error::Error for DecodeError {}
+
+impl From<EnvelopeError> for DecodeError {
+    fn from(e: EnvelopeError) -> Self {
+        DecodeError::Envelope(e)
+    }
+}
+
+impl From<DecompressError> for DecodeError {
+    fn from(e: DecompressError) -> Self {
+        DecodeError::Decompress(e)
+    }
+}
+
+/// Codec selected in the config
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CodecKind {
+    #[default]
+    Borsh,
+    Bincode,
+    Postcard,
+    /// Hand-written fixed layout, see [`FixedCodec`]
+    Fixed,
+}
+
+/// Dispatches to the selected codec at runtime. Prefer the concrete codecs on hot paths.
+impl Codec for CodecKind {
+    fn encode_action(&self, timestamp: Timestamp, action: &Action) -> Vec<u8> {
+        match self {
+            CodecKind::Borsh => BorshCodec.encode_action(timestamp, action),
+            CodecKind::Bincode => BincodeCodec::default().encode_action(timestamp, action),
+            CodecKind::Postcard => PostcardCodec.encode_action(timestamp, action),
+            CodecKind::Fixed => FixedCodec.encode_action(timestamp, action),
+        }
+    }
+
+    fn decode_action(&self, payload: &[u8]) -> Result<(Timestamp, Action), DecodeError> {
+        match self {
+            CodecKind::Borsh => BorshCodec.decode_action(payload),
+            CodecKind::Bincode => BincodeCodec::default().decode_action(payload),
+            CodecKind::Postcard => PostcardCodec.decode_action(payload),
+            CodecKind::Fixed => FixedCodec.decode_action(payload),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct BorshCodec;
+
+impl Codec for BorshCodec {
+    fn encode_action(&self, timestamp: Timestamp, action: &Action) -> Vec<u8> {
+        borsh::to_vec(&(timestamp, action)).unwrap()
+    }
+
+    fn decode_action(&self, payload: &[u8]) -> Result<(Timestamp, Action), DecodeError> {
+        borsh::from_slice(payload).map_err(|e| DecodeError::Payload(e.to_string()))
+    }
+}
+
+#[derive(Clone, Copy)]
+pub struct BincodeCodec(bincode::config::Configuration);
+
+impl BincodeCodec {
+    pub fn new(config: bincode::config::Configuration) -> Self {
+        Self(config)
+    }
+}
+
+impl Default for BincodeCodec {
+    fn default() -> Self {
+        Self(bincode::config::standard())
+    }
+}
+
+impl Codec for BincodeCodec {
+    fn encode_action(&self, timestamp: Timestamp, action: &Action) -> Vec<u8> {
+        bincode::encode_to_vec((timestamp, action), self.0).unwrap()
+    }
+
+    fn decode_action(&self, payload: &[u8]) -> Result<(Timestamp, Action), DecodeError> {
+        match bincode::decode_from_slice(payload, self.0) {
+            Ok((stamped, read)) if read == payload.len() => Ok(stamped),
+            Ok((_, read)) => Err(DecodeError::Payload(format!(
+                "{} trailing bytes",
+                payload.len() - read
+            ))),
+            Err(e) => Err(DecodeError::Payload(e.to_string())),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct PostcardCodec;
+
+impl Codec for PostcardCodec {
+    fn encode_action(&self, timestamp: Timestamp, action: &Action) -> Vec<u8> {
+        postcard::to_stdvec(&(timestamp, action)).unwrap()
+    }
+
+    fn decode_action(&self, payload: &[u8]) -> Result<(Timestamp, Action), DecodeError> {
+        match postcard::take_from_bytes(payload) {
+            Ok((stamped, [])) => Ok(stamped),
+            Ok((_, rest)) => Err(DecodeError::Payload(format!(
+                "{} trailing bytes",
+                rest.len()
+            ))),
+            Err(e) => Err(DecodeError::Payload(e.to_string())),
+        }
+    }
+}
+
+/// Hand-written format where every field has a fixed size and offset, integers are little-endian.
+/// An action starts with its timestamp (physical time on 8 bytes, then logical counter on 4
+/// bytes) and a tag byte (0 for place, 1 for cancel, 2 for amend). A placed order is followed by:
+///
+/// | offset | size | field               |
+/// | ------ | ---- | ------------------- |
+/// | 0      | 8    | id                  |
+/// | 8      | 2    | market              |
+/// | 10     | 1    | side                |
+/// | 11     | 8    | price, in ticks     |
+/// | 19     | 8    | quantity, in lots   |
+/// | 27     | 1    | order type          |
+/// | 28     | 8    | trigger, in ticks   |
+/// | 36     | 1    | time in force       |
+///
+/// The trigger is 0 for orders other than stops. A cancel is followed by the order's id and
+/// market, an amend by the id, market, price and quantity, laid out as above.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FixedCodec;
+
+impl FixedCodec {
+    /// Size of an encoded order, without the action tag
+    pub const ORDER_SIZE: usize = 37;
+    /// Size of a cancel, without the action tag
+    const CANCEL_SIZE: usize = 10;
+    /// Size of an amend, without the action tag
+    const AMEND_SIZE: usize = 26;
+
+    fn encode_order(order: &Order, bytes: &mut Vec<u8>) {
+        bytes.extend_from_slice(&order.id.to_le_bytes());
+        bytes.extend_from_slice(&order.market.value().to_le_bytes());
+        bytes.push(match order.side {
+            Side::Bid => 0,
+            Side::Ask => 1,
+        });
+        bytes.extend_from_slice(&order.price.ticks().to_le_bytes());
+        bytes.extend_from_slice(&order.quantity.lots().to_le_bytes());
+        let (order_type, trigger) = match order.order_type {
+            OrderType::Limit => (0, Price::default()),
+            OrderType::Market => (1, Price::default()),
+            OrderType::Stop { trigger } => (2, trigger),
+            OrderType::StopLimit { trigger } => (3, trigger),
+        };
+        bytes.push(order_type);
+        bytes.extend_from_slice(&trigger.ticks().to_le_bytes());
+        bytes.push(match order.time_in_force {
+            TimeInForce::GoodTillCancel => 0,
+            TimeInForce::ImmediateOrCancel => 1,
+            TimeInForce::FillOrKill => 2,
+            TimeInForce::PostOnly => 3,
+        });
+    }
+
+    fn decode_order(bytes: &[u8; Self::ORDER_SIZE]) -> Result<Order, DecodeError> {
+        let u64_at =
+            |offset: usize| u64::from_le_bytes(bytes[offset..offset + 8].try_into().unwrap());
+
+        let market = MarketId::new(u16::from_le_bytes([bytes[8], bytes[9]]));
+        let side = match bytes[10] {
+            0 => Side::Bid,
+            1 => Side::Ask,
+            tag => return Err(DecodeError::Payload(format!("unknown side tag {tag}"))),
+        };
+        let trigger = Price::from_ticks(u64_at(28));
+        let order_type = match bytes[27] {
+            0 => OrderType::Limit,
+            1 => OrderType::Market,
+            2 => OrderType::Stop { trigger },
+            3 => OrderType::StopLimit { trigger },
+            tag => {
+                return Err(DecodeError::Payload(format!(
+                    "unknown order type tag {tag}"
+                )));
+            }
+        };
+        let time_in_force = match bytes[36] {
+            0 => TimeInForce::GoodTillCancel,
+            1 => TimeInForce::ImmediateOrCancel,
+            2 => TimeInForce::FillOrKill,
+            3 => TimeInForce::PostOnly,
+            tag => {
+                return Err(DecodeError::Payload(format!(
+                    "unknown time in force tag {tag}"
+                )));
+            }
+        };
+
+        let order = Order::new(
+            u64_at(0),
+            market,
+            side,
+            Price::from_ticks(u64_at(11)),
+            Quantity::from_lots(u64_at(19)),
+        );
+        Ok(order.with_type(order_type, time_in_force))
+    }
+}
+
+impl Codec for FixedCodec {
+    fn encode_action(&self, timestamp: Timestamp, action: &Action) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(Timestamp::SIZE + 1 + Self::ORDER_SIZE);
+        bytes.extend_from_slice(&timestamp.physical.to_le_bytes());
+        bytes.extend_from_slice(&timestamp.logical.to_le_bytes());
+
+        match action {
+            Action::Place(order) => {
+                bytes.push(0);
+                Self::encode_order(order, &mut bytes);
+            }
+            Action::Cancel { market, order_id } => {
+                bytes.push(1);
+                bytes.extend_from_slice(&order_id.to_le_bytes());
+                bytes.extend_from_slice(&market.value().to_le_bytes());
+            }
+            Action::Amend {
+                market,
+                order_id,
+                price,
+                quantity,
+            } => {
+                bytes.push(2);
+                bytes.extend_from_slice(&order_id.to_le_bytes());
+                bytes.extend_from_slice(&market.value().to_le_bytes());
+                bytes.extend_from_slice(&price.ticks().to_le_bytes());
+                bytes.extend_from_slice(&quantity.lots().to_le_bytes());
+            }
+        }
+
+        bytes
+    }
+
+    fn decode_action(&self, payload: &[u8]) -> Result<(Timestamp, Action), DecodeError> {
+        let Some((timestamp, payload)) = payload.split_at_checked(Timestamp::SIZE) else {
+            return Err(DecodeError::Payload("truncated timestamp".to_string()));
+        };
+        let timestamp = Timestamp::new(
+            u64::from_le_bytes(timestamp[..8].try_into().unwrap()),
+            u32::from_le_bytes(timestamp[8..].try_into().unwrap()),
+        );
+        let (&tag, bytes) = payload
+            .split_first()
+            .ok_or_else(|| DecodeError::Payload("empty action".to_string()))?;
+        let expected = match tag {
+            0 => Self::ORDER_SIZE,
+            1 => Self::CANCEL_SIZE,
+            2 => Self::AMEND_SIZE,
+            tag => return Err(DecodeError::Payload(format!("unknown action tag {tag}"))),
+        };
+        if bytes.len() != expected {
+            return Err(DecodeError::Payload(format!(
+                "action of {} bytes, expected {expected}",
+                bytes.len()
+            )));
+        }
+        let u64_at =
+            |offset: usize| u64::from_le_bytes(bytes[offset..offset + 8].try_into().unwrap());
+        let market = || MarketId::new(u16::from_le_bytes([bytes[8], bytes[9]]));
+
+        let action = match tag {
+            0 => Action::Place(Self::decode_order(bytes.try_into().unwrap())?),
+            1 => Action::Cancel {
+                market: market(),
+                order_id: u64_at(0),
+            },
+            _ => Action::Amend {
+                market: market(),
+                order_id: u64_at(0),
+                price: Price::from_ticks(u64_at(10)),
+                quantity: Quantity::from_lots(u64_at(18)),
+            },
+        };
+        Ok((timestamp, action))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        market::MarketRegistry, network::NodeId, packet::PacketId,
+        transport::udp::MAX_DATAGRAM_SIZE,
+    };
+
+    /// Num runs for test
+    const NUM_RUNS: u64 = 100;
+
+    /// One of each order type and time in force
+    const ORDER_TYPES: [(OrderType, TimeInForce); 4] = [
+        (OrderType::Limit, TimeInForce::GoodTillCancel),
+        (OrderType::Market, TimeInForce::ImmediateOrCancel),
+        (
+            OrderType::Stop {
+                trigger: Price::from_ticks(14_000),
+            },
+            TimeInForce::FillOrKill,
+        ),
+        (
+            OrderType::StopLimit {
+                trigger: Price::from_ticks(16_000),
+            },
+            TimeInForce::PostOnly,
+        ),
+    ];
+
+    /// Every kind of action, placing orders of each type in turn
+    fn action(i: u64) -> Action {
+        let order = MarketRegistry::default().random_order();
+        match i % 6 {
+            4 => Action::Cancel {
+                market: order.market,
+                order_id: order.id,
+            },
+            5 => Action::Amend {
+                market: order.market,
+                order_id: order.id,
+                price: order.price,
+                quantity: order.quantity,
+            },
+            i => {
+                let (order_type, time_in_force) = ORDER_TYPES[i as usize];
+                Action::Place(order.with_type(order_type, time_in_force))
+            }
+        }
+    }
+
+    /// Property test: decoding an encoded packet gives back the same packet
+    fn check_round_trip<C: Codec>(codec: C) {
+        for i in 0..NUM_RUNS {
+            let packet = GossipPacket::new(PacketId::new(i), NodeId::new(i), i, action(i))
+                .with_timestamp(Timestamp::new(1_700_000_000_000 + i, i as u32));
+            let serialized = codec.encode(&packet);
+            let deserialized = codec.decode(&serialized).unwrap();
+            assert_eq!(packet, deserialized);
+
+            // Malformed actions are errors, not panics
+            let action = codec.encode_action(packet.timestamp, &packet.action);
+            assert!(codec.decode_action(&action[..action.len() - 1]).is_err());
+            assert!(
+                codec
+                    .decode_action(&[action.as_slice(), &[0]].concat())
+                    .is_err()
+            );
+        }
+    }
+
+    #[test]
+    fn test_bincode_codec() {
+        check_round_trip(BincodeCodec::default());
+    }
+
+    #[test]
+    fn test_borsch_codec() {
+        check_round_trip(BorshCodec);
+    }
+
+    #[test]
+    fn test_postcard_codec() {
+        check_round_trip(PostcardCodec);
+    }
+
+    #[test]
+    fn test_fixed_codec() {
+        check_round_trip(FixedCodec);
+
+        let packet = GossipPacket::new_with_random_order(PacketId::new(1), NodeId::new(2), 3);
+        let bytes = FixedCodec.encode(&packet);
+        assert_eq!(
+            bytes.as_bytes().len(),
+            SerialiedPacket::HEADER_SIZE + Timestamp::SIZE + 1 + FixedCodec::ORDER_SIZE
+        );
+        assert_eq!(bytes.id(), PacketId::new(1));
+
+        // Unknown action tag, order type and time in force
+        let action = FixedCodec.encode_action(packet.timestamp, &packet.action);
+        for offset in [12, 40, 49] {
+            let mut invalid = action.clone();
+            invalid[offset] = 4;
+            assert!(FixedCodec.decode_action(&invalid).is_err());
+        }
+        assert!(
+            FixedCodec
+                .decode_action(&action[..Timestamp::SIZE])
+                .is_err()
+        );
+        assert!(FixedCodec.decode_action(&[]).is_err());
+    }
+
+    #[test]
+    /// Serialized packets must fit in a single UDP datagram, whatever the codec
+    fn test_fits_in_datagram() {
+        let packet = GossipPacket::new_with_random_order(PacketId::new(1), NodeId::new(1), 1);
+        for codec in [
+            CodecKind::Borsh,
+            CodecKind::Bincode,
+            CodecKind::Postcard,
+            CodecKind::Fixed,
+        ] {
+            assert!(codec.encode(&packet).as_bytes().len() <= MAX_DATAGRAM_SIZE);
+        }
+    }
+}
@@ -0,0 +1,557 @@
+use std::{
+    collections::BTreeMap,
+    ops::Range,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use tokio::{sync::mpsc, time::Instant};
+
+use crate::{
+    codec::{Codec, DecodeError},
+    hlc::Timestamp,
+    network::NodeId,
+    order::Action,
+    packet::{PacketId, SerialiedPacket},
+    signing::Verification,
+};
+
+/// Packet ids of the batches start here, above the ids of the orders, so that nodes don't dedupe
+/// a batch against an order
+pub const BATCH_ID_BASE: u64 = 1 << 63;
+
+/// Action put in sequence by the committee
+#[derive(Debug, Clone, PartialEq)]
+pub struct Entry {
+    /// Packet that submitted the action, reported by the nodes once they applied it
+    pub packet_id: PacketId,
+    pub timestamp: Timestamp,
+    pub action: Action,
+}
+
+/// Entries committed together, starting at `first_index` in the committee's log. Encoded as:
+///
+/// | offset | size | field                          |
+/// | ------ | ---- | ------------------------------ |
+/// | 0      | 8    | index of the first entry       |
+/// | 8      | 4    | number of entries              |
+/// | 12     | ..   | entries                        |
+///
+/// Each entry is the id of the packet that submitted it (8 bytes), the length of its payload (4
+/// bytes), and the payload: its timestamp and action, encoded with the network's codec. Integers
+/// are little-endian.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Batch {
+    pub first_index: u64,
+    pub entries: Vec<Entry>,
+}
+
+impl Batch {
+    const HEADER_SIZE: usize = 12;
+    const ENTRY_HEADER_SIZE: usize = 12;
+
+    /// Entries following the first `first_index` ones of the log, split into batches whose packets
+    /// fit in `max_packet_size` bytes. An entry too large on its own still gets its batch.
+    pub fn split<C: Codec>(
+        codec: &C,
+        first_index: u64,
+        entries: &[Entry],
+        max_packet_size: Option<usize>,
+    ) -> Vec<Batch> {
+        let max_size = max_packet_size.unwrap_or(usize::MAX);
+        let mut batches = Vec::new();
+        let mut batch = Batch {
+            first_index,
+            entries: Vec::new(),
+        };
+        let mut size = SerialiedPacket::HEADER_SIZE + Self::HEADER_SIZE;
+        for entry in entries {
+            let entry_size =
+                Self::ENTRY_HEADER_SIZE + codec.encode_action(entry.timestamp, &entry.action).len();
+            if !batch.entries.is_empty() && size + entry_size > max_size {
+                let next = Batch {
+                    first_index: batch.first_index + batch.entries.len() as u64,
+                    entries: Vec::new(),
+                };
+                batches.push(std::mem::replace(&mut batch, next));
+                size = SerialiedPacket::HEADER_SIZE + Self::HEADER_SIZE;
+            }
+            batch.entries.push(entry.clone());
+            size += entry_size;
+        }
+        if !batch.entries.is_empty() {
+            batches.push(batch);
+        }
+        batches
+    }
+
+    pub fn encode<C: Codec>(&self, codec: &C) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(&self.first_index.to_le_bytes());
+        bytes.extend_from_slice(&(self.entries.len() as u32).to_le_bytes());
+        for entry in &self.entries {
+            let payload = codec.encode_action(entry.timestamp, &entry.action);
+            bytes.extend_from_slice(&entry.packet_id.value().to_le_bytes());
+            bytes.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+            bytes.extend_from_slice(&payload);
+        }
+        bytes
+    }
+
+    pub fn decode<C: Codec>(codec: &C, mut bytes: &[u8]) -> Result<Self, DecodeError> {
+        let first_index = u64::from_le_bytes(take(&mut bytes)?);
+        let num_entries = u32::from_le_bytes(take(&mut bytes)?);
+
+        let mut entries = Vec::new();
+        for _ in 0..num_entries {
+            let packet_id = PacketId::new(u64::from_le_bytes(take(&mut bytes)?));
+            let len = u32::from_le_bytes(take(&mut bytes)?) as usize;
+            let Some((payload, rest)) = bytes.split_at_checked(len) else {
+                return Err(DecodeError::Payload("truncated batch".to_string()));
+            };
+            let (timestamp, action) = codec.decode_action(payload)?;
+            entries.push(Entry {
+                packet_id,
+                timestamp,
+                action,
+            });
+            bytes = rest;
+        }
+
+        if !bytes.is_empty() {
+            return Err(DecodeError::Payload(format!(
+                "{} trailing bytes",
+                bytes.len()
+            )));
+        }
+        Ok(Self {
+            first_index,
+            entries,
+        })
+    }
+}
+
+/// Reads the next `N` bytes of a batch
+fn take<const N: usize>(bytes: &mut &[u8]) -> Result<[u8; N], DecodeError> {
+    let Some((field, rest)) = bytes.split_first_chunk::<N>() else {
+        return Err(DecodeError::Payload("truncated batch".to_string()));
+    };
+    *bytes = rest;
+    Ok(*field)
+}
+
+/// Batches received by a node, applied in the sequence of the committee's log. Gossip delivers
+/// them in any order, so a batch following one the node hasn't received yet waits for it.
+#[derive(Debug, Clone, Default)]
+pub struct SequencedLog {
+    /// Index of the next entry to apply
+    next_index: u64,
+    /// Batches received ahead of `next_index`, by index of their first entry
+    pending: BTreeMap<u64, Vec<Entry>>,
+}
+
+impl SequencedLog {
+    /// Entries that can be applied once `batch` is received, in sequence
+    pub fn push(&mut self, batch: Batch) -> Vec<Entry> {
+        self.pending.insert(batch.first_index, batch.entries);
+
+        let mut ready = Vec::new();
+        while let Some(batch) = self.pending.first_entry() {
+            let first_index = *batch.key();
+            if first_index > self.next_index {
+                break;
+            }
+            let entries = batch.remove();
+            let end = first_index + entries.len() as u64;
+            // Skips the entries already applied, if batches overlap
+            let applied = (self.next_index - first_index) as usize;
+            ready.extend(entries.into_iter().skip(applied));
+            self.next_index = self.next_index.max(end);
+        }
+        ready
+    }
+
+    pub fn next_index(&self) -> u64 {
+        self.next_index
+    }
+
+    /// Number of batches waiting for an earlier one
+    pub fn num_pending(&self) -> usize {
+        self.pending.len()
+    }
+}
+
+/// Sent by the leader to a follower: the entries following the first `prev_len` ones of its log
+#[derive(Debug, Clone)]
+struct AppendEntries {
+    prev_len: u64,
+    entries: Vec<Entry>,
+}
+
+/// Reply of follower `member` to [`AppendEntries`]. Its log has `log_len` entries, all matching the
+/// leader's if `success`. Otherwise entries are missing before `prev_len`, and the leader sends
+/// them again from `log_len`.
+#[derive(Debug, Clone, Copy)]
+struct AppendReply {
+    member: usize,
+    log_len: u64,
+    success: bool,
+}
+
+/// Committee of sequencer nodes, where the first member is the leader
+#[derive(Debug, Clone)]
+pub struct CommitteeSettings {
+    pub members: Vec<NodeId>,
+    /// Latency of the links between the members, and from the origin of a packet to the leader
+    pub latency: Duration,
+    /// Signature check done by the leader on the submitted packets
+    pub verification: Verification,
+    /// TTL of the batches gossiped through the network
+    pub ttl: u64,
+    /// Largest packet the network's transport sends, see [`crate::transport::TransportKind::max_packet_size`]
+    pub max_packet_size: Option<usize>,
+}
+
+/// Handle on a running committee. Packets are submitted to the leader, one hop away from their
+/// origin unless it is the leader, which appends their action to its log and replicates it to the
+/// followers, as in Raft. Once a majority of the members has an entry, it is committed, and the
+/// leader gossips the newly committed entries through the network, in as many [`Batch`]es as it
+/// takes to fit in the transport's packets. The leader is fixed: there is no election, the
+/// committee stops sequencing if it fails.
+///
+/// Only the leader runs on its node, whose inbox it gossips through. The followers are standalone
+/// tasks named after the other members, not the member nodes themselves: their logs stay apart
+/// from the nodes' books, and replication only costs them the latency of the links.
+#[derive(Debug)]
+pub struct Committee {
+    /// Packets submitted to the leader, as they would be sent to a node
+    pub submit: mpsc::Sender<SerialiedPacket>,
+    sequencing_times: Arc<Mutex<Vec<Duration>>>,
+}
+
+impl Committee {
+    /// Starts the members' tasks. The leader gossips the batches by sending them on `gossip`, the
+    /// inbox of the node it runs on.
+    pub fn start<C: Codec>(
+        settings: CommitteeSettings,
+        codec: C,
+        gossip: mpsc::Sender<SerialiedPacket>,
+    ) -> Self {
+        let num_followers = settings.members.len().saturating_sub(1);
+        let (reply_tx, reply_rx) = mpsc::channel(num_followers.max(1) * 32);
+
+        let followers = (0..num_followers)
+            .map(|member| {
+                let (append_tx, append_rx) = mpsc::channel(32);
+                tokio::spawn(follower_task(
+                    member,
+                    settings.latency,
+                    append_rx,
+                    reply_tx.clone(),
+                ));
+                append_tx
+            })
+            .collect();
+
+        let (submit, submit_rx) = mpsc::channel(32);
+        let (leader_tx, leader_rx) = mpsc::channel(32);
+        tokio::spawn(relay_submissions(
+            settings.members[0].clone(),
+            settings.latency,
+            submit_rx,
+            leader_tx,
+        ));
+        let sequencing_times = Arc::new(Mutex::new(Vec::new()));
+        let leader = Leader {
+            node_id: settings.members[0].clone(),
+            codec,
+            latency: settings.latency,
+            verification: settings.verification,
+            ttl: settings.ttl,
+            max_packet_size: settings.max_packet_size,
+            log: Vec::new(),
+            submitted_at: Vec::new(),
+            next_len: vec![0; num_followers],
+            match_len: vec![0; num_followers],
+            commit_len: 0,
+            followers,
+            gossip,
+            sequencing_times: sequencing_times.clone(),
+        };
+        tokio::spawn(leader.run(leader_rx, reply_rx));
+
+        Self {
+            submit,
+            sequencing_times,
+        }
+    }
+
+    /// Time between the submission of each action and its commit, in the sequence of the log
+    pub fn sequencing_times(&self) -> Vec<Duration> {
+        self.sequencing_times.lock().unwrap().clone()
+    }
+}
+
+struct Leader<C> {
+    node_id: NodeId,
+    codec: C,
+    latency: Duration,
+    verification: Verification,
+    ttl: u64,
+    max_packet_size: Option<usize>,
+    log: Vec<Entry>,
+    /// When each entry of the log was submitted
+    submitted_at: Vec<Instant>,
+    /// Length of each follower's log once it receives what was sent to it
+    next_len: Vec<u64>,
+    /// Number of entries each follower acknowledged
+    match_len: Vec<u64>,
+    commit_len: u64,
+    followers: Vec<mpsc::Sender<AppendEntries>>,
+    gossip: mpsc::Sender<SerialiedPacket>,
+    sequencing_times: Arc<Mutex<Vec<Duration>>>,
+}
+
+impl<C: Codec> Leader<C> {
+    async fn run(
+        mut self,
+        mut submissions: mpsc::Receiver<SerialiedPacket>,
+        mut replies: mpsc::Receiver<AppendReply>,
+    ) {
+        loop {
+            tokio::select! {
+                packet = submissions.recv() => match packet {
+                    Some(packet) => self.submit(packet),
+                    None => break,
+                },
+                Some(reply) = replies.recv() => self.on_reply(reply),
+            }
+            self.replicate();
+            self.commit().await;
+        }
+    }
+
+    /// Appends the action of a packet to the log. Packets that don't pass verification or can't
+    /// be decoded are dropped.
+    fn submit(&mut self, packet: SerialiedPacket) {
+        if self.verification.check(&packet).is_err() {
+            return;
+        }
+        let Ok(packet) = self.codec.decode(&packet) else {
+            return;
+        };
+        self.log.push(Entry {
+            packet_id: packet.id,
+            timestamp: packet.timestamp,
+            action: packet.action,
+        });
+        self.submitted_at.push(Instant::now());
+    }
+
+    fn on_reply(&mut self, reply: AppendReply) {
+        if reply.success {
+            self.match_len[reply.member] = self.match_len[reply.member].max(reply.log_len);
+        } else {
+            self.next_len[reply.member] = reply.log_len;
+        }
+    }
+
+    /// Sends each follower the entries it wasn't sent yet
+    fn replicate(&mut self) {
+        let log_len = self.log.len() as u64;
+        for (member, follower) in self.followers.iter().enumerate() {
+            let prev_len = self.next_len[member];
+            if prev_len >= log_len {
+                continue;
+            }
+            let append = AppendEntries {
+                prev_len,
+                entries: self.log[prev_len as usize..].to_vec(),
+            };
+            send_after(self.latency, follower.clone(), append);
+            self.next_len[member] = log_len;
+        }
+    }
+
+    /// Commits the entries a majority of the members has, and gossips them
+    async fn commit(&mut self) {
+        // The leader has every entry of its log
+        let mut lens = self.match_len.clone();
+        lens.push(self.log.len() as u64);
+        lens.sort_unstable_by(|a, b| b.cmp(a));
+        let quorum = lens.len() / 2 + 1;
+        let commit_len = lens[quorum - 1];
+        if commit_len <= self.commit_len {
+            return;
+        }
+
+        let committed = self.commit_len as usize..commit_len as usize;
+        let now = Instant::now();
+        self.sequencing_times.lock().unwrap().extend(
+            self.submitted_at[committed.clone()]
+                .iter()
+                .map(|submitted_at| now - *submitted_at),
+        );
+
+        let batches = self.batches(committed);
+        self.commit_len = commit_len;
+        for batch in batches {
+            let packet = SerialiedPacket::sequenced_batch(
+                PacketId::new(BATCH_ID_BASE + batch.first_index),
+                &self.node_id,
+                self.ttl,
+                &batch.encode(&self.codec),
+            );
+            let _ = self.gossip.send(packet).await;
+        }
+    }
+
+    fn batches(&self, committed: Range<usize>) -> Vec<Batch> {
+        Batch::split(
+            &self.codec,
+            committed.start as u64,
+            &self.log[committed],
+            self.max_packet_size,
+        )
+    }
+}
+
+/// Delivers the submitted packets to the leader once they crossed the link from their origin, at
+/// once for the ones injected on the leader's node
+async fn relay_submissions(
+    leader_id: NodeId,
+    latency: Duration,
+    mut submissions: mpsc::Receiver<SerialiedPacket>,
+    leader: mpsc::Sender<SerialiedPacket>,
+) {
+    while let Some(packet) = submissions.recv().await {
+        let latency = if packet.source_id() == leader_id {
+            Duration::ZERO
+        } else {
+            latency
+        };
+        send_after(latency, leader.clone(), packet);
+    }
+}
+
+/// Appends the entries sent by the leader to the follower's log and acknowledges them
+async fn follower_task(
+    member: usize,
+    latency: Duration,
+    mut appends: mpsc::Receiver<AppendEntries>,
+    leader: mpsc::Sender<AppendReply>,
+) {
+    let mut log = Vec::new();
+
+    while let Some(append) = appends.recv().await {
+        let log_len = log.len() as u64;
+        let success = append.prev_len <= log_len;
+        if success {
+            // Only the entries the follower doesn't have yet, appends can overlap
+            let known = (log_len - append.prev_len) as usize;
+            log.extend(append.entries.into_iter().skip(known));
+        }
+        let reply = AppendReply {
+            member,
+            log_len: log.len() as u64,
+            success,
+        };
+        send_after(latency, leader.clone(), reply);
+    }
+}
+
+/// Delivers `message` after the latency of the link, without blocking the sender
+fn send_after<T: Send + 'static>(latency: Duration, sender: mpsc::Sender<T>, message: T) {
+    tokio::spawn(async move {
+        tokio::time::sleep(latency).await;
+        let _ = sender.send(message).await;
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        codec::{BorshCodec, FixedCodec},
+        market::MarketRegistry,
+        network::NodeId,
+        transport::udp,
+    };
+
+    fn batch(first_index: u64, len: u64) -> Batch {
+        let markets = MarketRegistry::default();
+        let entries = (first_index..first_index + len)
+            .map(|index| Entry {
+                packet_id: PacketId::new(index),
+                timestamp: Timestamp::new(1_000 + index, 0),
+                action: Action::Place(markets.random_order()),
+            })
+            .collect();
+        Batch {
+            first_index,
+            entries,
+        }
+    }
+
+    fn packet_ids(entries: &[Entry]) -> Vec<u64> {
+        entries
+            .iter()
+            .map(|entry| entry.packet_id.value())
+            .collect()
+    }
+
+    #[test]
+    fn test_batch_codec() {
+        let batch = batch(5, 3);
+        let bytes = batch.encode(&FixedCodec);
+        assert_eq!(Batch::decode(&FixedCodec, &bytes), Ok(batch));
+
+        assert!(Batch::decode(&FixedCodec, &bytes[..bytes.len() - 1]).is_err());
+        assert!(Batch::decode(&FixedCodec, &[bytes.as_slice(), &[0]].concat()).is_err());
+        assert!(Batch::decode(&FixedCodec, &bytes[..10]).is_err());
+    }
+
+    #[test]
+    fn test_batch_split() {
+        let entries = batch(3, 40).entries;
+        assert!(batch(3, 40).encode(&BorshCodec).len() > udp::MAX_DATAGRAM_SIZE);
+
+        let batches = Batch::split(&BorshCodec, 3, &entries, Some(udp::MAX_DATAGRAM_SIZE));
+        assert!(batches.len() > 1);
+        let mut log = SequencedLog::default();
+        log.push(batch(0, 3));
+        let mut applied = Vec::new();
+        for batch in batches.into_iter().rev() {
+            let packet = SerialiedPacket::sequenced_batch(
+                PacketId::new(BATCH_ID_BASE + batch.first_index),
+                &NodeId::new(0),
+                3,
+                &batch.encode(&BorshCodec),
+            );
+            assert!(packet.as_bytes().len() <= udp::MAX_DATAGRAM_SIZE);
+            applied.extend(log.push(batch));
+        }
+        assert_eq!(applied, entries);
+
+        // No limit on the in-process transports
+        assert_eq!(Batch::split(&BorshCodec, 3, &entries, None).len(), 1);
+    }
+
+    #[test]
+    fn test_sequenced_log() {
+        let mut log = SequencedLog::default();
+        assert_eq!(packet_ids(&log.push(batch(0, 2))), [0, 1]);
+
+        // Received before the batch preceding it
+        assert!(log.push(batch(4, 1)).is_empty());
+        assert_eq!(log.num_pending(), 1);
+        assert_eq!(packet_ids(&log.push(batch(2, 2))), [2, 3, 4]);
+        assert_eq!(log.next_index(), 5);
+
+        // Already applied, entirely or partly
+        assert!(log.push(batch(1, 2)).is_empty());
+        assert_eq!(packet_ids(&log.push(batch(3, 3))), [5]);
+        assert_eq!(log.next_index(), 6);
+        assert_eq!(log.num_pending(), 0);
+    }
+}
@@ -0,0 +1,147 @@
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{envelope::Flags, transport::tcp::MAX_FRAME_SIZE};
+
+/// Largest order payload accepted once decompressed, so that a small packet can't make a node
+/// allocate an arbitrary amount of memory. No packet can be larger on any transport.
+pub const MAX_DECOMPRESSED_SIZE: usize = MAX_FRAME_SIZE;
+
+/// Compression of the order payload, selected in the config. The algorithm is marked by an
+/// envelope flag so that receivers know how to decompress.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Compression {
+    #[default]
+    None,
+    Lz4,
+    Zstd,
+}
+
+impl Compression {
+    /// Level used for zstd, favoring speed as packets are small
+    const ZSTD_LEVEL: i32 = 1;
+
+    /// Flag marking a payload compressed with this algorithm
+    pub fn flag(self) -> Flags {
+        match self {
+            Compression::None => Flags::EMPTY,
+            Compression::Lz4 => Flags::LZ4,
+            Compression::Zstd => Flags::ZSTD,
+        }
+    }
+
+    /// Algorithm of a payload given its envelope's flags
+    pub fn from_flags(flags: Flags) -> Self {
+        if flags.contains(Flags::LZ4) {
+            Compression::Lz4
+        } else if flags.contains(Flags::ZSTD) {
+            Compression::Zstd
+        } else {
+            Compression::None
+        }
+    }
+
+    /// Compresses `payload` if it is at least `threshold` bytes long and compression makes it
+    /// smaller. Returns the algorithm actually used with the bytes to send.
+    pub fn compress(self, payload: &[u8], threshold: usize) -> (Compression, Vec<u8>) {
+        if self == Compression::None || payload.len() < threshold {
+            return (Compression::None, payload.to_vec());
+        }
+
+        let compressed = match self {
+            Compression::None => unreachable!(),
+            Compression::Lz4 => lz4_flex::compress_prepend_size(payload),
+            Compression::Zstd => zstd::bulk::compress(payload, Self::ZSTD_LEVEL)
+                .expect("Compressing into a vector can't fail"),
+        };
+
+        if compressed.len() < payload.len() {
+            (self, compressed)
+        } else {
+            (Compression::None, payload.to_vec())
+        }
+    }
+
+    pub fn decompress(self, payload: &[u8]) -> Result<Vec<u8>, DecompressError> {
+        match self {
+            Compression::None => Ok(payload.to_vec()),
+            Compression::Lz4 => {
+                // The size is prepended by `compress_prepend_size`, check it before allocating
+                let size = payload
+                    .get(..4)
+                    .map(|size| u32::from_le_bytes(size.try_into().unwrap()) as usize)
+                    .ok_or_else(|| DecompressError::Invalid("missing size".to_string()))?;
+                if size > MAX_DECOMPRESSED_SIZE {
+                    return Err(DecompressError::TooLarge(size));
+                }
+                lz4_flex::decompress_size_prepended(payload)
+                    .map_err(|e| DecompressError::Invalid(e.to_string()))
+            }
+            Compression::Zstd => zstd::bulk::decompress(payload, MAX_DECOMPRESSED_SIZE)
+                .map_err(|e| DecompressError::Invalid(e.to_string())),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DecompressError {
+    /// The payload would be larger than [`MAX_DECOMPRESSED_SIZE`] once decompressed
+    TooLarge(usize),
+    Invalid(String),
+}
+
+impl fmt::Display for DecompressError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DecompressError::TooLarge(size) => write!(
+                f,
+                "decompressed payload of {size} bytes exceeds {MAX_DECOMPRESSED_SIZE}"
+            ),
+            DecompressError::Invalid(e) => write!(f, "invalid compressed payload: {e}"),
+        }
+    }
+}
+
+impl std::error::Error for DecompressError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_compression() {
+        let payload = [7u8; 1000];
+
+        for compression in [Compression::Lz4, Compression::Zstd] {
+            let (used, compressed) = compression.compress(&payload, 100);
+            assert_eq!(used, compression);
+            assert!(compressed.len() < payload.len());
+            assert_eq!(Compression::from_flags(used.flag()), compression);
+            assert_eq!(used.decompress(&compressed).unwrap(), payload);
+
+            // Below the threshold, or when it doesn't help, the payload is left as is
+            assert_eq!(
+                compression.compress(&payload, 2000),
+                (Compression::None, payload.to_vec())
+            );
+            assert_eq!(
+                compression.compress(&[1, 2, 3], 0),
+                (Compression::None, vec![1, 2, 3])
+            );
+
+            assert!(compression.decompress(&[1, 2, 3, 4, 5]).is_err());
+        }
+
+        // Announced size checked before allocating
+        let mut bomb = lz4_flex::compress_prepend_size(&payload);
+        bomb[..4].copy_from_slice(&u32::MAX.to_le_bytes());
+        assert_eq!(
+            Compression::Lz4.decompress(&bomb),
+            Err(DecompressError::TooLarge(u32::MAX as usize))
+        );
+        let bomb = zstd::bulk::compress(&vec![0; MAX_DECOMPRESSED_SIZE + 1], 1).unwrap();
+        assert!(Compression::Zstd.decompress(&bomb).is_err());
+    }
+}
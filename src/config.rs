@@ -1,7 +1,21 @@
-use std::time::Duration;
+use std::{sync::Arc, time::Duration};
 
 use serde::{Deserialize, Serialize};
 
+use crate::{
+    codec::CodecKind,
+    compression::Compression,
+    flow::FlowConfig,
+    load::LoadConfig,
+    market::{MarketConfig, MarketRegistry},
+    node::NodeSettings,
+    origin::OriginPolicy,
+    replay::ReplayConfig,
+    sequencer::{Sequencing, SequencingMode},
+    signing::{Verification, VerificationMode},
+    transport::TransportKind,
+};
+
 #[derive(Deserialize, Serialize, Clone)]
 pub struct Config {
     /// Latency between nodes in milliseconds
@@ -16,6 +30,65 @@ pub struct Config {
     pub num_peers: u64,
     /// Number of packets to send from main to the network
     pub num_runs: u64,
+    /// Backend carrying packets between nodes
+    #[serde(default)]
+    pub transport: TransportKind,
+    /// Wire format of the packets
+    #[serde(default)]
+    pub codec: CodecKind,
+    /// Probability that a link of the simulated network flips a bit of a packet
+    #[serde(default)]
+    pub bit_flip_probability: f64,
+    /// Whether the injected orders are signed by their trader
+    #[serde(default)]
+    pub sign_orders: bool,
+    /// Which packets the nodes check the signature of
+    #[serde(default)]
+    verification: VerificationMode,
+    /// Share of the packets verified by each node when `verification` is "sample"
+    #[serde(default)]
+    verification_sample_rate: f64,
+    /// Compression of the injected orders
+    #[serde(default)]
+    pub compression: Compression,
+    /// Orders smaller than this number of bytes are sent uncompressed
+    #[serde(default)]
+    pub compression_threshold: usize,
+    /// Probability that main cancels an injected order right after placing it
+    #[serde(default)]
+    pub cancel_probability: f64,
+    /// Once the orders are sent, interval at which the nodes' books are compared until they
+    /// converge. Not checked if 0.
+    #[serde(default)]
+    pub convergence_interval_ms: u64,
+    /// How long to wait for the books to converge
+    #[serde(default)]
+    pub convergence_timeout_ms: u64,
+    /// Sequence in which the nodes apply the actions they receive
+    #[serde(default)]
+    sequencing: SequencingMode,
+    /// How long after its timestamp an action is applied when `sequencing` is "timestamp"
+    #[serde(default)]
+    settlement_delay_ms: u64,
+    /// Number of sequencer nodes orders are submitted to, which gossip them in sequence. Orders
+    /// are gossiped directly if 0.
+    #[serde(default)]
+    pub committee_size: u64,
+    /// How the node injecting each run's packets is picked, any node if omitted
+    #[serde(default)]
+    pub origin: OriginPolicy,
+    /// Synthetic order flow the orders are drawn from, uniformly random orders if omitted
+    #[serde(default)]
+    pub flow: Option<FlowConfig>,
+    /// Recorded order flow replayed instead of measuring one order at a time
+    #[serde(default)]
+    pub replay: Option<ReplayConfig>,
+    /// Offered loads to test, instead of measuring one order at a time
+    #[serde(default)]
+    pub load: Option<LoadConfig>,
+    /// Markets the orders are placed on, SOL/USD only if omitted
+    #[serde(default = "Config::default_markets")]
+    pub markets: Vec<MarketConfig>,
 }
 
 impl Config {
@@ -42,6 +115,99 @@ impl Config {
                 "There must be more nodes (= {}) than neighbors (= {})",
                 config.num_nodes, config.num_neighbors
             )))
+        } else if !(0.0..=1.0).contains(&config.bit_flip_probability) {
+            Err(config::ConfigError::Message(format!(
+                "bit_flip_probability (= {}) must be between 0 and 1",
+                config.bit_flip_probability
+            )))
+        } else if !(0.0..=1.0).contains(&config.verification_sample_rate) {
+            Err(config::ConfigError::Message(format!(
+                "verification_sample_rate (= {}) must be between 0 and 1",
+                config.verification_sample_rate
+            )))
+        } else if config.verification != VerificationMode::None && !config.sign_orders {
+            Err(config::ConfigError::Message(format!(
+                "Nodes verifying signatures (verification = {}) would reject every order, unless sign_orders is set",
+                format!("{:?}", config.verification).to_lowercase()
+            )))
+        } else if !(0.0..=1.0).contains(&config.cancel_probability) {
+            Err(config::ConfigError::Message(format!(
+                "cancel_probability (= {}) must be between 0 and 1",
+                config.cancel_probability
+            )))
+        } else if config.committee_size > config.num_nodes {
+            Err(config::ConfigError::Message(format!(
+                "More committee members (= {}) than nodes (= {})",
+                config.committee_size, config.num_nodes
+            )))
+        } else if config.committee_size > 0 && config.verification != VerificationMode::None {
+            Err(config::ConfigError::Message(format!(
+                "Committee batches aren't signed, nodes verifying signatures (verification = {}) can't tell them from forged ones",
+                format!("{:?}", config.verification).to_lowercase()
+            )))
+        } else if let OriginPolicy::Fixed { node } = config.origin
+            && node >= config.num_nodes
+        {
+            Err(config::ConfigError::Message(format!(
+                "Origin {node} isn't a node (ids up to {})",
+                config.num_nodes - 1
+            )))
+        } else if let OriginPolicy::Region {
+            region,
+            num_regions,
+        } = config.origin
+            && (num_regions == 0 || num_regions > config.num_nodes || region >= num_regions)
+        {
+            Err(config::ConfigError::Message(format!(
+                "Origin region {region} out of {num_regions} regions, for {} nodes",
+                config.num_nodes
+            )))
+        } else if let Err(e) = MarketRegistry::new(&config.markets) {
+            Err(config::ConfigError::Message(e.to_string()))
+        } else if let Some(Err(e)) = config.flow.as_ref().map(FlowConfig::validate) {
+            Err(config::ConfigError::Message(format!("flow: {e}")))
+        } else if config.flow.is_some() && config.replay.is_some() {
+            Err(config::ConfigError::Message(
+                "A synthetic flow and a replay can't both be configured".to_string(),
+            ))
+        } else if let Some(replay) = config
+            .replay
+            .as_ref()
+            .filter(|r| r.speedup.is_nan() || r.speedup <= 0.0)
+        {
+            Err(config::ConfigError::Message(format!(
+                "replay speedup (= {}) must be positive",
+                replay.speedup
+            )))
+        } else if let Some(origin) = config
+            .replay
+            .iter()
+            .flat_map(|replay| &replay.origins)
+            .find(|id| **id >= config.num_nodes)
+        {
+            Err(config::ConfigError::Message(format!(
+                "Replay origin {origin} isn't a node (ids up to {})",
+                config.num_nodes - 1
+            )))
+        } else if config.load.is_some() && config.replay.is_some() {
+            Err(config::ConfigError::Message(
+                "A load test and a replay can't both be configured".to_string(),
+            ))
+        } else if let Some(load) = config.load.as_ref().filter(|load| {
+            load.rates.is_empty() || load.rates.iter().any(|rate| rate.is_nan() || *rate <= 0.0)
+        }) {
+            Err(config::ConfigError::Message(format!(
+                "Load rates (= {:?}) must be positive, and at least one",
+                load.rates
+            )))
+        } else if config
+            .load
+            .as_ref()
+            .is_some_and(|load| load.duration_ms == 0)
+        {
+            Err(config::ConfigError::Message(
+                "Load duration_ms can't be 0".to_string(),
+            ))
         } else {
             Ok(config)
         }
@@ -50,11 +216,59 @@ impl Config {
     pub fn latency(&self) -> Duration {
         Duration::from_millis(self.latency_ms)
     }
+
+    pub fn verification(&self) -> Verification {
+        match self.verification {
+            VerificationMode::None => Verification::None,
+            VerificationMode::All => Verification::All,
+            VerificationMode::Sample => Verification::Sample(self.verification_sample_rate),
+        }
+    }
+
+    fn default_markets() -> Vec<MarketConfig> {
+        vec![MarketConfig::sol_usd()]
+    }
+
+    /// Registry of the configured markets, which were checked when loading the config
+    pub fn markets(&self) -> MarketRegistry {
+        MarketRegistry::new(&self.markets).expect("Invalid markets")
+    }
+
+    pub fn sequencing(&self) -> Sequencing {
+        match self.sequencing {
+            SequencingMode::Arrival => Sequencing::Arrival,
+            SequencingMode::Timestamp => Sequencing::Timestamp {
+                settlement_delay: Duration::from_millis(self.settlement_delay_ms),
+            },
+        }
+    }
+
+    pub fn node_settings(&self) -> NodeSettings {
+        NodeSettings {
+            num_peers: self.num_peers,
+            verification: self.verification(),
+            markets: Arc::new(self.markets()),
+            sequencing: self.sequencing(),
+            time_to_live: self.time_to_live,
+        }
+    }
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::config::Config;
+    use crate::{
+        codec::CodecKind,
+        compression::Compression,
+        config::Config,
+        flow::{ArrivalProcess, FlowConfig, PriceDistribution},
+        load::LoadConfig,
+        market::MarketConfig,
+        origin::OriginPolicy,
+        replay::ReplayConfig,
+        sequencer::SequencingMode,
+        signing::VerificationMode,
+        transport::TransportKind,
+    };
 
     #[test]
     fn test_config() {
@@ -65,6 +279,25 @@ mod tests {
             num_neighbors: 24,
             num_peers: 8,
             num_runs: 0,
+            transport: TransportKind::Mpsc,
+            codec: CodecKind::Borsh,
+            bit_flip_probability: 0.0,
+            sign_orders: false,
+            verification: VerificationMode::None,
+            verification_sample_rate: 0.0,
+            compression: Compression::None,
+            compression_threshold: 0,
+            cancel_probability: 0.0,
+            convergence_interval_ms: 0,
+            convergence_timeout_ms: 0,
+            sequencing: SequencingMode::Arrival,
+            settlement_delay_ms: 0,
+            committee_size: 0,
+            origin: OriginPolicy::Random,
+            flow: None,
+            replay: None,
+            load: None,
+            markets: vec![MarketConfig::sol_usd()],
         };
 
         assert!(Config::validate_config(config.clone()).is_ok());
@@ -92,5 +325,120 @@ mod tests {
         config_2.num_peers = 0;
         let e_2 = Config::validate_config(config_2.clone()).err().unwrap();
         assert_eq!(e_2.to_string(), "num_peers can't be 0");
+
+        // Not a probability
+        let mut config_3 = config.clone();
+        config_3.bit_flip_probability = 1.5;
+        let e_3 = Config::validate_config(config_3.clone()).err().unwrap();
+        assert_eq!(
+            e_3.to_string(),
+            "bit_flip_probability (= 1.5) must be between 0 and 1"
+        );
+
+        let mut config_4 = config.clone();
+        config_4.verification_sample_rate = -0.5;
+        let e_4 = Config::validate_config(config_4.clone()).err().unwrap();
+        assert_eq!(
+            e_4.to_string(),
+            "verification_sample_rate (= -0.5) must be between 0 and 1"
+        );
+
+        let mut config_5 = config.clone();
+        config_5.markets.push(MarketConfig::sol_usd());
+        let e_5 = Config::validate_config(config_5.clone()).err().unwrap();
+        assert_eq!(e_5.to_string(), "market SOL/USD configured twice");
+
+        let mut config_6 = config.clone();
+        config_6.cancel_probability = 2.0;
+        let e_6 = Config::validate_config(config_6.clone()).err().unwrap();
+        assert_eq!(
+            e_6.to_string(),
+            "cancel_probability (= 2) must be between 0 and 1"
+        );
+
+        let mut config_7 = config.clone();
+        config_7.committee_size = 1_001;
+        let e_7 = Config::validate_config(config_7.clone()).err().unwrap();
+        assert_eq!(
+            e_7.to_string(),
+            "More committee members (= 1001) than nodes (= 1000)"
+        );
+
+        let mut config_8 = config.clone();
+        config_8.flow = Some(FlowConfig {
+            arrivals: ArrivalProcess::Poisson { rate: 0.0 },
+            mid_volatility_bps: 0.0,
+            price_distribution: PriceDistribution::Normal,
+            price_offset_bps: 0.0,
+            price_scale_bps: 10.0,
+            size_scale: 1.0,
+            size_tail_index: 2.0,
+            cancel_ratio: 0.0,
+            seed: None,
+        });
+        let e_8 = Config::validate_config(config_8.clone()).err().unwrap();
+        assert_eq!(e_8.to_string(), "flow: invalid rate (= 0)");
+
+        let mut config_9 = config.clone();
+        config_9.replay = Some(ReplayConfig {
+            file: "orders.csv".into(),
+            speedup: 0.0,
+            origins: vec![0, 999],
+        });
+        let e_9 = Config::validate_config(config_9.clone()).err().unwrap();
+        assert_eq!(e_9.to_string(), "replay speedup (= 0) must be positive");
+
+        let replay = config_9.replay.as_mut().unwrap();
+        replay.speedup = 10.0;
+        assert!(Config::validate_config(config_9.clone()).is_ok());
+        config_9.replay.as_mut().unwrap().origins.push(1_000);
+        let e_9 = Config::validate_config(config_9.clone()).err().unwrap();
+        assert_eq!(
+            e_9.to_string(),
+            "Replay origin 1000 isn't a node (ids up to 999)"
+        );
+
+        let mut config_10 = config.clone();
+        config_10.load = Some(LoadConfig {
+            rates: vec![100.0, -1.0],
+            duration_ms: 1_000,
+        });
+        let e_10 = Config::validate_config(config_10.clone()).err().unwrap();
+        assert_eq!(
+            e_10.to_string(),
+            "Load rates (= [100.0, -1.0]) must be positive, and at least one"
+        );
+
+        let mut config_11 = config.clone();
+        config_11.origin = OriginPolicy::Region {
+            region: 4,
+            num_regions: 4,
+        };
+        let e_11 = Config::validate_config(config_11.clone()).err().unwrap();
+        assert_eq!(
+            e_11.to_string(),
+            "Origin region 4 out of 4 regions, for 1000 nodes"
+        );
+        config_11.origin = OriginPolicy::Fixed { node: 1_000 };
+        let e_11 = Config::validate_config(config_11.clone()).err().unwrap();
+        assert_eq!(e_11.to_string(), "Origin 1000 isn't a node (ids up to 999)");
+
+        let mut config_12 = config.clone();
+        config_12.verification = VerificationMode::Sample;
+        let e_12 = Config::validate_config(config_12.clone()).err().unwrap();
+        assert_eq!(
+            e_12.to_string(),
+            "Nodes verifying signatures (verification = sample) would reject every order, unless sign_orders is set"
+        );
+        config_12.sign_orders = true;
+        assert!(Config::validate_config(config_12.clone()).is_ok());
+
+        let mut config_13 = config_12;
+        config_13.committee_size = 3;
+        let e_13 = Config::validate_config(config_13).err().unwrap();
+        assert_eq!(
+            e_13.to_string(),
+            "Committee batches aren't signed, nodes verifying signatures (verification = sample) can't tell them from forged ones"
+        );
     }
 }
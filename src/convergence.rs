@@ -0,0 +1,116 @@
+use std::{
+    collections::{HashMap, HashSet},
+    time::Duration,
+};
+
+use tokio::time::Instant;
+
+use crate::{fixed_point::Price, market::MarketRegistry, node::NodeState, order::MarketId};
+
+/// Books of every node at one instant. Gossip delivers orders to the nodes in different
+/// sequences, and each node matches them in the sequence it received them, so books can differ.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Snapshot {
+    pub num_nodes: usize,
+    /// Number of different book states among the nodes, 1 once they converged
+    pub distinct_states: usize,
+    /// Number of nodes sharing the most common state
+    pub largest_group: usize,
+    pub markets: Vec<MarketSnapshot>,
+}
+
+/// How much the nodes disagree on the top of a market's book
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MarketSnapshot {
+    pub market: MarketId,
+    /// Number of different best bids and asks among the nodes
+    pub distinct_tops: usize,
+    /// Smallest and largest spread among the nodes whose book has both sides
+    pub spread_range: Option<(Price, Price)>,
+}
+
+impl MarketSnapshot {
+    /// Gap between the largest and the smallest spread, in ticks
+    pub fn spread_disagreement(&self) -> u64 {
+        self.spread_range
+            .map_or(0, |(min, max)| max.ticks() - min.ticks())
+    }
+}
+
+impl Snapshot {
+    pub fn take<'a>(
+        nodes: impl IntoIterator<Item = &'a NodeState>,
+        markets: &MarketRegistry,
+    ) -> Self {
+        let nodes: Vec<&NodeState> = nodes.into_iter().collect();
+
+        let mut states = HashMap::<u64, usize>::new();
+        for node in &nodes {
+            *states.entry(node.books_hash()).or_default() += 1;
+        }
+
+        let markets = markets
+            .markets()
+            .iter()
+            .map(|market| {
+                let tops: Vec<_> = nodes
+                    .iter()
+                    .filter_map(|node| node.top_of_book(market.id))
+                    .collect();
+                let spreads = tops.iter().filter_map(|top| top.spread());
+                let spread_range = spreads.clone().min().zip(spreads.max());
+                MarketSnapshot {
+                    market: market.id,
+                    distinct_tops: tops.iter().collect::<HashSet<_>>().len(),
+                    spread_range,
+                }
+            })
+            .collect();
+
+        Self {
+            num_nodes: nodes.len(),
+            distinct_states: states.len(),
+            largest_group: states.values().copied().max().unwrap_or(0),
+            markets,
+        }
+    }
+
+    pub fn converged(&self) -> bool {
+        self.distinct_states <= 1
+    }
+}
+
+/// How the books of the nodes evolved once the order flow stopped
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Convergence {
+    /// Snapshot taken when the flow stopped
+    pub initial: Snapshot,
+    /// Last snapshot taken, once converged or at the timeout
+    pub last: Snapshot,
+    /// Time until every node had the same books, `None` if they didn't before the timeout
+    pub time: Option<Duration>,
+}
+
+/// Snapshots the books of the nodes every `interval` until they are all the same, or until
+/// `timeout`. To be called once no more orders are injected.
+pub async fn check_convergence(
+    nodes: &[&NodeState],
+    markets: &MarketRegistry,
+    interval: Duration,
+    timeout: Duration,
+) -> Convergence {
+    let start = Instant::now();
+    let initial = Snapshot::take(nodes.iter().copied(), markets);
+    let mut last = initial.clone();
+
+    while !last.converged() && start.elapsed() < timeout {
+        tokio::time::sleep(interval).await;
+        last = Snapshot::take(nodes.iter().copied(), markets);
+    }
+
+    Convergence {
+        time: last.converged().then(|| start.elapsed()),
+        initial,
+        last,
+    }
+}
@@ -0,0 +1,339 @@
+use std::fmt;
+
+use bytes::{BufMut, BytesMut};
+
+/// First bytes of every message, to reject anything that isn't ours early
+pub const MAGIC: [u8; 2] = *b"OP";
+
+/// Version of the protocol written by this node
+pub const PROTOCOL_VERSION: u8 = 1;
+
+/// Oldest version this node still understands. Messages of versions in
+/// `MIN_SUPPORTED_VERSION..=PROTOCOL_VERSION` are accepted, so nodes can be upgraded one at a time.
+pub const MIN_SUPPORTED_VERSION: u8 = 1;
+
+/// Kind of message carried by an envelope. Tag 2 is reserved: it carried cancels before they
+/// became actions of [`MessageType::GossipOrder`], and is rejected as unknown.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[repr(u8)]
+pub enum MessageType {
+    /// An order placed, cancelled or amended, gossiped through the network
+    GossipOrder = 1,
+    /// Summary of the packets a node has seen, for pull-based gossip
+    PullDigest = 3,
+    /// Node joining or leaving the network
+    Membership = 4,
+    /// Actions put in sequence by the sequencer committee, gossiped like orders
+    SequencedBatch = 5,
+}
+
+impl MessageType {
+    /// Whether the message is gossiped, its payload starting with the gossip header of
+    /// [`crate::packet::SerialiedPacket`]
+    pub fn is_gossiped(&self) -> bool {
+        matches!(self, MessageType::GossipOrder | MessageType::SequencedBatch)
+    }
+}
+
+impl TryFrom<u8> for MessageType {
+    type Error = EnvelopeError;
+
+    fn try_from(tag: u8) -> Result<Self, Self::Error> {
+        match tag {
+            1 => Ok(MessageType::GossipOrder),
+            3 => Ok(MessageType::PullDigest),
+            4 => Ok(MessageType::Membership),
+            5 => Ok(MessageType::SequencedBatch),
+            tag => Err(EnvelopeError::UnknownMessageType(tag)),
+        }
+    }
+}
+
+/// Optional features used by a message, set in its envelope
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct Flags(u8);
+
+impl Flags {
+    pub const EMPTY: Flags = Flags(0);
+    /// The payload carries the originator's signature, see [`crate::signing`]
+    pub const SIGNED: Flags = Flags(1 << 0);
+    /// The payload is compressed with LZ4, see [`crate::compression`]
+    pub const LZ4: Flags = Flags(1 << 1);
+    /// The payload is compressed with zstd
+    pub const ZSTD: Flags = Flags(1 << 2);
+
+    /// Every flag this version understands
+    const KNOWN: Flags = Flags(Self::SIGNED.0 | Self::LZ4.0 | Self::ZSTD.0);
+
+    pub fn contains(self, other: Flags) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    pub fn bits(self) -> u8 {
+        self.0
+    }
+}
+
+impl std::ops::BitOr for Flags {
+    type Output = Flags;
+
+    fn bitor(self, rhs: Flags) -> Flags {
+        Flags(self.0 | rhs.0)
+    }
+}
+
+/// Header wrapping every message on the wire:
+///
+/// | offset | size | field                          |
+/// | ------ | ---- | ------------------------------ |
+/// | 0      | 2    | magic (`OP`)                   |
+/// | 2      | 1    | protocol version               |
+/// | 3      | 1    | message type                   |
+/// | 4      | 1    | flags                          |
+/// | 5      | 4    | payload length (little-endian) |
+/// | 9      | 4    | checksum (little-endian)       |
+/// | 13     | ..   | payload                        |
+///
+/// The checksum is the CRC32C of the first 9 bytes of the envelope followed by the payload.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Envelope {
+    pub version: u8,
+    pub message_type: MessageType,
+    pub flags: Flags,
+    pub payload_len: u32,
+}
+
+impl Envelope {
+    /// Size of the envelope preceding the payload
+    pub const SIZE: usize = 13;
+
+    const FLAGS_OFFSET: usize = 4;
+    const CHECKSUM_OFFSET: usize = 9;
+
+    /// Envelope of the current protocol version, without any flag
+    pub fn new(message_type: MessageType, payload_len: u32) -> Self {
+        Self {
+            version: PROTOCOL_VERSION,
+            message_type,
+            flags: Flags::EMPTY,
+            payload_len,
+        }
+    }
+
+    pub fn with_flags(self, flags: Flags) -> Self {
+        Self { flags, ..self }
+    }
+
+    /// Writes the envelope with an empty checksum. Once the payload is written after it, the
+    /// message must be [`Envelope::seal`]ed.
+    pub fn write(&self, buffer: &mut BytesMut) {
+        buffer.put_slice(&MAGIC);
+        buffer.put_u8(self.version);
+        buffer.put_u8(self.message_type as u8);
+        buffer.put_u8(self.flags.bits());
+        buffer.put_u32_le(self.payload_len);
+        buffer.put_u32_le(0);
+    }
+
+    /// Flags of a message whose envelope was already validated, without parsing it again
+    pub fn flags_of(message: &[u8]) -> Flags {
+        Flags(message[Self::FLAGS_OFFSET])
+    }
+
+    /// Computes the checksum of a complete message and writes it in its envelope. Must be called
+    /// again whenever the message is modified.
+    pub fn seal(message: &mut [u8]) {
+        let checksum = Self::checksum(message);
+        message[Self::CHECKSUM_OFFSET..Self::SIZE].copy_from_slice(&checksum.to_le_bytes());
+    }
+
+    fn checksum(message: &[u8]) -> u32 {
+        let crc = crc32c::crc32c(&message[..Self::CHECKSUM_OFFSET]);
+        crc32c::crc32c_append(crc, &message[Self::SIZE..])
+    }
+
+    /// Reads and validates the envelope at the start of `bytes`. The payload must be exactly as
+    /// long as announced and match the checksum.
+    pub fn parse(bytes: &[u8]) -> Result<Self, EnvelopeError> {
+        if bytes.len() < Self::SIZE {
+            return Err(EnvelopeError::Truncated {
+                expected: Self::SIZE,
+                actual: bytes.len(),
+            });
+        }
+
+        if bytes[0..2] != MAGIC {
+            return Err(EnvelopeError::BadMagic([bytes[0], bytes[1]]));
+        }
+
+        let version = bytes[2];
+        if !(MIN_SUPPORTED_VERSION..=PROTOCOL_VERSION).contains(&version) {
+            return Err(EnvelopeError::UnsupportedVersion(version));
+        }
+
+        let message_type = MessageType::try_from(bytes[3])?;
+
+        let flags = Flags(bytes[Self::FLAGS_OFFSET]);
+        if !Flags::KNOWN.contains(flags) {
+            return Err(EnvelopeError::UnknownFlags(flags.bits()));
+        }
+
+        let payload_len = u32::from_le_bytes(bytes[5..9].try_into().unwrap());
+
+        let actual = bytes.len() - Self::SIZE;
+        if actual != payload_len as usize {
+            return Err(EnvelopeError::LengthMismatch {
+                declared: payload_len,
+                actual,
+            });
+        }
+
+        let expected =
+            u32::from_le_bytes(bytes[Self::CHECKSUM_OFFSET..Self::SIZE].try_into().unwrap());
+        let actual = Self::checksum(bytes);
+        if expected != actual {
+            return Err(EnvelopeError::ChecksumMismatch { expected, actual });
+        }
+
+        Ok(Self {
+            version,
+            message_type,
+            flags,
+            payload_len,
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EnvelopeError {
+    /// The message is shorter than its headers
+    Truncated {
+        expected: usize,
+        actual: usize,
+    },
+    BadMagic([u8; 2]),
+    /// Version outside of `MIN_SUPPORTED_VERSION..=PROTOCOL_VERSION`
+    UnsupportedVersion(u8),
+    UnknownMessageType(u8),
+    /// Flags only understood by a newer version
+    UnknownFlags(u8),
+    /// The payload isn't as long as announced in the envelope
+    LengthMismatch {
+        declared: u32,
+        actual: usize,
+    },
+    /// The message was corrupted
+    ChecksumMismatch {
+        expected: u32,
+        actual: u32,
+    },
+}
+
+impl fmt::Display for EnvelopeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EnvelopeError::Truncated { expected, actual } => {
+                write!(f, "message of {actual} bytes, expected at least {expected}")
+            }
+            EnvelopeError::BadMagic(magic) => write!(f, "bad magic bytes {magic:?}"),
+            EnvelopeError::UnsupportedVersion(version) => write!(
+                f,
+                "unsupported protocol version {version} (supported: {MIN_SUPPORTED_VERSION}..={PROTOCOL_VERSION})"
+            ),
+            EnvelopeError::UnknownMessageType(tag) => write!(f, "unknown message type {tag}"),
+            EnvelopeError::UnknownFlags(flags) => write!(f, "unknown flags {flags:#010b}"),
+            EnvelopeError::LengthMismatch { declared, actual } => write!(
+                f,
+                "payload of {actual} bytes, envelope announced {declared}"
+            ),
+            EnvelopeError::ChecksumMismatch { expected, actual } => {
+                write!(f, "checksum {actual:#010x} doesn't match {expected:#010x}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for EnvelopeError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn encoded(envelope: Envelope, payload: &[u8]) -> Vec<u8> {
+        let mut buffer = BytesMut::new();
+        envelope.write(&mut buffer);
+        buffer.put_slice(payload);
+        Envelope::seal(&mut buffer);
+        buffer.to_vec()
+    }
+
+    /// Same message with its checksum fixed up, to reach the checks after it
+    fn resealed(mut bytes: Vec<u8>) -> Vec<u8> {
+        Envelope::seal(&mut bytes);
+        bytes
+    }
+
+    #[test]
+    fn test_envelope() {
+        let envelope = Envelope::new(MessageType::GossipOrder, 3).with_flags(Flags::SIGNED);
+        let bytes = encoded(envelope, &[1, 2, 3]);
+        assert_eq!(Envelope::parse(&bytes), Ok(envelope));
+
+        // Anything but our magic
+        let mut bad_magic = bytes.clone();
+        bad_magic[0] = b'X';
+        assert_eq!(
+            Envelope::parse(&bad_magic),
+            Err(EnvelopeError::BadMagic([b'X', b'P']))
+        );
+
+        // Newer (or too old) versions are rejected, not misinterpreted
+        let mut newer = bytes.clone();
+        newer[2] = PROTOCOL_VERSION + 1;
+        assert_eq!(
+            Envelope::parse(&resealed(newer)),
+            Err(EnvelopeError::UnsupportedVersion(PROTOCOL_VERSION + 1))
+        );
+
+        let mut unknown_flags = bytes.clone();
+        unknown_flags[4] |= 0b1000_0000;
+        assert_eq!(
+            Envelope::parse(&resealed(unknown_flags)),
+            Err(EnvelopeError::UnknownFlags(0b1000_0001))
+        );
+
+        for tag in [2, 200] {
+            let mut unknown_type = bytes.clone();
+            unknown_type[3] = tag;
+            assert_eq!(
+                Envelope::parse(&resealed(unknown_type)),
+                Err(EnvelopeError::UnknownMessageType(tag))
+            );
+        }
+
+        assert_eq!(
+            Envelope::parse(&bytes[..5]),
+            Err(EnvelopeError::Truncated {
+                expected: Envelope::SIZE,
+                actual: 5
+            })
+        );
+        assert_eq!(
+            Envelope::parse(&bytes[..bytes.len() - 1]),
+            Err(EnvelopeError::LengthMismatch {
+                declared: 3,
+                actual: 2
+            })
+        );
+
+        // Any flipped bit is detected
+        for bit in 0..bytes.len() * 8 {
+            let mut corrupted = bytes.clone();
+            corrupted[bit / 8] ^= 1 << (bit % 8);
+            assert!(
+                Envelope::parse(&corrupted).is_err(),
+                "bit {bit} not detected"
+            );
+        }
+    }
+}
@@ -0,0 +1,234 @@
+use std::{collections::HashMap, time::Duration};
+
+use borsh::{BorshDeserialize, BorshSerialize};
+
+use crate::{
+    fairness::Arrival, hlc, network::NodeId, node::Rejection, order::Action, packet::PacketId,
+};
+
+/// Something a node did with a packet, reported to main. Metrics, plots and traces are all built
+/// from the events of every node.
+#[derive(Debug, Clone, PartialEq, Eq, BorshDeserialize, BorshSerialize)]
+pub struct NodeEvent {
+    pub node: NodeId,
+    /// Nanoseconds since the Unix epoch on the node's clock, see [`hlc::since_epoch`]
+    pub time_ns: u64,
+    pub kind: EventKind,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, BorshDeserialize, BorshSerialize)]
+pub enum EventKind {
+    /// First valid copy of a packet, received from `from`, `hop` links away from its origin. For
+    /// a committee batch, each of its actions once applied in the sequence of the log.
+    Delivered {
+        packet_id: PacketId,
+        from: NodeId,
+        hop: u64,
+    },
+    /// Copy of a packet the node already has
+    Duplicate { packet_id: PacketId, from: NodeId },
+    /// Packet gossiped to the neighbor `to`
+    Forwarded { packet_id: PacketId, to: NodeId },
+    /// Packet rejected, or not sent to a neighbor. Its id and sender are unknown when its envelope
+    /// is invalid or its message isn't gossiped, and its sender isn't set when the node failed to send it.
+    Dropped {
+        packet_id: Option<PacketId>,
+        from: Option<NodeId>,
+        reason: Rejection,
+    },
+    /// Delivered packet not gossiped further, as its TTL is reached
+    TtlExpired { packet_id: PacketId },
+}
+
+impl NodeEvent {
+    /// Event happening now on `node`
+    pub fn new(node: NodeId, kind: EventKind) -> Self {
+        Self {
+            node,
+            time_ns: hlc::since_epoch().as_nanos() as u64,
+            kind,
+        }
+    }
+
+    /// Time since the Unix epoch
+    pub fn time(&self) -> Duration {
+        Duration::from_nanos(self.time_ns)
+    }
+
+    /// Packet the node delivered, if this is a delivery
+    pub fn delivered(&self) -> Option<PacketId> {
+        match self.kind {
+            EventKind::Delivered { packet_id, .. } => Some(packet_id),
+            _ => None,
+        }
+    }
+}
+
+/// Totals of the events of every node, and the sequence in which each node had the injected
+/// orders delivered
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct EventStats {
+    pub delivered: usize,
+    pub duplicates: usize,
+    pub forwarded: usize,
+    pub dropped: usize,
+    pub ttl_expired: usize,
+    /// Deliveries at each hop from the origin
+    pub hops: Vec<usize>,
+    /// Drops for each reason, indexed like [`Rejection::ALL`]
+    drops: [usize; Rejection::ALL.len()],
+    /// Order placed by each injected packet, see [`EventStats::record_injected`]
+    placed: HashMap<PacketId, u64>,
+    arrivals: HashMap<NodeId, Vec<Arrival>>,
+}
+
+impl EventStats {
+    pub fn record(&mut self, event: &NodeEvent) {
+        match event.kind {
+            EventKind::Delivered { packet_id, hop, .. } => {
+                self.delivered += 1;
+                let hop = hop as usize;
+                if self.hops.len() <= hop {
+                    self.hops.resize(hop + 1, 0);
+                }
+                self.hops[hop] += 1;
+                if let Some(&order_id) = self.placed.get(&packet_id) {
+                    let arrival = Arrival {
+                        order_id,
+                        time: event.time(),
+                    };
+                    self.arrivals
+                        .entry(event.node.clone())
+                        .or_default()
+                        .push(arrival);
+                }
+            }
+            EventKind::Duplicate { .. } => self.duplicates += 1,
+            EventKind::Forwarded { .. } => self.forwarded += 1,
+            EventKind::Dropped { reason, .. } => {
+                self.dropped += 1;
+                self.drops[reason.index()] += 1;
+            }
+            EventKind::TtlExpired { .. } => self.ttl_expired += 1,
+        }
+    }
+
+    /// Notes the action of a packet main injects, before its deliveries are recorded, so that the
+    /// orders it places appear in [`EventStats::arrivals`]
+    pub fn record_injected(&mut self, packet_id: PacketId, action: &Action) {
+        if let Action::Place(order) = action {
+            self.placed.insert(packet_id, order.id);
+        }
+    }
+
+    /// Packets dropped for `reason`
+    pub fn drops(&self, reason: Rejection) -> usize {
+        self.drops[reason.index()]
+    }
+
+    /// Orders placed by the injected packets, in the sequence each node had them delivered
+    pub fn arrivals(&self) -> &HashMap<NodeId, Vec<Arrival>> {
+        &self.arrivals
+    }
+
+    /// Copies received for nothing for each delivery, the cost of gossiping
+    pub fn duplicates_per_delivery(&self) -> f64 {
+        if self.delivered == 0 {
+            return 0.0;
+        }
+        self.duplicates as f64 / self.delivered as f64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::market::MarketRegistry;
+
+    #[test]
+    fn test_event_stats() {
+        let node = NodeId::new(1);
+        let packet_id = PacketId::new(4);
+        let delivered = |hop| {
+            NodeEvent::new(
+                node.clone(),
+                EventKind::Delivered {
+                    packet_id,
+                    from: NodeId::new(0),
+                    hop,
+                },
+            )
+        };
+        let events = [
+            delivered(0),
+            delivered(2),
+            delivered(2),
+            NodeEvent::new(
+                node.clone(),
+                EventKind::Duplicate {
+                    packet_id,
+                    from: NodeId::new(2),
+                },
+            ),
+            NodeEvent::new(
+                node.clone(),
+                EventKind::Forwarded {
+                    packet_id,
+                    to: NodeId::new(2),
+                },
+            ),
+            NodeEvent::new(
+                node.clone(),
+                EventKind::Dropped {
+                    packet_id: None,
+                    from: None,
+                    reason: Rejection::BadChecksum,
+                },
+            ),
+            NodeEvent::new(node.clone(), EventKind::TtlExpired { packet_id }),
+        ];
+        assert_eq!(events[2].delivered(), Some(packet_id));
+        assert_eq!(events[3].delivered(), None);
+        assert!(events[0].time() <= events[6].time());
+
+        let mut stats = EventStats::default();
+        let order = MarketRegistry::default().random_order();
+        stats.record_injected(packet_id, &Action::Place(order.clone()));
+        let cancel = Action::Cancel {
+            market: order.market,
+            order_id: order.id,
+        };
+        stats.record_injected(PacketId::new(5), &cancel);
+        for event in &events {
+            stats.record(event);
+        }
+        assert_eq!(
+            (
+                stats.delivered,
+                stats.duplicates,
+                stats.forwarded,
+                stats.dropped,
+                stats.ttl_expired
+            ),
+            (3, 1, 1, 1, 1)
+        );
+        assert_eq!(stats.hops, [1, 0, 2]);
+        assert_eq!(stats.drops(Rejection::BadChecksum), 1);
+        assert_eq!(stats.drops(Rejection::Malformed), 0);
+        assert_eq!(stats.duplicates_per_delivery(), 1.0 / 3.0);
+
+        // Only the placements are in the arrival sequences
+        let arrivals: Vec<Arrival> = events[..3]
+            .iter()
+            .map(|event| Arrival {
+                order_id: order.id,
+                time: event.time(),
+            })
+            .collect();
+        assert_eq!(stats.arrivals(), &HashMap::from([(node.clone(), arrivals)]));
+
+        // Events go through the control connection of node processes
+        let bytes = borsh::to_vec(&events[5]).unwrap();
+        assert_eq!(borsh::from_slice::<NodeEvent>(&bytes).unwrap(), events[5]);
+    }
+}
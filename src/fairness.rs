@@ -0,0 +1,241 @@
+use std::{
+    collections::{HashMap, HashSet},
+    time::Duration,
+};
+
+use rand::Rng;
+
+use crate::network::NodeId;
+
+/// Delivery of an order by a node, as reported in its [`crate::event::NodeEvent`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Arrival {
+    pub order_id: u64,
+    /// Time since the Unix epoch
+    pub time: Duration,
+}
+
+/// How differently the nodes saw competing orders. For trading, the sequence in which orders
+/// reach a node matters as much as how fast they do.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FairnessReport {
+    pub num_orders: usize,
+    /// Mean Kendall tau between the arrival sequences of two nodes, over the orders both saw: 1
+    /// when they saw them in the same sequence, -1 when in reverse
+    pub mean_kendall_tau: f64,
+    /// Share of the pairs of orders that the nodes didn't all see in the same sequence
+    pub inconsistent_pairs: f64,
+    /// Number of orders each node saw first, or at the same time as the first ones
+    pub first_to_see: HashMap<NodeId, usize>,
+}
+
+impl FairnessReport {
+    /// Compares the arrival sequences of the nodes. The Kendall tau is averaged over at most
+    /// `max_pairs` random pairs of nodes, and the inconsistent pairs are counted among at most
+    /// `max_pairs` random pairs of orders, as there are too many pairs in large runs.
+    pub fn new(arrivals: &HashMap<NodeId, Vec<Arrival>>, max_pairs: usize) -> Self {
+        let sequences: Vec<Vec<u64>> = arrivals
+            .values()
+            .map(|arrivals| arrivals.iter().map(|arrival| arrival.order_id).collect())
+            .collect();
+
+        let taus: Vec<f64> = sample_pairs(sequences.len(), max_pairs)
+            .into_iter()
+            .filter_map(|(a, b)| kendall_tau(&sequences[a], &sequences[b]))
+            .collect();
+        let mean_kendall_tau = if taus.is_empty() {
+            1.0
+        } else {
+            taus.iter().sum::<f64>() / taus.len() as f64
+        };
+
+        let orders: HashSet<u64> = sequences.iter().flatten().copied().collect();
+        let orders: Vec<u64> = orders.into_iter().collect();
+
+        Self {
+            num_orders: orders.len(),
+            mean_kendall_tau,
+            inconsistent_pairs: inconsistent_pairs(&orders, &sequences, max_pairs),
+            first_to_see: first_to_see(arrivals),
+        }
+    }
+}
+
+/// Kendall tau between two sequences, over their common items. `None` if they have less than two
+/// items in common.
+pub fn kendall_tau(a: &[u64], b: &[u64]) -> Option<f64> {
+    let ranks_b: HashMap<u64, usize> = b.iter().enumerate().map(|(rank, id)| (*id, rank)).collect();
+    // Ranks in `b` of the common items, in the sequence of `a`
+    let mut ranks: Vec<usize> = a.iter().filter_map(|id| ranks_b.get(id).copied()).collect();
+    if ranks.len() < 2 {
+        return None;
+    }
+
+    // Ranks are distinct, so every pair is either concordant or discordant, i.e. an inversion
+    let num_pairs = ranks.len() * (ranks.len() - 1) / 2;
+    let mut buffer = vec![0; ranks.len()];
+    let discordant = count_inversions(&mut ranks, &mut buffer);
+    let concordant = num_pairs - discordant;
+    Some((concordant as f64 - discordant as f64) / num_pairs as f64)
+}
+
+/// Number of pairs out of order in `items`, counted while merge sorting them in O(n log n).
+/// `buffer` is scratch space of the same length.
+fn count_inversions(items: &mut [usize], buffer: &mut [usize]) -> usize {
+    if items.len() < 2 {
+        return 0;
+    }
+    let mid = items.len() / 2;
+    let mut inversions = count_inversions(&mut items[..mid], &mut buffer[..mid])
+        + count_inversions(&mut items[mid..], &mut buffer[mid..]);
+
+    let (mut i, mut j) = (0, mid);
+    for slot in buffer.iter_mut() {
+        if j == items.len() || (i < mid && items[i] < items[j]) {
+            *slot = items[i];
+            i += 1;
+        } else {
+            // Taken before every item left in the first half
+            *slot = items[j];
+            inversions += mid - i;
+            j += 1;
+        }
+    }
+    items.copy_from_slice(buffer);
+    inversions
+}
+
+/// Every pair of indices below `n` if there are at most `max_pairs` of them, else `max_pairs`
+/// random ones
+fn sample_pairs(n: usize, max_pairs: usize) -> Vec<(usize, usize)> {
+    if n * n.saturating_sub(1) / 2 <= max_pairs {
+        return (0..n)
+            .flat_map(|a| (a + 1..n).map(move |b| (a, b)))
+            .collect();
+    }
+    let mut rng = rand::rng();
+    (0..max_pairs)
+        .map(|_| {
+            let a = rng.random_range(0..n);
+            // Any other index
+            let b = (a + rng.random_range(1..n)) % n;
+            (a.min(b), a.max(b))
+        })
+        .collect()
+}
+
+/// Share of the pairs of orders seen in one sequence by a node and in the other by another node,
+/// among the pairs seen by at least one node, estimated over at most `max_pairs` of them
+fn inconsistent_pairs(orders: &[u64], sequences: &[Vec<u64>], max_pairs: usize) -> f64 {
+    let ranks: Vec<HashMap<u64, usize>> = sequences
+        .iter()
+        .map(|sequence| {
+            sequence
+                .iter()
+                .enumerate()
+                .map(|(rank, id)| (*id, rank))
+                .collect()
+        })
+        .collect();
+
+    let mut seen = 0;
+    let mut inconsistent = 0;
+    for (a, b) in sample_pairs(orders.len(), max_pairs) {
+        let (a, b) = (&orders[a], &orders[b]);
+        let mut a_first = false;
+        let mut b_first = false;
+        for ranks in &ranks {
+            if let (Some(rank_a), Some(rank_b)) = (ranks.get(a), ranks.get(b)) {
+                a_first |= rank_a < rank_b;
+                b_first |= rank_b < rank_a;
+            }
+        }
+        if a_first || b_first {
+            seen += 1;
+        }
+        if a_first && b_first {
+            inconsistent += 1;
+        }
+    }
+
+    if seen == 0 {
+        0.0
+    } else {
+        inconsistent as f64 / seen as f64
+    }
+}
+
+fn first_to_see(arrivals: &HashMap<NodeId, Vec<Arrival>>) -> HashMap<NodeId, usize> {
+    let mut first_times = HashMap::<u64, Duration>::new();
+    for arrival in arrivals.values().flatten() {
+        let first = first_times.entry(arrival.order_id).or_insert(arrival.time);
+        *first = (*first).min(arrival.time);
+    }
+
+    arrivals
+        .iter()
+        .map(|(node_id, arrivals)| {
+            let count = arrivals
+                .iter()
+                .filter(|arrival| first_times[&arrival.order_id] == arrival.time)
+                .count();
+            (node_id.clone(), count)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fairness() {
+        assert_eq!(kendall_tau(&[1, 2, 3], &[1, 2, 3]), Some(1.0));
+        assert_eq!(kendall_tau(&[1, 2, 3], &[3, 2, 1]), Some(-1.0));
+        // Pairs (1, 2) and (1, 3) concordant, (2, 3) discordant
+        assert_eq!(kendall_tau(&[1, 2, 3], &[1, 3, 2]), Some(1.0 / 3.0));
+        // Only the common items count
+        assert_eq!(kendall_tau(&[1, 4, 2], &[5, 1, 2]), Some(1.0));
+        assert_eq!(kendall_tau(&[1, 2], &[2, 3]), None);
+        // 3 inversions out of the 10 pairs
+        assert_eq!(kendall_tau(&[1, 2, 3, 4, 5], &[2, 4, 1, 3, 5]), Some(0.4));
+        let mut ranks = [3, 1, 4, 0, 2];
+        assert_eq!(count_inversions(&mut ranks, &mut [0; 5]), 6);
+        assert_eq!(ranks, [0, 1, 2, 3, 4]);
+        assert_eq!(sample_pairs(3, 10), [(0, 1), (0, 2), (1, 2)]);
+        let sampled = sample_pairs(100, 10);
+        assert_eq!(sampled.len(), 10);
+        assert!(sampled.iter().all(|(a, b)| a < b && *b < 100));
+
+        let start = Duration::from_secs(1_700_000_000);
+        let arrivals = |orders: &[(u64, u64)]| {
+            orders
+                .iter()
+                .map(|(order_id, ms)| Arrival {
+                    order_id: *order_id,
+                    time: start + Duration::from_millis(*ms),
+                })
+                .collect()
+        };
+        let arrivals = HashMap::from([
+            (NodeId::new(0), arrivals(&[(1, 0), (2, 10), (3, 70)])),
+            (NodeId::new(1), arrivals(&[(2, 5), (1, 50), (3, 60)])),
+            (NodeId::new(2), arrivals(&[(1, 30), (2, 40), (3, 60)])),
+        ]);
+
+        let report = FairnessReport::new(&arrivals, 10);
+        assert_eq!(report.num_orders, 3);
+        // Node 1 swapped orders 1 and 2: (1 + 1/3 + 1/3) / 3
+        assert!((report.mean_kendall_tau - 5.0 / 9.0).abs() < 1e-9);
+        // Only (1, 2) was seen in different sequences
+        assert!((report.inconsistent_pairs - 1.0 / 3.0).abs() < 1e-9);
+        assert_eq!(
+            report.first_to_see,
+            HashMap::from([
+                (NodeId::new(0), 1),
+                (NodeId::new(1), 2),
+                (NodeId::new(2), 1)
+            ])
+        );
+    }
+}
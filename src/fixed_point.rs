@@ -0,0 +1,353 @@
+use std::{fmt, str::FromStr};
+
+use bincode::{Decode, Encode};
+use borsh::{BorshDeserialize, BorshSerialize};
+use serde::{Deserialize, Deserializer, Serialize, Serializer, de};
+
+/// Most decimals of a [`Decimal`], so that `10^decimals` fits in a `u64`
+pub const MAX_DECIMALS: u8 = 18;
+
+/// Non-negative decimal number `mantissa × 10^-decimals`. Used to read and display prices and
+/// quantities, and for the tick and lot sizes of the markets.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Decimal {
+    mantissa: u64,
+    decimals: u8,
+}
+
+impl Decimal {
+    pub const fn new(mantissa: u64, decimals: u8) -> Self {
+        assert!(decimals <= MAX_DECIMALS, "Too many decimals");
+        Self { mantissa, decimals }
+    }
+
+    pub fn mantissa(self) -> u64 {
+        self.mantissa
+    }
+
+    pub fn decimals(self) -> u8 {
+        self.decimals
+    }
+
+    /// Same value written with `decimals` decimals, `None` if it would lose precision or overflow
+    pub fn rescale(self, decimals: u8) -> Option<Decimal> {
+        if decimals >= self.decimals {
+            let factor = 10u64.checked_pow((decimals - self.decimals) as u32)?;
+            Some(Decimal::new(self.mantissa.checked_mul(factor)?, decimals))
+        } else {
+            let factor = 10u64.pow((self.decimals - decimals) as u32);
+            self.mantissa
+                .is_multiple_of(factor)
+                .then(|| Decimal::new(self.mantissa / factor, decimals))
+        }
+    }
+}
+
+impl fmt::Display for Decimal {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.decimals == 0 {
+            return write!(f, "{}", self.mantissa);
+        }
+
+        let factor = 10u64.pow(self.decimals as u32);
+        write!(
+            f,
+            "{}.{:0width$}",
+            self.mantissa / factor,
+            self.mantissa % factor,
+            width = self.decimals as usize
+        )
+    }
+}
+
+impl FromStr for Decimal {
+    type Err = FixedPointError;
+
+    /// Parses numbers such as `12`, `12.5` or `0.001`. Signs, exponents and separators are refused.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || FixedPointError::Invalid(s.to_string());
+        let is_number =
+            |digits: &str| !digits.is_empty() && digits.bytes().all(|b| b.is_ascii_digit());
+
+        let (integer, fraction) = match s.split_once('.') {
+            Some((integer, fraction)) if is_number(fraction) => (integer, fraction),
+            Some(_) => return Err(invalid()),
+            None => (s, ""),
+        };
+        if !is_number(integer) || fraction.len() > MAX_DECIMALS as usize {
+            return Err(invalid());
+        }
+
+        // Only digits are left, so parsing can only fail on overflow
+        let mantissa = format!("{integer}{fraction}")
+            .parse()
+            .map_err(|_| FixedPointError::Overflow)?;
+        Ok(Decimal::new(mantissa, fraction.len() as u8))
+    }
+}
+
+/// Written as a string, e.g. `tick_size = "0.01"`, so that config files don't go through `f64`
+impl Serialize for Decimal {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for Decimal {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        <String as Deserialize>::deserialize(deserializer)?
+            .parse()
+            .map_err(de::Error::custom)
+    }
+}
+
+/// Price of an order, as a number of ticks of its market: the actual price is
+/// `ticks × tick size`, in the market's quote asset.
+#[derive(
+    Debug,
+    Clone,
+    Copy,
+    Default,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    Hash,
+    Decode,
+    Encode,
+    BorshDeserialize,
+    BorshSerialize,
+    Deserialize,
+    Serialize,
+)]
+pub struct Price(u64);
+
+/// Quantity of an order, as a number of lots of its market: the actual quantity is
+/// `lots × lot size`, in the market's base asset.
+#[derive(
+    Debug,
+    Clone,
+    Copy,
+    Default,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    Hash,
+    Decode,
+    Encode,
+    BorshDeserialize,
+    BorshSerialize,
+    Deserialize,
+    Serialize,
+)]
+pub struct Quantity(u64);
+
+impl Price {
+    pub const fn from_ticks(ticks: u64) -> Self {
+        Self(ticks)
+    }
+
+    pub fn ticks(self) -> u64 {
+        self.0
+    }
+
+    pub fn checked_add(self, other: Price) -> Option<Price> {
+        self.0.checked_add(other.0).map(Price)
+    }
+
+    pub fn checked_sub(self, other: Price) -> Option<Price> {
+        self.0.checked_sub(other.0).map(Price)
+    }
+
+    pub fn checked_mul(self, factor: u64) -> Option<Price> {
+        self.0.checked_mul(factor).map(Price)
+    }
+
+    /// Price of `value` in a market with the given `tick_size`, which it must be a multiple of
+    pub fn from_decimal(value: Decimal, tick_size: Decimal) -> Result<Price, FixedPointError> {
+        steps(value, tick_size).map(Price)
+    }
+
+    /// Reads a price such as `101.25`, see [`Price::from_decimal`]
+    pub fn parse(s: &str, tick_size: Decimal) -> Result<Price, FixedPointError> {
+        Price::from_decimal(s.parse()?, tick_size)
+    }
+
+    /// Actual price in a market with the given `tick_size`, which can be displayed
+    pub fn to_decimal(self, tick_size: Decimal) -> Result<Decimal, FixedPointError> {
+        value(self.0, tick_size)
+    }
+}
+
+impl Quantity {
+    pub const fn from_lots(lots: u64) -> Self {
+        Self(lots)
+    }
+
+    pub fn lots(self) -> u64 {
+        self.0
+    }
+
+    pub fn checked_add(self, other: Quantity) -> Option<Quantity> {
+        self.0.checked_add(other.0).map(Quantity)
+    }
+
+    pub fn checked_sub(self, other: Quantity) -> Option<Quantity> {
+        self.0.checked_sub(other.0).map(Quantity)
+    }
+
+    pub fn checked_mul(self, factor: u64) -> Option<Quantity> {
+        self.0.checked_mul(factor).map(Quantity)
+    }
+
+    /// Quantity of `value` in a market with the given `lot_size`, which it must be a multiple of
+    pub fn from_decimal(value: Decimal, lot_size: Decimal) -> Result<Quantity, FixedPointError> {
+        steps(value, lot_size).map(Quantity)
+    }
+
+    /// Reads a quantity such as `0.5`, see [`Quantity::from_decimal`]
+    pub fn parse(s: &str, lot_size: Decimal) -> Result<Quantity, FixedPointError> {
+        Quantity::from_decimal(s.parse()?, lot_size)
+    }
+
+    /// Actual quantity in a market with the given `lot_size`, which can be displayed
+    pub fn to_decimal(self, lot_size: Decimal) -> Result<Decimal, FixedPointError> {
+        value(self.0, lot_size)
+    }
+}
+
+/// Number of `step`s in `value`
+fn steps(value: Decimal, step: Decimal) -> Result<u64, FixedPointError> {
+    let not_multiple = FixedPointError::NotMultiple { value, step };
+    if step.mantissa == 0 {
+        return Err(not_multiple);
+    }
+
+    // Both written with the same decimals, the finest of the two
+    let decimals = value.decimals.max(step.decimals);
+    let value_mantissa = value
+        .rescale(decimals)
+        .ok_or(FixedPointError::Overflow)?
+        .mantissa;
+    let step_mantissa = step
+        .rescale(decimals)
+        .ok_or(FixedPointError::Overflow)?
+        .mantissa;
+
+    if !value_mantissa.is_multiple_of(step_mantissa) {
+        return Err(not_multiple);
+    }
+    Ok(value_mantissa / step_mantissa)
+}
+
+/// Value of `count` times `step`
+fn value(count: u64, step: Decimal) -> Result<Decimal, FixedPointError> {
+    let mantissa = count
+        .checked_mul(step.mantissa)
+        .ok_or(FixedPointError::Overflow)?;
+    Ok(Decimal::new(mantissa, step.decimals))
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FixedPointError {
+    /// Not a non-negative decimal number
+    Invalid(String),
+    /// The value isn't a whole number of ticks or lots
+    NotMultiple { value: Decimal, step: Decimal },
+    /// The value doesn't fit in 64 bits
+    Overflow,
+}
+
+impl fmt::Display for FixedPointError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FixedPointError::Invalid(s) => write!(f, "invalid number {s:?}"),
+            FixedPointError::NotMultiple { value, step } => {
+                write!(f, "{value} is not a multiple of {step}")
+            }
+            FixedPointError::Overflow => write!(f, "value out of range"),
+        }
+    }
+}
+
+impl std::error::Error for FixedPointError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TICK_SIZE: Decimal = Decimal::new(5, 2); // 0.05
+    const LOT_SIZE: Decimal = Decimal::new(1, 3); // 0.001
+
+    #[test]
+    fn test_decimal() {
+        for s in ["0", "12", "12.5", "0.001", "100.250"] {
+            assert_eq!(s.parse::<Decimal>().unwrap().to_string(), s);
+        }
+        assert_eq!("12.5".parse(), Ok(Decimal::new(125, 1)));
+
+        for s in ["", ".", "1.", ".5", "-1", "+1", "1e3", "1,5", "1.2.3", " 1"] {
+            assert_eq!(
+                s.parse::<Decimal>(),
+                Err(FixedPointError::Invalid(s.to_string()))
+            );
+        }
+        assert_eq!(
+            "18446744073709551616".parse::<Decimal>(),
+            Err(FixedPointError::Overflow)
+        );
+
+        assert_eq!(
+            Decimal::new(125, 1).rescale(3),
+            Some(Decimal::new(12500, 3))
+        );
+        assert_eq!(
+            Decimal::new(12500, 3).rescale(1),
+            Some(Decimal::new(125, 1))
+        );
+        assert_eq!(Decimal::new(12501, 3).rescale(1), None);
+        assert_eq!(Decimal::new(u64::MAX, 0).rescale(1), None);
+    }
+
+    #[test]
+    fn test_price_and_quantity() {
+        let price = Price::parse("101.25", TICK_SIZE).unwrap();
+        assert_eq!(price, Price::from_ticks(2025));
+        assert_eq!(price.to_decimal(TICK_SIZE).unwrap().to_string(), "101.25");
+        assert_eq!(Price::parse("101", TICK_SIZE), Ok(Price::from_ticks(2020)));
+
+        // Off tick
+        assert_eq!(
+            Price::parse("101.26", TICK_SIZE),
+            Err(FixedPointError::NotMultiple {
+                value: Decimal::new(10126, 2),
+                step: TICK_SIZE
+            })
+        );
+        assert!(Price::parse("101.251", TICK_SIZE).is_err());
+        assert_eq!(
+            Price::from_ticks(u64::MAX).to_decimal(TICK_SIZE),
+            Err(FixedPointError::Overflow)
+        );
+
+        let quantity = Quantity::parse("0.5", LOT_SIZE).unwrap();
+        assert_eq!(quantity, Quantity::from_lots(500));
+        assert_eq!(quantity.to_decimal(LOT_SIZE).unwrap().to_string(), "0.500");
+        assert!(Quantity::parse("0.0005", LOT_SIZE).is_err());
+
+        // Checked arithmetic
+        assert_eq!(
+            price.checked_add(Price::from_ticks(1)),
+            Some(Price::from_ticks(2026))
+        );
+        assert_eq!(price.checked_sub(Price::from_ticks(2026)), None);
+        assert_eq!(Price::from_ticks(u64::MAX).checked_add(price), None);
+        assert_eq!(quantity.checked_mul(3), Some(Quantity::from_lots(1500)));
+        assert_eq!(Quantity::from_lots(u64::MAX).checked_mul(2), None);
+        assert_eq!(
+            quantity.checked_sub(Quantity::from_lots(500)),
+            Some(Quantity::default())
+        );
+    }
+}
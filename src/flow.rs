@@ -0,0 +1,441 @@
+use std::{fmt, time::Duration};
+
+use rand::{Rng, SeedableRng, rngs::StdRng, seq::IndexedRandom};
+use rand_distr::{Distribution, Exp, Normal, Pareto};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    fixed_point::{Price, Quantity},
+    market::{Market, MarketRegistry},
+    order::{Action, MarketId, Order, Side},
+};
+
+/// How the times between two orders are drawn
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize)]
+#[serde(tag = "process", rename_all = "lowercase")]
+pub enum ArrivalProcess {
+    /// Independent arrivals at a constant `rate`, in events per second
+    Poisson { rate: f64 },
+    /// Self-exciting arrivals, which come in bursts: each event raises the rate by `excitation`,
+    /// and the raise decays exponentially at `decay` per second. The rate is `base_rate` without
+    /// recent events, and `base_rate / (1 - excitation / decay)` on average.
+    Hawkes {
+        base_rate: f64,
+        excitation: f64,
+        decay: f64,
+    },
+}
+
+impl ArrivalProcess {
+    /// Average number of events per second
+    pub fn mean_rate(&self) -> f64 {
+        match *self {
+            ArrivalProcess::Poisson { rate } => rate,
+            ArrivalProcess::Hawkes {
+                base_rate,
+                excitation,
+                decay,
+            } => base_rate / (1.0 - excitation / decay),
+        }
+    }
+}
+
+/// Distribution of the distance between an order's price and the mid price
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PriceDistribution {
+    #[default]
+    Normal,
+    /// Double exponential, with more orders far from the mid than the normal distribution
+    Laplace,
+    Uniform,
+}
+
+/// Parameters of the synthetic order flow, see [`FlowGenerator`]
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct FlowConfig {
+    pub arrivals: ArrivalProcess,
+    /// Volatility of the mid price, in basis points over one second
+    pub mid_volatility_bps: f64,
+    #[serde(default)]
+    pub price_distribution: PriceDistribution,
+    /// Mean distance of the orders from the mid price, in basis points, away from the other side:
+    /// below the mid for bids, above for asks
+    pub price_offset_bps: f64,
+    /// Spread of the distances around `price_offset_bps`: the standard deviation for "normal",
+    /// the scale for "laplace" and the half width for "uniform". Orders more than
+    /// `price_offset_bps` away from it cross the mid and are likely to trade.
+    pub price_scale_bps: f64,
+    /// Smallest generated size, in multiples of the market's minimum size
+    pub size_scale: f64,
+    /// Tail index of the sizes, which follow a Pareto distribution: the smaller, the more large
+    /// orders. Sizes have no finite variance below 2.
+    pub size_tail_index: f64,
+    /// Share of the events cancelling a previously generated order
+    #[serde(default)]
+    pub cancel_ratio: f64,
+    /// Seed of the generator, random if omitted
+    #[serde(default)]
+    pub seed: Option<u64>,
+}
+
+impl FlowConfig {
+    pub fn validate(&self) -> Result<(), FlowError> {
+        let positive = [
+            ("mid_volatility_bps", self.mid_volatility_bps, true),
+            ("price_offset_bps", self.price_offset_bps, true),
+            ("price_scale_bps", self.price_scale_bps, true),
+            ("size_scale", self.size_scale, false),
+            ("size_tail_index", self.size_tail_index, false),
+        ];
+        let rates = match self.arrivals {
+            ArrivalProcess::Poisson { rate } => vec![("rate", rate, false)],
+            ArrivalProcess::Hawkes {
+                base_rate,
+                excitation,
+                decay,
+            } => {
+                if excitation >= decay {
+                    return Err(FlowError::Unstable { excitation, decay });
+                }
+                vec![
+                    ("base_rate", base_rate, false),
+                    ("excitation", excitation, true),
+                    ("decay", decay, false),
+                ]
+            }
+        };
+        for (name, value, zero_allowed) in positive.into_iter().chain(rates) {
+            if !(value > 0.0 || zero_allowed && value == 0.0) {
+                return Err(FlowError::Invalid { name, value });
+            }
+        }
+
+        if !(0.0..1.0).contains(&self.cancel_ratio) {
+            return Err(FlowError::Invalid {
+                name: "cancel_ratio",
+                value: self.cancel_ratio,
+            });
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum FlowError {
+    /// A parameter is out of its range
+    Invalid { name: &'static str, value: f64 },
+    /// Each event of a Hawkes process triggers more than one other on average, so the rate grows
+    /// without bound
+    Unstable { excitation: f64, decay: f64 },
+}
+
+impl fmt::Display for FlowError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FlowError::Invalid { name, value } => write!(f, "invalid {name} (= {value})"),
+            FlowError::Unstable { excitation, decay } => write!(
+                f,
+                "Hawkes excitation (= {excitation}) must be lower than its decay (= {decay})"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for FlowError {}
+
+/// Action of the synthetic flow
+#[derive(Debug, Clone, PartialEq)]
+pub struct FlowEvent {
+    /// Time since the start of the flow
+    pub time: Duration,
+    pub action: Action,
+}
+
+/// Market state of the generator
+#[derive(Debug, Clone)]
+struct MarketFlow {
+    /// Mid price, in ticks
+    mid: f64,
+    /// When the mid last moved, in seconds since the start of the flow
+    updated_at: f64,
+}
+
+/// Endless synthetic order flow, closer to real market traffic than [`MarketRegistry::random_order`].
+/// Events arrive according to an [`ArrivalProcess`] on markets picked by weight. Each market's mid
+/// price follows a Gaussian random walk from its reference price, orders are placed around it on
+/// either side with equal probability, and their sizes are heavy-tailed. Some events cancel one of
+/// the orders placed before instead.
+#[derive(Debug, Clone)]
+pub struct FlowGenerator {
+    config: FlowConfig,
+    markets: Vec<Market>,
+    flows: Vec<MarketFlow>,
+    rng: StdRng,
+    /// Time of the last event, in seconds
+    time: f64,
+    /// Excitation of a Hawkes process at `time`
+    excitation: f64,
+    /// Orders placed and not cancelled yet
+    live: Vec<(MarketId, u64)>,
+}
+
+impl FlowGenerator {
+    /// Generator of a flow whose config was validated
+    pub fn new(config: FlowConfig, markets: &MarketRegistry) -> Self {
+        let rng = match config.seed {
+            Some(seed) => StdRng::seed_from_u64(seed),
+            None => StdRng::from_rng(&mut rand::rng()),
+        };
+        let flows = markets
+            .markets()
+            .iter()
+            .map(|market| MarketFlow {
+                mid: market.reference_price.ticks() as f64,
+                updated_at: 0.0,
+            })
+            .collect();
+
+        Self {
+            config,
+            markets: markets.markets().to_vec(),
+            flows,
+            rng,
+            time: 0.0,
+            excitation: 0.0,
+            live: Vec::new(),
+        }
+    }
+
+    /// Mid price of a market at the last event
+    pub fn mid(&self, market: MarketId) -> Option<f64> {
+        self.flows.get(market.value() as usize).map(|flow| flow.mid)
+    }
+
+    /// Draws the time of the next event, in seconds
+    fn next_time(&mut self) -> f64 {
+        match self.config.arrivals {
+            ArrivalProcess::Poisson { rate } => {
+                self.time += Exp::new(rate).unwrap().sample(&mut self.rng)
+            }
+            ArrivalProcess::Hawkes {
+                base_rate,
+                excitation,
+                decay,
+            } => {
+                // Thinning: the rate only decreases until the next event, so candidates are drawn
+                // at the current rate and kept with the ratio of the rate at their time to it
+                loop {
+                    let bound = base_rate + self.excitation;
+                    let wait = Exp::new(bound).unwrap().sample(&mut self.rng);
+                    self.time += wait;
+                    self.excitation *= (-decay * wait).exp();
+                    if self.rng.random::<f64>() * bound <= base_rate + self.excitation {
+                        break;
+                    }
+                }
+                self.excitation += excitation;
+            }
+        }
+        self.time
+    }
+
+    fn place(&mut self, market: usize) -> Order {
+        let config = &self.config;
+        let flow = &mut self.flows[market];
+        let market = &self.markets[market];
+
+        // The mid moves by its volatility over the time since its last move
+        let elapsed = self.time - flow.updated_at;
+        let volatility = flow.mid * config.mid_volatility_bps / 10_000.0 * elapsed.sqrt();
+        if volatility > 0.0 {
+            flow.mid += Normal::new(0.0, volatility).unwrap().sample(&mut self.rng);
+            flow.mid = flow.mid.max(1.0);
+        }
+        flow.updated_at = self.time;
+
+        let scale = config.price_scale_bps;
+        let noise = match config.price_distribution {
+            PriceDistribution::Normal => Normal::new(0.0, scale).unwrap().sample(&mut self.rng),
+            PriceDistribution::Laplace => {
+                let distance = Exp::new(1.0 / scale).unwrap().sample(&mut self.rng);
+                if self.rng.random_bool(0.5) {
+                    distance
+                } else {
+                    -distance
+                }
+            }
+            PriceDistribution::Uniform => self.rng.random_range(-scale..=scale),
+        };
+        let distance = flow.mid * (config.price_offset_bps + noise) / 10_000.0;
+        let side = if self.rng.random_bool(0.5) {
+            Side::Bid
+        } else {
+            Side::Ask
+        };
+        let price = match side {
+            Side::Bid => flow.mid - distance,
+            Side::Ask => flow.mid + distance,
+        };
+        let price = Price::from_ticks(price.round().max(1.0) as u64);
+
+        let min = market.min_quantity.lots() as f64;
+        let size = Pareto::new(min * config.size_scale, config.size_tail_index)
+            .unwrap()
+            .sample(&mut self.rng);
+        let quantity = Quantity::from_lots(
+            (size.round() as u64).clamp(market.min_quantity.lots(), market.max_quantity.lots()),
+        );
+
+        Order::new(self.rng.random(), market.id, side, price, quantity)
+    }
+}
+
+impl Iterator for FlowGenerator {
+    type Item = FlowEvent;
+
+    fn next(&mut self) -> Option<FlowEvent> {
+        let time = Duration::from_secs_f64(self.next_time());
+
+        if !self.live.is_empty() && self.rng.random_bool(self.config.cancel_ratio) {
+            let index = self.rng.random_range(0..self.live.len());
+            let (market, order_id) = self.live.swap_remove(index);
+            return Some(FlowEvent {
+                time,
+                action: Action::Cancel { market, order_id },
+            });
+        }
+
+        let market = self
+            .markets
+            .choose_weighted(&mut self.rng, |market| market.weight)
+            .expect("At least one market has a weight")
+            .id;
+        let order = self.place(market.value() as usize);
+        self.live.push((order.market, order.id));
+        Some(FlowEvent {
+            time,
+            action: Action::Place(order),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use super::*;
+
+    fn flow_config(arrivals: ArrivalProcess) -> FlowConfig {
+        FlowConfig {
+            arrivals,
+            mid_volatility_bps: 10.0,
+            price_distribution: PriceDistribution::Laplace,
+            price_offset_bps: 5.0,
+            price_scale_bps: 10.0,
+            size_scale: 100.0,
+            size_tail_index: 1.5,
+            cancel_ratio: 0.3,
+            seed: Some(42),
+        }
+    }
+
+    #[test]
+    fn test_flow() {
+        let markets = MarketRegistry::default();
+        let config = flow_config(ArrivalProcess::Poisson { rate: 100.0 });
+        assert_eq!(config.validate(), Ok(()));
+        let events: Vec<FlowEvent> = FlowGenerator::new(config, &markets).take(10_000).collect();
+
+        // 100 events per second
+        let duration = events.last().unwrap().time.as_secs_f64();
+        assert!((duration - 100.0).abs() < 5.0, "{duration}");
+        assert!(events.windows(2).all(|w| w[0].time <= w[1].time));
+
+        let mut placed = HashSet::new();
+        let mut cancels = 0;
+        for event in &events {
+            match &event.action {
+                Action::Place(order) => {
+                    assert_eq!(markets.check(order), Ok(()));
+                    placed.insert(order.id);
+                }
+                Action::Cancel { order_id, .. } => {
+                    cancels += 1;
+                    assert!(placed.remove(order_id));
+                }
+                Action::Amend { .. } => unreachable!(),
+            }
+        }
+        assert!((2_800..3_200).contains(&cancels), "{cancels}");
+
+        // Heavy tail: a few orders much larger than the typical one
+        let mut sizes: Vec<u64> = events
+            .iter()
+            .filter_map(|event| match &event.action {
+                Action::Place(order) => Some(order.quantity.lots()),
+                _ => None,
+            })
+            .collect();
+        sizes.sort_unstable();
+        let median = sizes[sizes.len() / 2];
+        assert!(sizes[0] >= 100);
+        assert!(*sizes.last().unwrap() > 50 * median);
+
+        // Same seed, same flow
+        let again: Vec<FlowEvent> = FlowGenerator::new(
+            flow_config(ArrivalProcess::Poisson { rate: 100.0 }),
+            &markets,
+        )
+        .take(10_000)
+        .collect();
+        assert_eq!(events, again);
+    }
+
+    #[test]
+    fn test_hawkes() {
+        let markets = MarketRegistry::default();
+        let arrivals = ArrivalProcess::Hawkes {
+            base_rate: 25.0,
+            excitation: 75.0,
+            decay: 100.0,
+        };
+        assert_eq!(arrivals.mean_rate(), 100.0);
+        let times: Vec<f64> = FlowGenerator::new(flow_config(arrivals), &markets)
+            .take(20_000)
+            .map(|event| event.time.as_secs_f64())
+            .collect();
+
+        let duration = times.last().unwrap();
+        assert!((duration - 200.0).abs() < 20.0, "{duration}");
+
+        // Bursts: the gaps between events vary more than for a Poisson process, where their
+        // standard deviation equals their mean
+        let gaps: Vec<f64> = times.windows(2).map(|w| w[1] - w[0]).collect();
+        let mean = gaps.iter().sum::<f64>() / gaps.len() as f64;
+        let variance = gaps.iter().map(|gap| (gap - mean).powi(2)).sum::<f64>() / gaps.len() as f64;
+        assert!(variance.sqrt() > 1.5 * mean);
+
+        let unstable = ArrivalProcess::Hawkes {
+            base_rate: 50.0,
+            excitation: 10.0,
+            decay: 10.0,
+        };
+        assert_eq!(
+            flow_config(unstable).validate(),
+            Err(FlowError::Unstable {
+                excitation: 10.0,
+                decay: 10.0
+            })
+        );
+        let mut invalid = flow_config(ArrivalProcess::Poisson { rate: 100.0 });
+        invalid.cancel_ratio = 1.0;
+        assert_eq!(
+            invalid.validate(),
+            Err(FlowError::Invalid {
+                name: "cancel_ratio",
+                value: 1.0
+            })
+        );
+    }
+}
@@ -0,0 +1,143 @@
+use std::{
+    sync::OnceLock,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use bincode::{Decode, Encode};
+use borsh::{BorshDeserialize, BorshSerialize};
+use serde::{Deserialize, Serialize};
+use tokio::time::Instant;
+
+/// Hybrid logical clock timestamp: a physical time in milliseconds, and a counter ordering the
+/// events that happened within the same millisecond. Timestamps compare physical time first.
+#[derive(
+    Debug,
+    Clone,
+    Copy,
+    Default,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    Hash,
+    Decode,
+    Encode,
+    BorshDeserialize,
+    BorshSerialize,
+    Deserialize,
+    Serialize,
+)]
+pub struct Timestamp {
+    /// Milliseconds since the Unix epoch
+    pub physical: u64,
+    pub logical: u32,
+}
+
+impl Timestamp {
+    /// Size of an encoded timestamp in the fixed codec
+    pub const SIZE: usize = 12;
+
+    pub fn new(physical: u64, logical: u32) -> Self {
+        Self { physical, logical }
+    }
+}
+
+/// Hybrid logical clock: stays close to physical time, but never goes backwards and always runs
+/// ahead of the timestamps it received. An event stamped after receiving another one is always
+/// stamped later, whatever the skew between the physical clocks.
+#[derive(Debug, Clone, Default)]
+pub struct HybridClock {
+    last: Timestamp,
+}
+
+impl HybridClock {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Timestamp of a local event, such as sending an order
+    pub fn now(&mut self) -> Timestamp {
+        self.tick(physical_now(), None)
+    }
+
+    /// Merges a timestamp received from another clock, returns the timestamp of the reception
+    pub fn update(&mut self, received: Timestamp) -> Timestamp {
+        self.tick(physical_now(), Some(received))
+    }
+
+    /// Last timestamp given by the clock
+    pub fn last(&self) -> Timestamp {
+        self.last
+    }
+
+    fn tick(&mut self, physical: u64, received: Option<Timestamp>) -> Timestamp {
+        let latest = received.map_or(self.last, |received| received.max(self.last));
+        self.last = if physical > latest.physical {
+            Timestamp::new(physical, 0)
+        } else {
+            match latest.logical.checked_add(1) {
+                Some(logical) => Timestamp::new(latest.physical, logical),
+                // Out of counters for this millisecond, e.g. sent one at the limit by a peer
+                None => Timestamp::new(latest.physical + 1, 0),
+            }
+        };
+        self.last
+    }
+}
+
+/// Physical time in milliseconds since the Unix epoch. It follows tokio's clock from the first
+/// call, so that it runs on the virtual clock of the simulated network.
+pub fn physical_now() -> u64 {
+    since_epoch().as_millis() as u64
+}
+
+/// Time since the Unix epoch, on the same clock as [`physical_now`]
+pub fn since_epoch() -> Duration {
+    static START: OnceLock<(Duration, Instant)> = OnceLock::new();
+    let (since_epoch, start) = START.get_or_init(|| {
+        let since_epoch = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .expect("System clock before the Unix epoch");
+        (since_epoch, Instant::now())
+    });
+    *since_epoch + Instant::now().saturating_duration_since(*start)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hybrid_clock() {
+        let mut clock = HybridClock::new();
+        assert_eq!(clock.tick(100, None), Timestamp::new(100, 0));
+        // Same millisecond, or physical clock going backwards
+        assert_eq!(clock.tick(100, None), Timestamp::new(100, 1));
+        assert_eq!(clock.tick(90, None), Timestamp::new(100, 2));
+        assert_eq!(clock.tick(101, None), Timestamp::new(101, 0));
+
+        // Received from a clock running ahead: stamped after it
+        assert_eq!(
+            clock.tick(102, Some(Timestamp::new(150, 3))),
+            Timestamp::new(150, 4)
+        );
+        assert_eq!(clock.tick(120, None), Timestamp::new(150, 5));
+        // Received from a clock running behind: only the physical time counts
+        assert_eq!(
+            clock.tick(151, Some(Timestamp::new(110, 0))),
+            Timestamp::new(151, 0)
+        );
+        assert_eq!(
+            clock.tick(151, Some(Timestamp::new(151, 7))),
+            Timestamp::new(151, 8)
+        );
+        assert_eq!(clock.last(), Timestamp::new(151, 8));
+
+        // The counter doesn't wrap around, the next millisecond starts instead
+        assert_eq!(
+            clock.tick(151, Some(Timestamp::new(160, u32::MAX))),
+            Timestamp::new(161, 0)
+        );
+        assert_eq!(clock.tick(155, None), Timestamp::new(161, 1));
+    }
+}
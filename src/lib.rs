@@ -1,6 +1,27 @@
+pub mod book;
+pub mod codec;
+pub mod committee;
+pub mod compression;
 pub mod config;
+pub mod convergence;
+pub mod envelope;
+pub mod event;
+pub mod fairness;
+pub mod fixed_point;
+pub mod flow;
+pub mod hlc;
+pub mod load;
+pub mod market;
 pub mod network;
 pub mod node;
 pub mod order;
+pub mod order_state;
+pub mod origin;
 pub mod packet;
 pub mod plot;
+pub mod process;
+pub mod replay;
+pub mod sequencer;
+pub mod signing;
+pub mod topology;
+pub mod transport;
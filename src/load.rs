@@ -0,0 +1,196 @@
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use crate::packet::PacketId;
+
+/// Offered loads of the load test, read from the `[load]` table of the config
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct LoadConfig {
+    /// Injection rates, in orders per second, tested one after the other
+    pub rates: Vec<f64>,
+    /// How long orders are injected at each rate
+    pub duration_ms: u64,
+}
+
+impl LoadConfig {
+    pub fn duration(&self) -> Duration {
+        Duration::from_millis(self.duration_ms)
+    }
+
+    /// Number of orders injected at `rate`
+    pub fn num_orders(&self, rate: f64) -> usize {
+        (rate * self.duration().as_secs_f64()).round() as usize
+    }
+}
+
+/// Coverage of packets in flight together, whose ids follow each other: how many nodes each one
+/// reached, and when
+#[derive(Debug, Clone)]
+pub struct Coverage {
+    first_id: u64,
+    /// Nodes a packet must reach to be propagated
+    threshold: usize,
+    /// Nodes reached by each packet
+    received: Vec<usize>,
+    /// Latency of every delivery
+    latencies: Vec<Duration>,
+    /// Latency of the delivery that made each propagated packet reach the threshold
+    propagation_times: Vec<Duration>,
+    /// Index of each propagated packet, in the sequence of `propagation_times`
+    propagated: Vec<usize>,
+}
+
+impl Coverage {
+    pub fn new(first_id: PacketId, num_packets: usize, threshold: usize) -> Self {
+        Self {
+            first_id: first_id.value(),
+            threshold,
+            received: vec![0; num_packets],
+            latencies: Vec::new(),
+            propagation_times: Vec::new(),
+            propagated: Vec::new(),
+        }
+    }
+
+    /// Index of a tracked packet, `None` for packets of other runs
+    pub fn index(&self, id: PacketId) -> Option<usize> {
+        let index = id.value().checked_sub(self.first_id)? as usize;
+        (index < self.received.len()).then_some(index)
+    }
+
+    /// Records that the packet at `index` reached one more node, `latency` after it was sent
+    pub fn record(&mut self, index: usize, latency: Duration) {
+        self.latencies.push(latency);
+        self.received[index] += 1;
+        if self.received[index] == self.threshold {
+            self.propagation_times.push(latency);
+            self.propagated.push(index);
+        }
+    }
+
+    /// Whether every packet reached the threshold
+    pub fn is_complete(&self) -> bool {
+        self.propagation_times.len() == self.received.len()
+    }
+
+    pub fn num_packets(&self) -> usize {
+        self.received.len()
+    }
+
+    /// Packets that reached the threshold
+    pub fn num_propagated(&self) -> usize {
+        self.propagation_times.len()
+    }
+
+    /// Average share of the `num_nodes` nodes reached by the packets
+    pub fn mean_coverage(&self, num_nodes: usize) -> f64 {
+        if self.received.is_empty() {
+            return 0.0;
+        }
+        let total: usize = self.received.iter().sum();
+        total as f64 / (self.received.len() * num_nodes) as f64
+    }
+
+    /// Time each propagated packet took to reach the threshold, in the order they reached it
+    pub fn propagation_times(&self) -> &[Duration] {
+        &self.propagation_times
+    }
+
+    /// Index and propagation time of each propagated packet
+    pub fn propagated(&self) -> impl Iterator<Item = (usize, Duration)> + '_ {
+        self.propagated
+            .iter()
+            .copied()
+            .zip(self.propagation_times.iter().copied())
+    }
+
+    pub fn into_latencies(self) -> Vec<Duration> {
+        self.latencies
+    }
+}
+
+/// Percentiles of a set of durations
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Percentiles {
+    pub p50: Duration,
+    pub p90: Duration,
+    pub p99: Duration,
+    pub max: Duration,
+}
+
+impl Percentiles {
+    /// Nearest-rank percentiles, `None` without any duration
+    pub fn new(durations: &[Duration]) -> Option<Self> {
+        let mut sorted = durations.to_vec();
+        sorted.sort_unstable();
+        let max = *sorted.last()?;
+        let percentile = |p: f64| {
+            let rank = (p * sorted.len() as f64).ceil() as usize;
+            sorted[rank.max(1) - 1]
+        };
+
+        Some(Self {
+            p50: percentile(0.5),
+            p90: percentile(0.9),
+            p99: percentile(0.99),
+            max,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_coverage() {
+        let ms = Duration::from_millis;
+        let mut coverage = Coverage::new(PacketId::new(10), 3, 2);
+        assert_eq!(coverage.index(PacketId::new(9)), None);
+        assert_eq!(coverage.index(PacketId::new(12)), Some(2));
+        assert_eq!(coverage.index(PacketId::new(13)), None);
+
+        coverage.record(0, ms(0));
+        coverage.record(1, ms(0));
+        coverage.record(0, ms(50));
+        coverage.record(0, ms(60));
+        coverage.record(2, ms(10));
+        coverage.record(2, ms(40));
+        assert!(!coverage.is_complete());
+        assert_eq!(coverage.num_propagated(), 2);
+        assert_eq!(coverage.propagation_times(), [ms(50), ms(40)]);
+        assert!(coverage.propagated().eq([(0, ms(50)), (2, ms(40))]));
+        assert_eq!(coverage.mean_coverage(3), 6.0 / 9.0);
+        coverage.record(1, ms(100));
+        assert!(coverage.is_complete());
+        assert_eq!(coverage.into_latencies().len(), 7);
+
+        assert_eq!(Percentiles::new(&[]), None);
+        let durations: Vec<Duration> = (1..=200).rev().map(ms).collect();
+        assert_eq!(
+            Percentiles::new(&durations),
+            Some(Percentiles {
+                p50: ms(100),
+                p90: ms(180),
+                p99: ms(198),
+                max: ms(200),
+            })
+        );
+        assert_eq!(
+            Percentiles::new(&[ms(7)]),
+            Some(Percentiles {
+                p50: ms(7),
+                p90: ms(7),
+                p99: ms(7),
+                max: ms(7),
+            })
+        );
+
+        let config = LoadConfig {
+            rates: vec![100.0, 2_500.0],
+            duration_ms: 2_000,
+        };
+        assert_eq!(config.num_orders(2_500.0), 5_000);
+    }
+}
@@ -1,89 +1,895 @@
-use std::{process::exit, time::Duration};
+use std::{
+    collections::{HashMap, HashSet},
+    net::SocketAddr,
+    path::{Path, PathBuf},
+    process::exit,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
 
+use clap::{Parser, Subcommand};
 use order_propagation::{
+    codec::Codec,
+    committee::{Committee, CommitteeSettings},
     config::Config,
-    network::Network,
-    packet::{GossipPacket, PacketId, SerialiedPacket},
+    convergence::{Convergence, check_convergence},
+    event::{EventStats, NodeEvent},
+    fairness::FairnessReport,
+    flow::{FlowEvent, FlowGenerator},
+    hlc::{self, HybridClock, Timestamp},
+    load::{Coverage, LoadConfig, Percentiles},
+    network::{Network, NodeId},
+    node::{NodeState, Rejection},
+    order::Action,
+    origin::{OriginPolicy, Origins},
+    packet::{EncodeOptions, GossipPacket, PacketId, SerialiedPacket},
     plot,
+    process::{self, Cluster},
+    replay::{self, ReplayConfig},
+    sequencer::Sequencing,
+    signing,
+    topology::Topology,
+    transport::{TransportKind, quic::QuicIdentity},
 };
 use tokio::sync::mpsc;
 
-#[tokio::main]
-async fn main() {
+#[derive(Parser)]
+#[command(about = "Simulates the propagation of orders through a gossip network")]
+struct Cli {
+    /// Runs the whole network in this process when omitted
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Runs a single node of a multi-process network
+    Node {
+        /// Id of the node in the topology
+        #[arg(long)]
+        id: u64,
+        /// Topology file giving the node's listen address and its peers
+        #[arg(long, default_value = "topology.json")]
+        topology: PathBuf,
+        /// Launcher's control socket, used to receive injected packets and send reports
+        #[arg(long)]
+        control: SocketAddr,
+    },
+    /// Starts one process per node on localhost and measures the propagation across them
+    Launch {
+        /// Where to write the topology file shared with the node processes
+        #[arg(long, default_value = "topology.json")]
+        topology: PathBuf,
+        /// First port assigned to the nodes, each node listens on the next one
+        #[arg(long, default_value_t = 40_000)]
+        base_port: u16,
+    },
+}
+
+fn main() {
+    let cli = Cli::parse();
     let config = Config::load().unwrap();
+
+    match cli.command {
+        None => {
+            let runtime = if config.transport.uses_virtual_clock() {
+                // Time only advances when every task is idle, so processing takes no time.
+                // A paused clock is only available on the current thread runtime.
+                tokio::runtime::Builder::new_current_thread()
+                    .enable_all()
+                    .start_paused(true)
+                    .build()
+            } else {
+                tokio::runtime::Builder::new_multi_thread()
+                    .enable_all()
+                    .build()
+            };
+
+            runtime
+                .expect("Failed to start the runtime")
+                .block_on(run(config));
+        }
+        Some(Command::Node {
+            id,
+            topology,
+            control,
+        }) => {
+            // Nodes only have one task to run, keep each process light
+            let runtime = tokio::runtime::Builder::new_current_thread()
+                .enable_all()
+                .build()
+                .expect("Failed to start the runtime");
+            let topology = Topology::load(&topology).expect("Failed to load the topology");
+
+            if let Err(e) = runtime.block_on(process::run_node(
+                NodeId::new(id),
+                &topology,
+                control,
+                config.latency(),
+                config.node_settings(),
+                config.transport,
+                config.codec,
+            )) {
+                eprintln!("[{:?}]: {e}", NodeId::new(id));
+                exit(1)
+            }
+        }
+        Some(Command::Launch {
+            topology,
+            base_port,
+        }) => {
+            tokio::runtime::Builder::new_multi_thread()
+                .enable_all()
+                .build()
+                .expect("Failed to start the runtime")
+                .block_on(launch(config, &topology, base_port));
+        }
+    }
+}
+
+/// Runs every node as a task of this process
+async fn run(config: Config) {
+    let network = Network::generate_network(config.num_nodes, config.num_neighbors);
+    let (report_tx, report_rx) = mpsc::channel::<NodeEvent>(report_channel_size(&config));
+
+    let running = network
+        .run_network(
+            config.latency(),
+            config.node_settings(),
+            config.transport,
+            config.codec,
+            config.bit_flip_probability,
+            &report_tx,
+        )
+        .await
+        .expect("Failed to start the network")
+        .expect("Empty network");
+
+    // Orders go to the committee, whose leader runs on the start node and gossips them in sequence
+    let committee = (config.committee_size > 0).then(|| {
+        let mut followers: Vec<NodeId> = running
+            .nodes
+            .keys()
+            .filter(|node_id| **node_id != running.start_node_id)
+            .cloned()
+            .collect();
+        followers.sort_by_key(NodeId::value);
+        let members = std::iter::once(running.start_node_id.clone())
+            .chain(followers)
+            .take(config.committee_size as usize)
+            .collect();
+        let settings = CommitteeSettings {
+            members,
+            latency: config.latency(),
+            verification: config.verification(),
+            ttl: config.time_to_live,
+            max_packet_size: config.transport.max_packet_size(),
+        };
+        Committee::start(settings, config.codec, running.start_sender.clone())
+    });
+    let submit = committee.as_ref().map(|c| &c.submit);
+    let injector = Injector {
+        origins: Origins::new(config.origin, &network).expect("No node matches the origin policy"),
+        senders: &running.senders,
+        submit,
+        nodes: Some(&running.nodes),
+        clock: Mutex::new(HybridClock::new()),
+    };
+
+    let stats = match (&config.replay, &config.load) {
+        (Some(replay_config), _) => {
+            replay(&config, replay_config, &network, &injector, report_rx).await
+        }
+        (None, Some(load_config)) => {
+            load_test(&config, load_config, &network, &injector, report_rx).await
+        }
+        (None, None) => measure(&config, &network, &injector, report_rx).await,
+    };
+
+    if let Some(committee) = &committee {
+        let (mean, std_dev) = calculate_stats(&committee.sequencing_times());
+        println!(
+            "Time to sequenced by a committee of {} (mean ± σ): {mean:?} ± {std_dev:?}",
+            config.committee_size
+        );
+    }
+
+    if config.convergence_interval_ms > 0 {
+        let nodes: Vec<&NodeState> = running.nodes.values().map(Arc::as_ref).collect();
+        let convergence = check_convergence(
+            &nodes,
+            &config.markets(),
+            Duration::from_millis(config.convergence_interval_ms),
+            Duration::from_millis(config.convergence_timeout_ms),
+        )
+        .await;
+        print_convergence(&config, &convergence);
+    }
+
+    print_fairness(&stats);
+
+    // Every node matches the orders in its own books, in the order it received them
+    let fills = running.nodes.values().map(|node| node.num_fills());
+    println!(
+        "Fills per node: {} to {}",
+        fills.clone().min().unwrap_or(0),
+        fills.max().unwrap_or(0)
+    );
+    println!(
+        "Cancels received before their order: {}",
+        running.total_buffered_cancels()
+    );
+    if let Sequencing::Timestamp { settlement_delay } = config.sequencing() {
+        println!(
+            "Actions received too late to be applied in sequence, with a settlement delay of {settlement_delay:?}: {}",
+            running.total_late_actions()
+        );
+    }
+}
+
+/// Pairs of nodes whose arrival sequences are compared, and pairs of orders checked for
+/// inconsistent sequences, as there are too many in large networks and long runs
+const MAX_FAIRNESS_PAIRS: usize = 10_000;
+
+/// Compares the sequences in which the nodes had the injected orders delivered, if there were
+/// several orders
+fn print_fairness(stats: &EventStats) {
+    let fairness = FairnessReport::new(stats.arrivals(), MAX_FAIRNESS_PAIRS);
+    if fairness.num_orders <= 1 {
+        return;
+    }
+    println!(
+        "Arrival order of {} orders: mean Kendall tau between nodes {:.3}, {:.1}% of the order pairs seen in different sequences",
+        fairness.num_orders,
+        fairness.mean_kendall_tau,
+        fairness.inconsistent_pairs * 100.0
+    );
+
+    let mut first_to_see: Vec<_> = fairness
+        .first_to_see
+        .iter()
+        .filter(|(_, count)| **count > 0)
+        .collect();
+    first_to_see.sort_by_key(|(node_id, count)| (std::cmp::Reverse(**count), node_id.value()));
+    println!(
+        "  Nodes that saw at least one order first: {}, most often:",
+        first_to_see.len()
+    );
+    for (node_id, count) in first_to_see.iter().take(3) {
+        println!("    {node_id:?}: {count} orders");
+    }
+}
+
+fn print_convergence(config: &Config, convergence: &Convergence) {
+    let initial = &convergence.initial;
+    println!(
+        "Books when the last order was sent: {} distinct states, the most common one on {} of {} nodes",
+        initial.distinct_states, initial.largest_group, initial.num_nodes
+    );
+    let markets = config.markets();
+    for snapshot in &initial.markets {
+        let symbol = &markets.get(snapshot.market).unwrap().symbol;
+        println!(
+            "  {symbol}: {} distinct tops of book, spreads disagree by {} ticks",
+            snapshot.distinct_tops,
+            snapshot.spread_disagreement()
+        );
+    }
+
+    match convergence.time {
+        Some(time) => println!("Books converged after {time:?}"),
+        None => println!(
+            "Books didn't converge within {} ms: {} distinct states left",
+            config.convergence_timeout_ms, convergence.last.distinct_states
+        ),
+    }
+}
+
+/// Runs every node in its own process, connected through real sockets
+async fn launch(config: Config, topology_path: &Path, base_port: u16) {
+    let identity = match config.transport {
+        TransportKind::Udp | TransportKind::Tcp => None,
+        TransportKind::QuicStream | TransportKind::QuicDatagram => {
+            Some(QuicIdentity::generate().expect("Failed to generate the QUIC certificate"))
+        }
+        TransportKind::Mpsc | TransportKind::Sim => {
+            eprintln!("Nodes in different processes need a socket transport (udp, tcp or quic)");
+            exit(1)
+        }
+    };
+    // These need the nodes running in main
+    let unsupported: Vec<&str> = [
+        (config.committee_size > 0, "committee_size"),
+        (config.replay.is_some(), "[replay]"),
+        (config.load.is_some(), "[load]"),
+        (
+            config.convergence_interval_ms > 0,
+            "convergence_interval_ms",
+        ),
+    ]
+    .into_iter()
+    .filter_map(|(set, setting)| set.then_some(setting))
+    .collect();
+    if !unsupported.is_empty() {
+        eprintln!(
+            "Not supported with nodes in different processes: {}",
+            unsupported.join(", ")
+        );
+        exit(1)
+    }
+
     let network = Network::generate_network(config.num_nodes, config.num_neighbors);
+    let topology = Topology::new(&network, base_port, identity.as_ref())
+        .expect("Failed to assign node addresses");
+    topology
+        .save(topology_path)
+        .expect("Failed to write the topology");
+
+    let (report_tx, report_rx) = mpsc::channel::<NodeEvent>(report_channel_size(&config));
+
+    println!("Starting {} node processes...", topology.nodes.len());
+    let cluster = Cluster::launch(topology_path, &topology, report_tx)
+        .await
+        .expect("Failed to launch the nodes");
+
+    let injector = Injector {
+        origins: Origins::new(config.origin, &network).expect("No node matches the origin policy"),
+        senders: cluster.injectors(),
+        submit: None,
+        nodes: None,
+        clock: Mutex::new(HybridClock::new()),
+    };
+
+    let stats = measure(&config, &network, &injector, report_rx).await;
+    print_fairness(&stats);
+}
+
+/// Room for about every event of a packet: its delivery, forwards and duplicates on each node
+fn report_channel_size(config: &Config) -> usize {
+    (config.num_nodes * (2 * config.num_peers + 1)) as usize
+}
+
+/// Where the measured packets are injected
+struct Injector<'a> {
+    origins: Origins,
+    senders: &'a HashMap<NodeId, mpsc::Sender<SerialiedPacket>>,
+    /// Submission channel of the committee, which sequences the packets of every origin
+    submit: Option<&'a mpsc::Sender<SerialiedPacket>>,
+    /// Nodes running in main, whose clocks stamp the actions injected at them
+    nodes: Option<&'a HashMap<NodeId, Arc<NodeState>>>,
+    /// Stamps the actions injected at node processes, whose clocks main can't reach
+    clock: Mutex<HybridClock>,
+}
+
+impl Injector<'_> {
+    /// Origin of the next packet, picked by the configured policy, and where to send it
+    fn pick(&self) -> (NodeId, mpsc::Sender<SerialiedPacket>) {
+        let origin = self.origins.pick();
+        let sender = self.submit.unwrap_or(&self.senders[&origin]).clone();
+        (origin, sender)
+    }
+
+    /// Timestamp of an action injected at `origin`, from the origin's clock
+    fn stamp(&self, origin: &NodeId) -> Timestamp {
+        match self.nodes {
+            Some(nodes) => nodes[origin].stamp(),
+            None => self.clock.lock().unwrap().now(),
+        }
+    }
+}
+
+/// Sends `num_runs` packets, each from an origin picked by the configured policy, and reports the
+/// time they took to reach 95% of the network, overall and by origin
+async fn measure(
+    config: &Config,
+    network: &Network,
+    injector: &Injector<'_>,
+    mut report_rx: mpsc::Receiver<NodeEvent>,
+) -> EventStats {
     let num_runs = config.num_runs as usize;
     let threshold = (config.num_nodes as f64 * 0.95).ceil() as usize;
     let mut packet_latencies = Vec::<Duration>::with_capacity(num_runs * threshold);
     let mut elapsed_times = Vec::<Duration>::with_capacity(num_runs);
+    let trader_key = config.sign_orders.then(signing::generate_trader_key);
+    let options = EncodeOptions {
+        trader_key: trader_key.as_ref(),
+        compression: config.compression,
+        compression_threshold: config.compression_threshold,
+    };
 
-    let (report_tx, mut report_rx) = mpsc::channel::<PacketId>(config.num_nodes as usize);
+    let markets = config.markets();
 
-    let (start_node_id, start_sender) = network
-        .run_network(config.latency(), config.num_peers, &report_tx)
-        .expect("Empty network");
+    // Actions of the synthetic flow if configured, in sequence. Their times are ignored since
+    // each one waits for the previous one to propagate.
+    let mut flow = config
+        .flow
+        .clone()
+        .map(|flow| FlowGenerator::new(flow, &markets));
+    let mut flow_cancels = 0;
+    let mut by_origin = HashMap::<NodeId, Vec<Duration>>::new();
+    // Runs whose packet didn't reach the threshold before the events stopped coming
+    let mut unpropagated = 0;
+    let mut stats = EventStats::default();
 
     for i in 0..num_runs {
-        let packet = GossipPacket::new_with_random_order(
+        let (origin, sender) = injector.pick();
+        let action = match flow.as_mut().and_then(Iterator::next) {
+            Some(event) => event.action,
+            None => Action::Place(markets.random_order()),
+        };
+        flow_cancels += matches!(action, Action::Cancel { .. }) as usize;
+        // Sent right behind the order, so it can overtake it on another path
+        let cancel = match &action {
+            Action::Place(order) => rand::random_bool(config.cancel_probability).then(|| {
+                let cancel = Action::Cancel {
+                    market: order.market,
+                    order_id: order.id,
+                };
+                // Ids after the orders' ones
+                let id = PacketId::new((num_runs + i) as u64);
+                GossipPacket::new(id, origin.clone(), config.time_to_live, cancel)
+            }),
+            _ => None,
+        };
+        let packet = GossipPacket::new(
             PacketId::new(i as u64),
-            start_node_id.clone(),
+            origin.clone(),
             config.time_to_live,
-        );
+            action,
+        )
+        .with_timestamp(injector.stamp(&origin));
+        let cancel = cancel.map(|cancel| cancel.with_timestamp(injector.stamp(&origin)));
+        let packets = std::iter::once(&packet)
+            .chain(&cancel)
+            .map(|packet| {
+                stats.record_injected(packet.id, &packet.action);
+                config.codec.encode_with(packet, &options)
+            })
+            .collect();
 
-        let (elapsed, latencies, returned_rx) =
-            propagate_message(packet, threshold, report_rx, start_sender.clone()).await;
+        let (reached, latencies) = propagate_message(
+            packet.id,
+            packets,
+            threshold,
+            &mut report_rx,
+            sender,
+            &mut stats,
+        )
+        .await;
 
-        report_rx = returned_rx;
+        packet_latencies.extend(&latencies);
+        let Some(elapsed) = reached else {
+            unpropagated += 1;
+            continue;
+        };
         elapsed_times.push(elapsed);
-        packet_latencies.extend(latencies);
+        by_origin.entry(origin).or_default().push(elapsed);
     }
 
     let (mean, std_dev) = calculate_stats(&elapsed_times);
     println!("Number of runs: {num_runs}\n95% Propagation Time (mean ± σ): {mean:?} ± {std_dev:?}");
+    if unpropagated > 0 {
+        println!("Runs whose packet didn't reach 95% of the nodes: {unpropagated}");
+    }
+    // The committee sequences the packets of every origin alike
+    if injector.submit.is_none() && by_origin.len() > 1 {
+        print_origins(Some(config.origin), &network.in_degrees(), &by_origin);
+    }
+    // Runs are sent one after the other, whatever the arrival times of the flow
+    if config.flow.is_some() {
+        println!(
+            "Synthetic flow: {} placements and {flow_cancels} cancels",
+            num_runs - flow_cancels
+        );
+    }
+    drain_events(&mut report_rx, &mut stats).await;
+    print_events(&stats);
 
     plot::plot_gossip_data(packet_latencies).expect("Failed to plot gossip data");
+    stats
+}
+
+/// Adds the propagation time of each packet tracked by `coverage` to the times of its origin, the
+/// packet at `index` having been injected from `origins[index]`
+fn add_by_origin(
+    by_origin: &mut HashMap<NodeId, Vec<Duration>>,
+    coverage: &Coverage,
+    origins: &[NodeId],
+) {
+    for (index, time) in coverage.propagated() {
+        by_origin
+            .entry(origins[index].clone())
+            .or_default()
+            .push(time);
+    }
+}
+
+/// Origins listed at each end of the breakdown by origin
+const MAX_LISTED_ORIGINS: usize = 5;
+
+/// Propagation times by origin, slowest first, for the origins picked by `policy` or else recorded
+fn print_origins(
+    policy: Option<OriginPolicy>,
+    in_degrees: &HashMap<NodeId, usize>,
+    by_origin: &HashMap<NodeId, Vec<Duration>>,
+) {
+    let mut origins: Vec<(&NodeId, Duration, Duration, usize)> = by_origin
+        .iter()
+        .map(|(origin, times)| {
+            let (mean, std_dev) = calculate_stats(times);
+            (origin, mean, std_dev, times.len())
+        })
+        .collect();
+    origins.sort_by_key(|(origin, mean, ..)| (std::cmp::Reverse(*mean), origin.value()));
+
+    let picked_by = match policy {
+        Some(policy) => format!("picked by {policy:?}"),
+        None => "recorded".to_string(),
+    };
+    println!(
+        "95% Propagation Time by origin ({} origins {picked_by}), slowest first:",
+        origins.len()
+    );
+    let print = |(origin, mean, std_dev, runs): &(&NodeId, Duration, Duration, usize)| {
+        println!(
+            "  {origin:?} (in-degree {}): {mean:?} ± {std_dev:?} over {runs} runs",
+            in_degrees.get(origin).copied().unwrap_or_default()
+        )
+    };
+    if origins.len() <= 2 * MAX_LISTED_ORIGINS {
+        origins.iter().for_each(print);
+    } else {
+        origins[..MAX_LISTED_ORIGINS].iter().for_each(print);
+        println!("  ...");
+        origins[origins.len() - MAX_LISTED_ORIGINS..]
+            .iter()
+            .for_each(print);
+    }
+}
+
+/// How long the reports can stop once every packet is sent before the packets that didn't reach
+/// the threshold are given up on
+const REPORT_IDLE_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Injects the recorded actions at their relative times, sped up, from their origin nodes (or into
+/// the committee, if any), and measures how long each one takes to reach 95% of the nodes. Unlike
+/// [`measure`], the actions don't wait for each other and are in flight together.
+async fn replay(
+    config: &Config,
+    replay_config: &ReplayConfig,
+    network: &Network,
+    injector: &Injector<'_>,
+    mut report_rx: mpsc::Receiver<NodeEvent>,
+) -> EventStats {
+    let events = match replay::load(&replay_config.file, &config.markets()) {
+        Ok(events) => events,
+        Err(e) => {
+            eprintln!("Failed to load {}: {e}", replay_config.file.display());
+            exit(1)
+        }
+    };
+
+    // Records without an origin are injected by the replay's origins in turn, or else by an origin
+    // picked by the policy
+    let mut default_origins = replay_config.origins.iter().cycle();
+    let origins: Vec<NodeId> = events
+        .iter()
+        .map(
+            |event| match event.origin.or_else(|| default_origins.next().copied()) {
+                Some(id) => NodeId::new(id),
+                None => injector.origins.pick(),
+            },
+        )
+        .collect();
+    if let Some(origin) = origins.iter().find(|id| !injector.senders.contains_key(id)) {
+        eprintln!("Replay origin {} isn't a node", origin.value());
+        exit(1)
+    }
+
+    let threshold = (config.num_nodes as f64 * 0.95).ceil() as usize;
+    let trader_key = config.sign_orders.then(signing::generate_trader_key);
+    let options = EncodeOptions {
+        trader_key: trader_key.as_ref(),
+        compression: config.compression,
+        compression_threshold: config.compression_threshold,
+    };
+    let start = tokio::time::Instant::now();
+    let start_epoch = hlc::since_epoch();
+    let sent_at = |i: usize| start_epoch + replay_config.delay(&events[i]);
+    let last = events.len().checked_sub(1).map_or(start_epoch, sent_at);
+
+    let inject = async {
+        for (i, (event, origin)) in events.iter().zip(&origins).enumerate() {
+            tokio::time::sleep_until(start + replay_config.delay(&events[i])).await;
+            let packet = GossipPacket::new(
+                PacketId::new(i as u64),
+                origin.clone(),
+                config.time_to_live,
+                event.action.clone(),
+            )
+            .with_timestamp(injector.stamp(origin));
+            let sender = injector.submit.unwrap_or(&injector.senders[origin]);
+            if let Err(e) = sender
+                .send(config.codec.encode_with(&packet, &options))
+                .await
+            {
+                eprintln!("Failed to send recorded action: {e}. Exiting...");
+                exit(1)
+            }
+        }
+    };
+
+    let mut coverage = Coverage::new(PacketId::new(0), events.len(), threshold);
+    let mut stats = EventStats::default();
+    for (i, event) in events.iter().enumerate() {
+        stats.record_injected(PacketId::new(i as u64), &event.action);
+    }
+    let collect = collect_coverage(&mut coverage, &mut report_rx, &mut stats, sent_at, last);
+    tokio::join!(inject, collect);
+    drain_events(&mut report_rx, &mut stats).await;
+
+    let num_origins = origins.iter().collect::<HashSet<_>>().len();
+    println!(
+        "Replayed {} actions recorded over {:?} from {num_origins} origin nodes, {}x faster",
+        events.len(),
+        events.last().map_or(Duration::ZERO, |event| event.time),
+        replay_config.speedup
+    );
+    let (mean, std_dev) = calculate_stats(coverage.propagation_times());
+    println!(
+        "Actions that reached 95% of the nodes: {} of {}\n95% Propagation Time (mean ± σ): {mean:?} ± {std_dev:?}",
+        coverage.num_propagated(),
+        coverage.num_packets()
+    );
+    if injector.submit.is_none() && num_origins > 1 {
+        let mut by_origin = HashMap::new();
+        add_by_origin(&mut by_origin, &coverage, &origins);
+        print_origins(None, &network.in_degrees(), &by_origin);
+    }
+    print_events(&stats);
+
+    plot::plot_gossip_data(coverage.into_latencies()).expect("Failed to plot gossip data");
+    stats
+}
+
+/// Injects orders at each of the configured rates in turn, with as many in flight as the rate
+/// gives, each from an origin picked by the configured policy, and reports the latency
+/// percentiles of each offered load
+async fn load_test(
+    config: &Config,
+    load_config: &LoadConfig,
+    network: &Network,
+    injector: &Injector<'_>,
+    mut report_rx: mpsc::Receiver<NodeEvent>,
+) -> EventStats {
+    let threshold = (config.num_nodes as f64 * 0.95).ceil() as usize;
+    let trader_key = config.sign_orders.then(signing::generate_trader_key);
+    let options = EncodeOptions {
+        trader_key: trader_key.as_ref(),
+        compression: config.compression,
+        compression_threshold: config.compression_threshold,
+    };
+    let markets = config.markets();
+    // The flow with its average rate, to rescale its arrival times to the offered rates
+    let mut flow = config.flow.clone().map(|flow| {
+        (
+            flow.arrivals.mean_rate(),
+            FlowGenerator::new(flow, &markets),
+        )
+    });
+    let mut next_id = 0;
+    let mut latencies = Vec::new();
+    let mut by_origin = HashMap::new();
+    let mut stats = EventStats::default();
+
+    println!(
+        "{:>13} {:>13} {:>12} {:>9} {:>9} {:>9} {:>9} {:>9}",
+        "offered (/s)", "sent (/s)", "reached 95%", "coverage", "p50", "p90", "p99", "max"
+    );
+    for &rate in &load_config.rates {
+        let num_orders = load_config.num_orders(rate);
+        let mut coverage = Coverage::new(PacketId::new(next_id), num_orders, threshold);
+        // Time of each order since the start of the step: random orders are evenly spaced, the
+        // flow keeps its bursts
+        let (offsets, actions): (Vec<Duration>, Vec<Action>) = match flow.as_mut() {
+            Some((flow_rate, flow)) => {
+                let events: Vec<FlowEvent> = flow.take(num_orders).collect();
+                let first = events.first().map_or(Duration::ZERO, |event| event.time);
+                events
+                    .into_iter()
+                    .map(|event| {
+                        (
+                            (event.time - first).mul_f64(*flow_rate / rate),
+                            event.action,
+                        )
+                    })
+                    .unzip()
+            }
+            None => (0..num_orders)
+                .map(|i| {
+                    let offset = Duration::from_secs_f64(i as f64 / rate);
+                    (offset, Action::Place(markets.random_order()))
+                })
+                .unzip(),
+        };
+        for (i, action) in actions.iter().enumerate() {
+            stats.record_injected(PacketId::new(next_id + i as u64), action);
+        }
+        let origins: Vec<NodeId> = (0..num_orders).map(|_| injector.origins.pick()).collect();
+        let start = tokio::time::Instant::now();
+        let start_epoch = hlc::since_epoch();
+        let sent_at = |i: usize| start_epoch + offsets[i];
+        let last = num_orders.checked_sub(1).map_or(start_epoch, sent_at);
+
+        let inject = async {
+            for (i, (offset, action)) in offsets.iter().zip(actions).enumerate() {
+                tokio::time::sleep_until(start + *offset).await;
+                let origin = &origins[i];
+                let packet = GossipPacket::new(
+                    PacketId::new(next_id + i as u64),
+                    origin.clone(),
+                    config.time_to_live,
+                    action,
+                )
+                .with_timestamp(injector.stamp(origin));
+                let sender = injector.submit.unwrap_or(&injector.senders[origin]);
+                if let Err(e) = sender
+                    .send(config.codec.encode_with(&packet, &options))
+                    .await
+                {
+                    eprintln!("Failed to send order: {e}. Exiting...");
+                    exit(1)
+                }
+            }
+            // Slower than offered when the network can't take the orders in
+            start.elapsed()
+        };
+        let collect = collect_coverage(&mut coverage, &mut report_rx, &mut stats, sent_at, last);
+        let (sending_time, ()) = tokio::join!(inject, collect);
+        next_id += num_orders as u64;
+
+        let sent_rate = num_orders as f64 / sending_time.max(load_config.duration()).as_secs_f64();
+        let reached = format!("{}/{}", coverage.num_propagated(), num_orders);
+        let coverage_pct = format!(
+            "{:.1}%",
+            coverage.mean_coverage(config.num_nodes as usize) * 100.0
+        );
+        match Percentiles::new(coverage.propagation_times()) {
+            Some(p) => println!(
+                "{rate:>13.0} {sent_rate:>13.0} {reached:>12} {coverage_pct:>9} {:>9} {:>9} {:>9} {:>9}",
+                format!("{:.1?}", p.p50),
+                format!("{:.1?}", p.p90),
+                format!("{:.1?}", p.p99),
+                format!("{:.1?}", p.max)
+            ),
+            None => println!("{rate:>13.0} {sent_rate:>13.0} {reached:>12} {coverage_pct:>9}"),
+        }
+        add_by_origin(&mut by_origin, &coverage, &origins);
+        latencies.extend(coverage.into_latencies());
+    }
+    if injector.submit.is_none() && by_origin.len() > 1 {
+        print_origins(Some(config.origin), &network.in_degrees(), &by_origin);
+    }
+    drain_events(&mut report_rx, &mut stats).await;
+    print_events(&stats);
+
+    plot::plot_gossip_data(latencies).expect("Failed to plot gossip data");
+    stats
+}
+
+/// Records the deliveries of the packets tracked by `coverage`, sent at `sent_at(index)` since the
+/// Unix epoch, until they all reached the threshold or no event came for a while after the `last`
+/// one was sent. Every event read is added to `stats`.
+async fn collect_coverage(
+    coverage: &mut Coverage,
+    report_rx: &mut mpsc::Receiver<NodeEvent>,
+    stats: &mut EventStats,
+    sent_at: impl Fn(usize) -> Duration,
+    last: Duration,
+) {
+    while !coverage.is_complete() {
+        let event = match tokio::time::timeout(REPORT_IDLE_TIMEOUT, report_rx.recv()).await {
+            Ok(Some(event)) => event,
+            Ok(None) => {
+                eprintln!("Report channel closed. Exiting...");
+                exit(1)
+            }
+            // Injection can pause longer than the timeout
+            Err(_) if hlc::since_epoch() < last => continue,
+            Err(_) => break,
+        };
+        stats.record(&event);
+        if let Some(index) = event.delivered().and_then(|id| coverage.index(id)) {
+            coverage.record(index, event.time().saturating_sub(sent_at(index)));
+        }
+    }
+}
+
+/// Adds the events still coming to `stats`, until none came for a while
+async fn drain_events(report_rx: &mut mpsc::Receiver<NodeEvent>, stats: &mut EventStats) {
+    while let Ok(Some(event)) = tokio::time::timeout(REPORT_IDLE_TIMEOUT, report_rx.recv()).await {
+        stats.record(&event);
+    }
 }
 
+fn print_events(stats: &EventStats) {
+    println!(
+        "Events: {} deliveries, {} forwards, {} duplicates ({:.2} per delivery), {} TTL expirations, {} drops",
+        stats.delivered,
+        stats.forwarded,
+        stats.duplicates,
+        stats.duplicates_per_delivery(),
+        stats.ttl_expired,
+        stats.dropped
+    );
+    let hops: Vec<String> = stats
+        .hops
+        .iter()
+        .enumerate()
+        .map(|(hop, count)| format!("{hop}: {count}"))
+        .collect();
+    println!("Deliveries by hop: {}", hops.join(", "));
+    let drops: Vec<String> = Rejection::ALL
+        .into_iter()
+        .filter(|reason| stats.drops(*reason) > 0)
+        .map(|reason| format!("{reason:?}: {}", stats.drops(reason)))
+        .collect();
+    if !drops.is_empty() {
+        println!("Drops by reason: {}", drops.join(", "));
+    }
+}
+
+/// Sends the encoded `packets`, the first one being `packet_id`, and waits for that packet to reach
+/// `threshold` nodes, or for the events to stop coming. Returns the time it took to reach them, if
+/// it did, and the latency of each delivery, every event read being added to `stats`.
 async fn propagate_message(
-    packet: GossipPacket,
+    packet_id: PacketId,
+    packets: Vec<SerialiedPacket>,
     threshold: usize,
-    mut report_rx: mpsc::Receiver<PacketId>,
+    report_rx: &mut mpsc::Receiver<NodeEvent>,
     node_sender: mpsc::Sender<SerialiedPacket>,
-) -> (Duration, Vec<Duration>, mpsc::Receiver<PacketId>) {
-    let now = tokio::time::Instant::now();
+    stats: &mut EventStats,
+) -> (Option<Duration>, Vec<Duration>) {
+    let sent = hlc::since_epoch();
 
-    if let Err(e) = node_sender.send(packet.borsh_serialize()).await {
-        eprintln!("Failed to send initial message: {e}. Exiting...");
-        exit(1)
+    for packet in packets {
+        if let Err(e) = node_sender.send(packet).await {
+            eprintln!("Failed to send initial message: {e}. Exiting...");
+            exit(1)
+        }
     }
 
     println!("Waiting for message to reach {threshold} nodes...");
 
-    let mut received_count = 0;
     let mut latencies = Vec::with_capacity(threshold);
 
     loop {
-        match report_rx.recv().await {
-            Some(packet_id) => {
-                // Ignore messages that are not for that packet_id (can happen if num_runs is > 1)
-                if packet_id == packet.id {
-                    received_count += 1;
-                    latencies.push(now.elapsed());
-                    if received_count >= threshold {
+        match tokio::time::timeout(REPORT_IDLE_TIMEOUT, report_rx.recv()).await {
+            Ok(Some(event)) => {
+                stats.record(&event);
+                // Ignore events of other packets (can happen if num_runs is > 1)
+                if event.delivered() == Some(packet_id) {
+                    latencies.push(event.time().saturating_sub(sent));
+                    if latencies.len() >= threshold {
                         println!("Propagation threshold reached!");
-                        break;
+                        return (latencies.last().copied(), latencies);
                     }
                 }
             }
-            None => {
+            Ok(None) => {
                 eprintln!("Report channel closed. Exiting...");
                 exit(1)
             }
+            // Dropped on the way, e.g. rejected by every node
+            Err(_) => {
+                println!(
+                    "No event for {REPORT_IDLE_TIMEOUT:?}, giving up after reaching {} nodes",
+                    latencies.len()
+                );
+                return (None, latencies);
+            }
         }
     }
-    let elapsed = now.elapsed();
-
-    (elapsed, latencies, report_rx)
 }
 
 /// Calculates the mean and standard deviation of durations.
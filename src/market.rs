@@ -0,0 +1,547 @@
+use std::{collections::HashSet, fmt};
+
+use rand::{Rng, seq::IndexedRandom};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    fixed_point::{Decimal, Price, Quantity},
+    order::{Action, MarketId, Order, OrderType, Side, TimeInForce},
+};
+
+/// Whether a market accepts orders
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MarketStatus {
+    #[default]
+    Open,
+    /// Orders are rejected until the market opens again
+    Halted,
+}
+
+/// Market as written in the config, with human-readable decimals
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct MarketConfig {
+    /// e.g. `SOL/USD`
+    pub symbol: String,
+    /// Asset being traded
+    pub base: String,
+    /// Asset the prices are expressed in
+    pub quote: String,
+    /// Smallest price increment, in the quote asset
+    pub tick_size: Decimal,
+    /// Smallest quantity increment, in the base asset
+    pub lot_size: Decimal,
+    /// Smallest order quantity accepted, in the base asset
+    pub min_size: Decimal,
+    /// Largest order quantity accepted, in the base asset
+    pub max_size: Decimal,
+    #[serde(default)]
+    pub status: MarketStatus,
+    /// Price around which orders are generated
+    pub reference_price: Decimal,
+    /// Share of the generated orders going to this market, relative to the other markets
+    #[serde(default = "MarketConfig::default_weight")]
+    pub weight: u32,
+}
+
+impl MarketConfig {
+    fn default_weight() -> u32 {
+        1
+    }
+
+    /// Market used when none is configured
+    pub fn sol_usd() -> Self {
+        Self {
+            symbol: "SOL/USD".to_string(),
+            base: "SOL".to_string(),
+            quote: "USD".to_string(),
+            tick_size: Decimal::new(1, 2),
+            lot_size: Decimal::new(1, 3),
+            min_size: Decimal::new(1, 3),
+            max_size: Decimal::new(10_000, 0),
+            status: MarketStatus::Open,
+            reference_price: Decimal::new(150, 0),
+            weight: 1,
+        }
+    }
+}
+
+/// Market of the registry, with its limits converted to ticks and lots
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Market {
+    pub id: MarketId,
+    pub symbol: String,
+    pub base: String,
+    pub quote: String,
+    pub tick_size: Decimal,
+    pub lot_size: Decimal,
+    pub min_quantity: Quantity,
+    pub max_quantity: Quantity,
+    pub status: MarketStatus,
+    pub reference_price: Price,
+    pub weight: u32,
+}
+
+impl Market {
+    fn new(id: MarketId, config: &MarketConfig) -> Result<Self, MarketError> {
+        let invalid = |reason: String| MarketError::Invalid {
+            symbol: config.symbol.clone(),
+            reason,
+        };
+
+        if config.tick_size.mantissa() == 0 || config.lot_size.mantissa() == 0 {
+            return Err(invalid("tick and lot sizes can't be 0".to_string()));
+        }
+
+        let quantity = |size: Decimal| {
+            Quantity::from_decimal(size, config.lot_size).map_err(|e| invalid(e.to_string()))
+        };
+        let min_quantity = quantity(config.min_size)?;
+        let max_quantity = quantity(config.max_size)?;
+        if min_quantity.lots() == 0 || min_quantity > max_quantity {
+            return Err(invalid(format!(
+                "invalid order size range {}..={}",
+                config.min_size, config.max_size
+            )));
+        }
+
+        let reference_price = Price::from_decimal(config.reference_price, config.tick_size)
+            .map_err(|e| invalid(e.to_string()))?;
+        if reference_price.ticks() == 0 {
+            return Err(invalid("reference price can't be 0".to_string()));
+        }
+
+        Ok(Self {
+            id,
+            symbol: config.symbol.clone(),
+            base: config.base.clone(),
+            quote: config.quote.clone(),
+            tick_size: config.tick_size,
+            lot_size: config.lot_size,
+            min_quantity,
+            max_quantity,
+            status: config.status,
+            reference_price,
+            weight: config.weight,
+        })
+    }
+
+    /// Random order on this market, within 10% of the reference price
+    pub fn random_order(&self) -> Order {
+        let mut rng = rand::rng();
+
+        let side = if rng.random_bool(0.5) {
+            Side::Bid
+        } else {
+            Side::Ask
+        };
+        let reference = self.reference_price.ticks();
+        let spread = reference / 10;
+
+        Order::new(
+            rng.random(),
+            self.id,
+            side,
+            Price::from_ticks(
+                rng.random_range(reference.saturating_sub(spread).max(1)..=reference + spread),
+            ),
+            Quantity::from_lots(
+                rng.random_range(self.min_quantity.lots()..=self.max_quantity.lots()),
+            ),
+        )
+    }
+}
+
+/// Markets the nodes accept orders for. A [`MarketId`] is the index of its market in the registry.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MarketRegistry {
+    markets: Vec<Market>,
+}
+
+impl MarketRegistry {
+    pub fn new(configs: &[MarketConfig]) -> Result<Self, MarketError> {
+        if configs.is_empty() {
+            return Err(MarketError::Empty);
+        }
+        if configs.len() > u16::MAX as usize + 1 {
+            return Err(MarketError::TooMany(configs.len()));
+        }
+        if configs.iter().all(|config| config.weight == 0) {
+            return Err(MarketError::NoWeight);
+        }
+
+        let mut symbols = HashSet::new();
+        let markets = configs
+            .iter()
+            .enumerate()
+            .map(|(index, config)| {
+                if !symbols.insert(&config.symbol) {
+                    return Err(MarketError::DuplicateSymbol(config.symbol.clone()));
+                }
+                Market::new(MarketId::new(index as u16), config)
+            })
+            .collect::<Result<_, _>>()?;
+
+        Ok(Self { markets })
+    }
+
+    pub fn get(&self, id: MarketId) -> Option<&Market> {
+        self.markets.get(id.value() as usize)
+    }
+
+    pub fn by_symbol(&self, symbol: &str) -> Option<&Market> {
+        self.markets.iter().find(|market| market.symbol == symbol)
+    }
+
+    pub fn markets(&self) -> &[Market] {
+        &self.markets
+    }
+
+    /// Random order on a market picked according to the markets' weights
+    pub fn random_order(&self) -> Order {
+        self.markets
+            .choose_weighted(&mut rand::rng(), |market| market.weight)
+            .expect("At least one market has a weight")
+            .random_order()
+    }
+
+    /// Checks an order against the rules of its market
+    pub fn check(&self, order: &Order) -> Result<(), OrderError> {
+        let market = self
+            .get(order.market)
+            .ok_or(OrderError::UnknownMarket(order.market))?;
+
+        if market.status == MarketStatus::Halted {
+            return Err(OrderError::Halted(order.market));
+        }
+        check_type(order)?;
+        if order.quantity < market.min_quantity || order.quantity > market.max_quantity {
+            return Err(OrderError::SizeOutOfRange {
+                quantity: order.quantity,
+                min: market.min_quantity,
+                max: market.max_quantity,
+            });
+        }
+
+        Ok(())
+    }
+
+    /// Checks what can be checked of an action without knowing the order it refers to: cancels
+    /// only need a known market, amends also an open one and a valid size. The amended price is
+    /// checked against the order's type once it is known.
+    pub fn check_action(&self, action: &Action) -> Result<(), OrderError> {
+        let (market_id, quantity) = match action {
+            Action::Place(order) => return self.check(order),
+            Action::Cancel { market, .. } => {
+                return self
+                    .get(*market)
+                    .map(|_| ())
+                    .ok_or(OrderError::UnknownMarket(*market));
+            }
+            Action::Amend {
+                market, quantity, ..
+            } => (*market, *quantity),
+        };
+
+        let market = self
+            .get(market_id)
+            .ok_or(OrderError::UnknownMarket(market_id))?;
+        if market.status == MarketStatus::Halted {
+            return Err(OrderError::Halted(market_id));
+        }
+        if quantity < market.min_quantity || quantity > market.max_quantity {
+            return Err(OrderError::SizeOutOfRange {
+                quantity,
+                min: market.min_quantity,
+                max: market.max_quantity,
+            });
+        }
+
+        Ok(())
+    }
+}
+
+/// Checks that the order's price, trigger and time in force are consistent with its type
+fn check_type(order: &Order) -> Result<(), OrderError> {
+    if order.order_type.has_price() {
+        if order.price.ticks() == 0 {
+            return Err(OrderError::ZeroPrice);
+        }
+    } else if order.price.ticks() != 0 {
+        return Err(OrderError::UnexpectedPrice);
+    }
+
+    if order.order_type.trigger() == Some(Price::from_ticks(0)) {
+        return Err(OrderError::ZeroPrice);
+    }
+
+    let valid_time_in_force = match order.time_in_force {
+        TimeInForce::GoodTillCancel => order.order_type.has_price(),
+        TimeInForce::ImmediateOrCancel | TimeInForce::FillOrKill => true,
+        TimeInForce::PostOnly => order.order_type == OrderType::Limit,
+    };
+    if !valid_time_in_force {
+        return Err(OrderError::InvalidTimeInForce(
+            order.order_type,
+            order.time_in_force,
+        ));
+    }
+
+    Ok(())
+}
+
+/// Only SOL/USD
+impl Default for MarketRegistry {
+    fn default() -> Self {
+        Self::new(&[MarketConfig::sol_usd()]).unwrap()
+    }
+}
+
+/// Invalid market configuration
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MarketError {
+    Empty,
+    /// More markets than ids
+    TooMany(usize),
+    /// Every market has a weight of 0, no order can be generated
+    NoWeight,
+    DuplicateSymbol(String),
+    Invalid {
+        symbol: String,
+        reason: String,
+    },
+}
+
+impl fmt::Display for MarketError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MarketError::Empty => write!(f, "no market configured"),
+            MarketError::TooMany(count) => write!(
+                f,
+                "{count} markets configured, at most {} supported",
+                u16::MAX as usize + 1
+            ),
+            MarketError::NoWeight => write!(f, "at least one market must have a weight"),
+            MarketError::DuplicateSymbol(symbol) => write!(f, "market {symbol} configured twice"),
+            MarketError::Invalid { symbol, reason } => write!(f, "market {symbol}: {reason}"),
+        }
+    }
+}
+
+impl std::error::Error for MarketError {}
+
+/// Why an order was refused by its market
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum OrderError {
+    UnknownMarket(MarketId),
+    Halted(MarketId),
+    /// Limit price or trigger of 0
+    ZeroPrice,
+    /// Market and stop orders trade at the book's prices, they can't have a limit price
+    UnexpectedPrice,
+    /// e.g. a market order can't rest in the book, nor be post-only
+    InvalidTimeInForce(OrderType, TimeInForce),
+    SizeOutOfRange {
+        quantity: Quantity,
+        min: Quantity,
+        max: Quantity,
+    },
+}
+
+impl fmt::Display for OrderError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OrderError::UnknownMarket(id) => write!(f, "unknown market {}", id.value()),
+            OrderError::Halted(id) => write!(f, "market {} is halted", id.value()),
+            OrderError::ZeroPrice => write!(f, "price can't be 0"),
+            OrderError::UnexpectedPrice => write!(f, "only limit orders have a price"),
+            OrderError::InvalidTimeInForce(order_type, time_in_force) => {
+                write!(f, "{order_type:?} order can't be {time_in_force:?}")
+            }
+            OrderError::SizeOutOfRange { quantity, min, max } => write!(
+                f,
+                "quantity of {} lots outside of {}..={}",
+                quantity.lots(),
+                min.lots(),
+                max.lots()
+            ),
+        }
+    }
+}
+
+impl std::error::Error for OrderError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn eth_usd() -> MarketConfig {
+        MarketConfig {
+            symbol: "ETH/USD".to_string(),
+            base: "ETH".to_string(),
+            quote: "USD".to_string(),
+            tick_size: Decimal::new(5, 2),
+            lot_size: Decimal::new(1, 4),
+            min_size: Decimal::new(1, 2),
+            max_size: Decimal::new(500, 0),
+            status: MarketStatus::Open,
+            reference_price: Decimal::new(3000, 0),
+            weight: 3,
+        }
+    }
+
+    #[test]
+    fn test_registry() {
+        let registry = MarketRegistry::new(&[MarketConfig::sol_usd(), eth_usd()]).unwrap();
+        let eth = registry.by_symbol("ETH/USD").unwrap();
+        assert_eq!(eth.id, MarketId::new(1));
+        assert_eq!(registry.get(MarketId::new(1)), Some(eth));
+        assert_eq!(eth.min_quantity, Quantity::from_lots(100));
+        assert_eq!(eth.max_quantity, Quantity::from_lots(5_000_000));
+        assert_eq!(eth.reference_price, Price::from_ticks(60_000));
+
+        // Generated orders follow the weights and the market's rules
+        let orders: Vec<Order> = (0..1000).map(|_| registry.random_order()).collect();
+        let eth_orders = orders.iter().filter(|o| o.market == eth.id).count();
+        assert!((600..900).contains(&eth_orders), "{eth_orders} ETH orders");
+        assert!(orders.iter().all(|order| registry.check(order).is_ok()));
+
+        let duplicate = MarketRegistry::new(&[eth_usd(), eth_usd()]);
+        assert_eq!(
+            duplicate,
+            Err(MarketError::DuplicateSymbol("ETH/USD".to_string()))
+        );
+        assert_eq!(MarketRegistry::new(&[]), Err(MarketError::Empty));
+
+        let off_lot = MarketConfig {
+            min_size: Decimal::new(1, 5),
+            ..eth_usd()
+        };
+        assert!(matches!(
+            MarketRegistry::new(&[off_lot]),
+            Err(MarketError::Invalid { .. })
+        ));
+        let off_tick = MarketConfig {
+            reference_price: Decimal::new(300001, 2),
+            ..eth_usd()
+        };
+        assert!(matches!(
+            MarketRegistry::new(&[off_tick]),
+            Err(MarketError::Invalid { .. })
+        ));
+    }
+
+    #[test]
+    fn test_order_rules() {
+        let halted = MarketConfig {
+            status: MarketStatus::Halted,
+            ..eth_usd()
+        };
+        let registry = MarketRegistry::new(&[MarketConfig::sol_usd(), halted]).unwrap();
+        let sol = registry.get(MarketId::new(0)).unwrap();
+
+        let mut order = sol.random_order();
+        assert_eq!(registry.check(&order), Ok(()));
+
+        order.quantity = sol
+            .max_quantity
+            .checked_add(Quantity::from_lots(1))
+            .unwrap();
+        assert!(matches!(
+            registry.check(&order),
+            Err(OrderError::SizeOutOfRange { .. })
+        ));
+
+        order.quantity = sol.min_quantity;
+        order.price = Price::from_ticks(0);
+        assert_eq!(registry.check(&order), Err(OrderError::ZeroPrice));
+
+        // Order types
+        order.price = sol.reference_price;
+        let market = order
+            .clone()
+            .with_type(OrderType::Market, TimeInForce::ImmediateOrCancel);
+        assert_eq!(registry.check(&market), Err(OrderError::UnexpectedPrice));
+        let market = Order {
+            price: Price::from_ticks(0),
+            ..market
+        };
+        assert_eq!(registry.check(&market), Ok(()));
+        assert_eq!(
+            registry.check(
+                &market
+                    .clone()
+                    .with_type(OrderType::Market, TimeInForce::GoodTillCancel)
+            ),
+            Err(OrderError::InvalidTimeInForce(
+                OrderType::Market,
+                TimeInForce::GoodTillCancel
+            ))
+        );
+        let stop = market.with_type(
+            OrderType::Stop {
+                trigger: sol.reference_price,
+            },
+            TimeInForce::FillOrKill,
+        );
+        assert_eq!(registry.check(&stop), Ok(()));
+        let stop_limit = order.clone().with_type(
+            OrderType::StopLimit {
+                trigger: Price::from_ticks(0),
+            },
+            TimeInForce::GoodTillCancel,
+        );
+        assert_eq!(registry.check(&stop_limit), Err(OrderError::ZeroPrice));
+        let post_only = order
+            .clone()
+            .with_type(OrderType::Limit, TimeInForce::PostOnly);
+        assert_eq!(registry.check(&post_only), Ok(()));
+        assert!(
+            registry
+                .check(&post_only.with_type(
+                    OrderType::StopLimit {
+                        trigger: sol.reference_price
+                    },
+                    TimeInForce::PostOnly
+                ))
+                .is_err()
+        );
+
+        order.market = MarketId::new(1);
+        assert_eq!(
+            registry.check(&order),
+            Err(OrderError::Halted(MarketId::new(1)))
+        );
+        order.market = MarketId::new(2);
+        assert_eq!(
+            registry.check(&order),
+            Err(OrderError::UnknownMarket(MarketId::new(2)))
+        );
+
+        // Orders of a halted market can be cancelled but not amended
+        let cancel = |market| Action::Cancel {
+            market: MarketId::new(market),
+            order_id: order.id,
+        };
+        let amend = |market, quantity| Action::Amend {
+            market: MarketId::new(market),
+            order_id: order.id,
+            price: sol.reference_price,
+            quantity,
+        };
+        assert_eq!(registry.check_action(&cancel(1)), Ok(()));
+        assert_eq!(
+            registry.check_action(&cancel(2)),
+            Err(OrderError::UnknownMarket(MarketId::new(2)))
+        );
+        assert_eq!(registry.check_action(&amend(0, sol.min_quantity)), Ok(()));
+        assert_eq!(
+            registry.check_action(&amend(1, sol.min_quantity)),
+            Err(OrderError::Halted(MarketId::new(1)))
+        );
+        assert!(matches!(
+            registry.check_action(&amend(0, Quantity::from_lots(0))),
+            Err(OrderError::SizeOutOfRange { .. })
+        ));
+    }
+}
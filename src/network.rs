@@ -1,25 +1,88 @@
 use std::{
     collections::{HashMap, HashSet},
+    io,
+    net::{Ipv4Addr, SocketAddr},
+    sync::Arc,
     time::Duration,
 };
 
 use bincode::{Decode, Encode};
 use borsh::{BorshDeserialize, BorshSerialize};
 use rand::seq::IndexedRandom;
+use serde::{Deserialize, Serialize};
 use tokio::sync::mpsc;
 
 use crate::{
-    node,
-    packet::{PacketId, SerialiedPacket},
+    codec::{BincodeCodec, BorshCodec, Codec, CodecKind, FixedCodec, PostcardCodec},
+    event::NodeEvent,
+    node::{self, NodeSettings, NodeState},
+    packet::SerialiedPacket,
+    transport::{
+        Transport, TransportKind,
+        memory::MemoryTransport,
+        quic::QuicIdentity,
+        sim::{SimNetwork, SimTransport},
+        socket::{SocketBinding, SocketTransport},
+    },
 };
 
-#[derive(Debug, Eq, Hash, PartialEq, Clone, Encode, Decode, BorshDeserialize, BorshSerialize)]
+#[derive(
+    Debug,
+    Eq,
+    Hash,
+    PartialEq,
+    Clone,
+    Encode,
+    Decode,
+    BorshDeserialize,
+    BorshSerialize,
+    Deserialize,
+    Serialize,
+)]
 pub struct NodeId(u64);
 
 impl NodeId {
     pub fn new(id: u64) -> Self {
         Self(id)
     }
+
+    pub fn value(&self) -> u64 {
+        self.0
+    }
+}
+
+/// Handle on the nodes started by [`Network::run_network`]
+#[derive(Debug)]
+pub struct RunningNetwork {
+    /// Node receiving the packets sent on `start_sender`
+    pub start_node_id: NodeId,
+    pub start_sender: mpsc::Sender<SerialiedPacket>,
+    /// Inbox of every node, to inject packets from any of them
+    pub senders: HashMap<NodeId, mpsc::Sender<SerialiedPacket>>,
+    /// Counters and order books of each node, updated as they run
+    pub nodes: HashMap<NodeId, Arc<NodeState>>,
+}
+
+impl RunningNetwork {
+    /// Packets rejected by all the nodes so far
+    pub fn total_rejected(&self) -> u64 {
+        self.nodes
+            .values()
+            .map(|n| n.counters.total_rejected())
+            .sum()
+    }
+
+    /// Cancels that reached a node before their order, summed over all the nodes
+    pub fn total_buffered_cancels(&self) -> u64 {
+        self.nodes
+            .values()
+            .map(|n| n.counters.buffered_cancels())
+            .sum()
+    }
+
+    pub fn total_late_actions(&self) -> u64 {
+        self.nodes.values().map(|n| n.counters.late_actions()).sum()
+    }
 }
 
 #[derive(Debug)]
@@ -28,22 +91,56 @@ pub struct Network {
     neighbors: HashMap<NodeId, HashSet<NodeId>>,
 }
 
+/// Everything a node task needs but its codec
+struct NodeSpawn<T> {
+    node_id: NodeId,
+    neighbors: HashSet<NodeId>,
+    settings: NodeSettings,
+    transport: T,
+    state: Arc<NodeState>,
+    report_tx: mpsc::Sender<NodeEvent>,
+}
+
+impl<T: Transport> NodeSpawn<T> {
+    fn spawn<C: Codec>(self, codec: C) {
+        tokio::spawn(node::node_task(
+            self.node_id,
+            self.neighbors,
+            self.settings,
+            self.transport,
+            codec,
+            self.state,
+            self.report_tx,
+        ));
+    }
+}
+
 impl Network {
-    fn new(neighbors: HashMap<NodeId, HashSet<NodeId>>) -> Self {
+    pub(crate) fn new(neighbors: HashMap<NodeId, HashSet<NodeId>>) -> Self {
         Self { neighbors }
     }
 
-    fn nodes(&self) -> HashSet<NodeId> {
+    pub(crate) fn nodes(&self) -> HashSet<NodeId> {
         self.neighbors.keys().cloned().collect()
     }
 
-    fn neighbors(&self, node_id: NodeId) -> HashSet<NodeId> {
+    pub(crate) fn neighbors(&self, node_id: NodeId) -> HashSet<NodeId> {
         self.neighbors
             .get(&node_id)
             .cloned()
             .unwrap_or_else(HashSet::new)
     }
 
+    /// Number of nodes gossiping to each node, that is having it as a neighbor
+    pub fn in_degrees(&self) -> HashMap<NodeId, usize> {
+        let mut in_degrees: HashMap<NodeId, usize> =
+            self.neighbors.keys().map(|id| (id.clone(), 0)).collect();
+        for neighbor in self.neighbors.values().flatten() {
+            *in_degrees.entry(neighbor.clone()).or_default() += 1;
+        }
+        in_degrees
+    }
+
     pub fn generate_network(num_nodes: u64, num_neighbors: u64) -> Self {
         assert!(
             num_neighbors < num_nodes,
@@ -68,76 +165,876 @@ impl Network {
         Network::new(network)
     }
 
-    /// Starts each node task and returns one node_id and its sender to propagate messages to the network
-    pub fn run_network(
+    /// Starts each node task and returns one node_id and its sender to propagate messages to the
+    /// network, along with the senders of all the nodes. `bit_flip_probability` only applies to the simulated network.
+    pub async fn run_network(
         &self,
         latency: Duration,
-        num_peers: u64,
-        report_tx: &mpsc::Sender<PacketId>,
-    ) -> Option<(NodeId, mpsc::Sender<SerialiedPacket>)> {
-        let mut senders = HashMap::new();
+        settings: NodeSettings,
+        transport: TransportKind,
+        codec: CodecKind,
+        bit_flip_probability: f64,
+        report_tx: &mpsc::Sender<NodeEvent>,
+    ) -> io::Result<Option<RunningNetwork>> {
+        let mut inboxes = HashMap::new();
         let mut receivers = HashMap::new();
 
         for id in self.nodes() {
             // Create a channel for each node. 32 is the buffer size.
             let (tx, rx) = mpsc::channel::<SerialiedPacket>(32); // queue of 32
-            senders.insert(id.clone(), tx);
+            inboxes.insert(id.clone(), tx);
             receivers.insert(id.clone(), rx);
         }
 
-        let (start_node_id, start_sender) = senders.iter().next()?;
+        let Some((start_node_id, start_sender)) = inboxes.iter().next() else {
+            return Ok(None);
+        };
+        let start_node_id = start_node_id.clone();
+        let start_sender = start_sender.clone();
+
+        let senders = inboxes.clone();
+
+        let nodes = match transport {
+            TransportKind::Mpsc => {
+                let transports = self.memory_transports(latency, &inboxes, receivers);
+                self.spawn_nodes(transports, settings, codec, report_tx)
+            }
+            TransportKind::Sim => {
+                let transports =
+                    self.sim_transports(latency, bit_flip_probability, inboxes, receivers);
+                self.spawn_nodes(transports, settings, codec, report_tx)
+            }
+            TransportKind::Udp
+            | TransportKind::Tcp
+            | TransportKind::QuicStream
+            | TransportKind::QuicDatagram => {
+                let transports = self
+                    .socket_transports(transport, latency, &inboxes, receivers)
+                    .await?;
+                self.spawn_nodes(transports, settings, codec, report_tx)
+            }
+        };
+
+        Ok(Some(RunningNetwork {
+            start_node_id,
+            start_sender,
+            senders,
+            nodes,
+        }))
+    }
+
+    /// Spawns a task for each node, running on its own transport, and returns their states
+    fn spawn_nodes<T: Transport>(
+        &self,
+        mut transports: HashMap<NodeId, T>,
+        settings: NodeSettings,
+        codec: CodecKind,
+        report_tx: &mpsc::Sender<NodeEvent>,
+    ) -> HashMap<NodeId, Arc<NodeState>> {
+        let mut states = HashMap::new();
 
-        // Spawn a task for each node.
         for node_id in self.nodes() {
-            let receiver = receivers.remove(&node_id).unwrap();
-            let all_senders_clone = senders.clone();
+            let transport = transports.remove(&node_id).unwrap();
+            let neighbors = self.neighbors(node_id.clone());
+            let report_tx = report_tx.clone();
+            let state = Arc::new(NodeState::new(settings.markets.clone()));
+            let settings = settings.clone();
+            states.insert(node_id.clone(), state.clone());
+
+            // Nodes are generic over the codec so that encoding is statically dispatched
+            let spawn = NodeSpawn {
+                node_id,
+                neighbors,
+                settings,
+                transport,
+                state,
+                report_tx,
+            };
+            match codec {
+                CodecKind::Borsh => spawn.spawn(BorshCodec),
+                CodecKind::Bincode => spawn.spawn(BincodeCodec::default()),
+                CodecKind::Postcard => spawn.spawn(PostcardCodec),
+                CodecKind::Fixed => spawn.spawn(FixedCodec),
+            }
+        }
+
+        states
+    }
+
+    /// Every node writes directly into its neighbors' inboxes
+    fn memory_transports(
+        &self,
+        latency: Duration,
+        inboxes: &HashMap<NodeId, mpsc::Sender<SerialiedPacket>>,
+        mut receivers: HashMap<NodeId, mpsc::Receiver<SerialiedPacket>>,
+    ) -> HashMap<NodeId, MemoryTransport> {
+        self.neighbors
+            .iter()
+            .map(|(node_id, neighbors)| {
+                let peers = neighbors
+                    .iter()
+                    .map(|n| (n.clone(), inboxes[n].clone()))
+                    .collect();
+                let receiver = receivers.remove(node_id).unwrap();
+                let transport = MemoryTransport::new(node_id.clone(), latency, receiver, peers);
+                (node_id.clone(), transport)
+            })
+            .collect()
+    }
+
+    /// Every node hands its packets to a single scheduler, which delivers them after the latency
+    fn sim_transports(
+        &self,
+        latency: Duration,
+        bit_flip_probability: f64,
+        inboxes: HashMap<NodeId, mpsc::Sender<SerialiedPacket>>,
+        mut receivers: HashMap<NodeId, mpsc::Receiver<SerialiedPacket>>,
+    ) -> HashMap<NodeId, SimTransport> {
+        let sim_network = SimNetwork::start(latency, bit_flip_probability, inboxes);
 
-            tokio::spawn(node::node_task(
-                node_id.clone(),
-                latency,
-                self.neighbors(node_id),
-                num_peers,
-                receiver,
-                all_senders_clone,
-                report_tx.clone(),
-            ));
+        self.neighbors
+            .iter()
+            .map(|(node_id, neighbors)| {
+                let receiver = receivers.remove(node_id).unwrap();
+                let transport = SimTransport::new(
+                    node_id.clone(),
+                    neighbors.clone(),
+                    receiver,
+                    sim_network.clone(),
+                );
+                (node_id.clone(), transport)
+            })
+            .collect()
+    }
+
+    /// Binds one socket per node on an ephemeral loopback port, then connects each node to its
+    /// neighbors. With TCP and QUIC, the connection to a neighbor is only opened the first time a
+    /// node gossips to it.
+    async fn socket_transports(
+        &self,
+        kind: TransportKind,
+        latency: Duration,
+        inboxes: &HashMap<NodeId, mpsc::Sender<SerialiedPacket>>,
+        mut receivers: HashMap<NodeId, mpsc::Receiver<SerialiedPacket>>,
+    ) -> io::Result<HashMap<NodeId, SocketTransport>> {
+        // Every node shares the same self-signed certificate, generated here
+        let identity = match kind {
+            TransportKind::QuicStream | TransportKind::QuicDatagram => {
+                Some(QuicIdentity::generate()?)
+            }
+            _ => None,
+        };
+
+        let mut bindings = HashMap::new();
+        for (node_id, inbox) in inboxes {
+            let addr = SocketAddr::from((Ipv4Addr::LOCALHOST, 0));
+            let binding = SocketBinding::bind(kind, addr, identity.as_ref(), inbox.clone()).await?;
+            bindings.insert(node_id.clone(), binding);
         }
 
-        Some((start_node_id.clone(), start_sender.clone()))
+        let addrs: HashMap<NodeId, SocketAddr> = bindings
+            .iter()
+            .map(|(node_id, binding)| (node_id.clone(), binding.local_addr()))
+            .collect();
+
+        Ok(bindings
+            .into_iter()
+            .map(|(node_id, binding)| {
+                let peers = self
+                    .neighbors(node_id.clone())
+                    .into_iter()
+                    .map(|n| {
+                        let addr = addrs[&n];
+                        (n, addr)
+                    })
+                    .collect();
+                let receiver = receivers.remove(&node_id).unwrap();
+                (node_id, binding.into_transport(latency, receiver, peers))
+            })
+            .collect())
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::packet::GossipPacket;
+    use bytes::{BufMut, BytesMut};
+
+    use crate::{
+        book::Level,
+        codec::Codec,
+        committee::{BATCH_ID_BASE, Batch, Committee, CommitteeSettings, Entry},
+        convergence::{MarketSnapshot, Snapshot, check_convergence},
+        envelope::{Envelope, MessageType},
+        event::{EventKind, EventStats},
+        fixed_point::{Price, Quantity},
+        hlc::{self, Timestamp},
+        market::{MarketConfig, MarketRegistry, MarketStatus},
+        node::Rejection,
+        order::{Action, MarketId, Order, Side},
+        packet::{GossipPacket, PacketId},
+        sequencer::Sequencing,
+        signing::{self, Verification},
+        transport::udp,
+    };
 
     use super::*;
 
-    #[tokio::test]
-    /// Start a simple network with 3 nodes and check a message can be propagated to the network
-    async fn test_network() {
-        let network = Network::new(HashMap::from([
+    /// Room for every event of the tests, so that nodes never wait for them to be read
+    const REPORT_CHANNEL_SIZE: usize = 256;
+
+    /// Latency of the links of the simulated networks
+    const SIM_LATENCY: Duration = Duration::from_millis(50);
+
+    /// Each node gossips to a single peer, without verifying signatures, packets being sent with a
+    /// TTL of 3
+    fn single_peer() -> NodeSettings {
+        NodeSettings {
+            num_peers: 1,
+            time_to_live: 3,
+            ..Default::default()
+        }
+    }
+
+    /// Next delivery, skipping the other events
+    async fn next_delivery(report_rx: &mut mpsc::Receiver<NodeEvent>) -> Option<NodeEvent> {
+        while let Some(event) = report_rx.recv().await {
+            if event.delivered().is_some() {
+                return Some(event);
+            }
+        }
+        None
+    }
+
+    /// Events already reported
+    fn reported(report_rx: &mut mpsc::Receiver<NodeEvent>) -> Vec<NodeEvent> {
+        std::iter::from_fn(|| report_rx.try_recv().ok()).collect()
+    }
+
+    fn num_deliveries(events: &[NodeEvent]) -> usize {
+        events.iter().filter(|e| e.delivered().is_some()).count()
+    }
+
+    fn stats(events: &[NodeEvent]) -> EventStats {
+        let mut stats = EventStats::default();
+        for event in events {
+            stats.record(event);
+        }
+        stats
+    }
+
+    /// Simple network with 3 nodes in a ring
+    fn ring_network() -> Network {
+        Network::new(HashMap::from([
             (NodeId(0), HashSet::from([NodeId(1)])),
             (NodeId(1), HashSet::from([NodeId(2)])),
             (NodeId(2), HashSet::from([NodeId(0)])),
-        ]));
+        ]))
+    }
 
-        let (report_tx, mut report_rx) = mpsc::channel::<PacketId>(3);
+    /// Runs `ring_network` on the simulated transport, and returns the events of its nodes
+    async fn sim_ring(settings: NodeSettings) -> (RunningNetwork, mpsc::Receiver<NodeEvent>) {
+        corrupted_sim_ring(settings, 0.0).await
+    }
+
+    /// Same as `sim_ring`, the links flipping each bit of the packets with `bit_flip_probability`
+    async fn corrupted_sim_ring(
+        settings: NodeSettings,
+        bit_flip_probability: f64,
+    ) -> (RunningNetwork, mpsc::Receiver<NodeEvent>) {
+        let (report_tx, report_rx) = mpsc::channel::<NodeEvent>(REPORT_CHANNEL_SIZE);
+        let running = ring_network()
+            .run_network(
+                SIM_LATENCY,
+                settings,
+                TransportKind::Sim,
+                CodecKind::Borsh,
+                bit_flip_probability,
+                &report_tx,
+            )
+            .await
+            .unwrap()
+            .unwrap();
+        (running, report_rx)
+    }
+
+    /// Sends each action on `sender`, in a packet from `source_id`. Packet ids count from 0.
+    async fn inject(
+        sender: &mpsc::Sender<SerialiedPacket>,
+        source_id: &NodeId,
+        actions: impl IntoIterator<Item = Action>,
+    ) {
+        let stamped = actions
+            .into_iter()
+            .map(|action| (action, Timestamp::default()));
+        inject_stamped(sender, source_id, stamped).await;
+    }
+
+    /// Same as `inject`, with the timestamp of each action
+    async fn inject_stamped(
+        sender: &mpsc::Sender<SerialiedPacket>,
+        source_id: &NodeId,
+        actions: impl IntoIterator<Item = (Action, Timestamp)>,
+    ) {
+        for (i, (action, timestamp)) in actions.into_iter().enumerate() {
+            let packet = GossipPacket::new(PacketId::new(i as u64), source_id.clone(), 3, action)
+                .with_timestamp(timestamp);
+            sender.send(BorshCodec.encode(&packet)).await.unwrap();
+        }
+    }
+
+    /// Run the network and check a message can be propagated to all of its nodes
+    async fn check_propagation(network: Network, transport: TransportKind) {
+        let num_nodes = network.nodes().len();
+        let (report_tx, mut report_rx) = mpsc::channel::<NodeEvent>(REPORT_CHANNEL_SIZE);
 
         // Run network and send start packet
-        let (start_id, start_sender) = network.run_network(Duration::ZERO, 1, &report_tx).unwrap();
-        let packet = GossipPacket::new_with_random_order(PacketId::new(1), start_id, 3);
-        let _ = start_sender.send(packet.borsh_serialize()).await;
+        let running = network
+            .run_network(
+                Duration::ZERO,
+                single_peer(),
+                transport,
+                CodecKind::Borsh,
+                0.0,
+                &report_tx,
+            )
+            .await
+            .unwrap()
+            .unwrap();
+        let packet =
+            GossipPacket::new_with_random_order(PacketId::new(1), running.start_node_id, 3);
+        let _ = running.start_sender.send(BorshCodec.encode(&packet)).await;
 
         // Wait for packet to be propagated
         let mut received_count = 0;
-        loop {
-            if let Some(_) = report_rx.recv().await {
-                received_count += 1;
-                if received_count == 3 {
-                    break;
+        while next_delivery(&mut report_rx).await.is_some() {
+            received_count += 1;
+            if received_count == num_nodes {
+                break;
+            }
+        }
+    }
+
+    #[tokio::test]
+    /// Start a simple network with 3 nodes and check a message can be propagated to the network
+    async fn test_network() {
+        check_propagation(ring_network(), TransportKind::Mpsc).await;
+    }
+
+    #[tokio::test(start_paused = true)]
+    /// Same as `test_network` on the simulated network, where only the links' latency is measured
+    async fn test_network_sim() {
+        let (running, mut report_rx) = sim_ring(single_peer()).await;
+        let now = tokio::time::Instant::now();
+        let start = hlc::since_epoch();
+        let order = MarketRegistry::default().random_order();
+        inject(
+            &running.start_sender,
+            &running.start_node_id,
+            [Action::Place(order)],
+        )
+        .await;
+
+        // Each node is one more hop away on the ring, and received the packet from the previous one
+        let mut from = running.start_node_id.clone();
+        for hop in 0..3 {
+            let event = next_delivery(&mut report_rx).await.unwrap();
+            assert_eq!(now.elapsed(), SIM_LATENCY * hop);
+            assert_eq!(event.time(), start + SIM_LATENCY * hop);
+            assert_eq!(
+                event.kind,
+                EventKind::Delivered {
+                    packet_id: PacketId::new(0),
+                    from: from.clone(),
+                    hop: hop as u64,
                 }
+            );
+            from = event.node;
+        }
+
+        // The start node gets the packet back from the last one, whose TTL isn't reached yet
+        tokio::time::sleep(SIM_LATENCY * 2).await;
+        let events = reported(&mut report_rx);
+        assert_eq!(
+            events.last().unwrap(),
+            &NodeEvent {
+                node: running.start_node_id.clone(),
+                time_ns: (start + SIM_LATENCY * 3).as_nanos() as u64,
+                kind: EventKind::Duplicate {
+                    packet_id: PacketId::new(0),
+                    from,
+                },
             }
+        );
+        let forwards = events
+            .iter()
+            .filter(|e| matches!(e.kind, EventKind::Forwarded { .. }));
+        assert_eq!(forwards.count(), 1);
+    }
+
+    #[tokio::test(start_paused = true)]
+    /// Every link corrupts every packet: nodes must reject them and keep running
+    async fn test_network_sim_corruption() {
+        let (running, mut report_rx) = corrupted_sim_ring(single_peer(), 1.0).await;
+        let markets = MarketRegistry::default();
+        let orders = (0..10).map(|_| Action::Place(markets.random_order()));
+        inject(&running.start_sender, &running.start_node_id, orders).await;
+
+        // Only the start node, which isn't reached through a link, sees each packet
+        for i in 0..10 {
+            let delivery = next_delivery(&mut report_rx).await.unwrap();
+            assert_eq!(delivery.node, running.start_node_id);
+            assert_eq!(delivery.delivered(), Some(PacketId::new(i)));
+        }
+
+        tokio::time::sleep(SIM_LATENCY * 2).await;
+        let events = reported(&mut report_rx);
+        assert_eq!(num_deliveries(&events), 0);
+        // Neither the id nor the sender of a corrupted packet can be trusted
+        assert!(events.iter().any(|e| matches!(
+            e.kind,
+            EventKind::Dropped {
+                packet_id: None,
+                from: None,
+                reason: Rejection::BadChecksum | Rejection::Malformed,
+            }
+        )));
+        assert_eq!(stats(&events).dropped, 10);
+        assert_eq!(running.total_rejected(), 10);
+    }
+
+    #[tokio::test(start_paused = true)]
+    /// Valid messages of a type nodes don't take part in are dropped and reported
+    async fn test_network_not_gossiped() {
+        let (running, mut report_rx) = sim_ring(single_peer()).await;
+        let mut digest = BytesMut::new();
+        Envelope::new(MessageType::PullDigest, 4).write(&mut digest);
+        digest.put_u32_le(7);
+        Envelope::seal(&mut digest);
+        let packet = SerialiedPacket::from(digest.to_vec());
+        running.start_sender.send(packet).await.unwrap();
+
+        tokio::time::sleep(SIM_LATENCY).await;
+        let events = reported(&mut report_rx);
+        assert_eq!(
+            events,
+            [NodeEvent {
+                node: running.start_node_id.clone(),
+                time_ns: events[0].time_ns,
+                kind: EventKind::Dropped {
+                    packet_id: None,
+                    from: None,
+                    reason: Rejection::NotGossiped,
+                },
+            }]
+        );
+        let start_counters = &running.nodes[&running.start_node_id].counters;
+        assert_eq!(start_counters.rejected(Rejection::NotGossiped), 1);
+        assert_eq!(running.total_rejected(), 1);
+    }
+
+    #[tokio::test(start_paused = true)]
+    /// Signed orders reach every node, a tampered one is rejected by the first node verifying it
+    async fn test_network_signed() {
+        let (running, mut report_rx) = sim_ring(NodeSettings {
+            verification: Verification::All,
+            ..single_peer()
+        })
+        .await;
+        let key = signing::generate_trader_key();
+
+        let packet =
+            GossipPacket::new_with_random_order(PacketId::new(1), running.start_node_id.clone(), 3);
+        let signed = BorshCodec.encode_signed(&packet, &key);
+        running.start_sender.send(signed).await.unwrap();
+        for _ in 0..3 {
+            let delivery = next_delivery(&mut report_rx).await.unwrap();
+            assert_eq!(delivery.delivered(), Some(PacketId::new(1)));
+        }
+
+        // Order modified after being signed, the checksum is fixed up by forwarding
+        let packet =
+            GossipPacket::new_with_random_order(PacketId::new(2), running.start_node_id.clone(), 3);
+        let mut bytes = BorshCodec.encode_signed(&packet, &key).as_bytes().to_vec();
+        *bytes.last_mut().unwrap() ^= 1;
+        let tampered = SerialiedPacket::from(bytes).forwarded(&running.start_node_id, 3);
+        running.start_sender.send(tampered).await.unwrap();
+
+        // Unsigned orders aren't accepted either
+        let unsigned = MarketRegistry::default().random_order();
+        inject(
+            &running.start_sender,
+            &running.start_node_id,
+            [Action::Place(unsigned)],
+        )
+        .await;
+
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        let events = reported(&mut report_rx);
+        assert_eq!(num_deliveries(&events), 0);
+        let stats = stats(&events);
+        assert_eq!(stats.dropped, 2);
+        assert_eq!(stats.drops(Rejection::BadSignature), 1);
+        assert_eq!(stats.drops(Rejection::Unsigned), 1);
+        let start_counters = &running.nodes[&running.start_node_id].counters;
+        assert_eq!(start_counters.rejected(Rejection::BadSignature), 1);
+        assert_eq!(start_counters.rejected(Rejection::Unsigned), 1);
+        assert_eq!(running.total_rejected(), 2);
+    }
+
+    #[tokio::test(start_paused = true)]
+    /// Orders breaking their market's rules are rejected by the first node and not forwarded
+    async fn test_network_invalid_order() {
+        let halted = MarketConfig {
+            symbol: "HALTED/USD".to_string(),
+            status: MarketStatus::Halted,
+            ..MarketConfig::sol_usd()
+        };
+        let markets = MarketRegistry::new(&[MarketConfig::sol_usd(), halted]).unwrap();
+        let (running, mut report_rx) = sim_ring(NodeSettings {
+            num_peers: 1,
+            markets: Arc::new(markets.clone()),
+            ..Default::default()
+        })
+        .await;
+
+        let sol = markets.get(MarketId::new(0)).unwrap();
+        let mut too_large = sol.random_order();
+        too_large.quantity = sol.max_quantity.checked_mul(2).unwrap();
+        let mut halted = sol.random_order();
+        halted.market = MarketId::new(1);
+        let orders = [too_large, halted].map(Action::Place);
+        inject(&running.start_sender, &running.start_node_id, orders).await;
+
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        let events = reported(&mut report_rx);
+        assert_eq!(events.len(), 2);
+        for (i, event) in events.iter().enumerate() {
+            assert_eq!(event.node, running.start_node_id);
+            assert_eq!(
+                event.kind,
+                EventKind::Dropped {
+                    packet_id: Some(PacketId::new(i as u64)),
+                    from: Some(running.start_node_id.clone()),
+                    reason: Rejection::InvalidOrder,
+                }
+            );
+        }
+        let start_counters = &running.nodes[&running.start_node_id].counters;
+        assert_eq!(start_counters.rejected(Rejection::InvalidOrder), 2);
+        assert_eq!(running.total_rejected(), 2);
+    }
+
+    #[tokio::test(start_paused = true)]
+    /// A cancel overtaking its order is buffered by every node and applied once the order arrives
+    async fn test_network_cancel_race() {
+        let (running, mut report_rx) = sim_ring(single_peer()).await;
+        let order = MarketRegistry::default().random_order();
+        let cancel = Action::Cancel {
+            market: order.market,
+            order_id: order.id,
+        };
+        inject(
+            &running.start_sender,
+            &running.start_node_id,
+            [cancel, Action::Place(order)],
+        )
+        .await;
+
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        let events = reported(&mut report_rx);
+        assert_eq!(num_deliveries(&events), 6);
+        assert_eq!(running.total_buffered_cancels(), 3);
+        assert_eq!(stats(&events).dropped, 0);
+        assert_eq!(running.total_rejected(), 0);
+    }
+
+    #[tokio::test(start_paused = true)]
+    /// Every node matches the orders in its own book
+    async fn test_network_matching() {
+        let (running, _report_rx) = sim_ring(single_peer()).await;
+        let market = MarketId::new(0);
+        let price = Price::from_ticks(15_000);
+        let ask = Order::new(1, market, Side::Ask, price, Quantity::from_lots(3));
+        let bid = Order::new(2, market, Side::Bid, price, Quantity::from_lots(1));
+        let orders = [ask, bid].map(Action::Place);
+        inject(&running.start_sender, &running.start_node_id, orders).await;
+
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        for node in running.nodes.values() {
+            assert_eq!(node.num_fills(), 1);
+            let top = node.top_of_book(market).unwrap();
+            assert_eq!(top.best_bid, None);
+            assert_eq!(
+                top.best_ask,
+                Some(Level {
+                    price,
+                    quantity: Quantity::from_lots(2)
+                })
+            );
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    /// Books differ while orders propagate, and converge once every node received them
+    async fn test_network_convergence() {
+        let (running, _report_rx) = sim_ring(single_peer()).await;
+        let market = MarketId::new(0);
+        let ask = Order::new(
+            1,
+            market,
+            Side::Ask,
+            Price::from_ticks(15_100),
+            Quantity::from_lots(1),
+        );
+        let bid = Order::new(
+            2,
+            market,
+            Side::Bid,
+            Price::from_ticks(14_900),
+            Quantity::from_lots(1),
+        );
+        let orders = [ask, bid].map(Action::Place);
+        inject(&running.start_sender, &running.start_node_id, orders).await;
+        tokio::time::sleep(Duration::from_millis(1)).await;
+
+        // Only the first node has the orders
+        let nodes: Vec<&NodeState> = running.nodes.values().map(Arc::as_ref).collect();
+        let markets = MarketRegistry::default();
+        let convergence = check_convergence(
+            &nodes,
+            &markets,
+            Duration::from_millis(10),
+            Duration::from_secs(1),
+        )
+        .await;
+        assert_eq!(
+            convergence.initial,
+            Snapshot {
+                num_nodes: 3,
+                distinct_states: 2,
+                largest_group: 2,
+                markets: vec![MarketSnapshot {
+                    market,
+                    distinct_tops: 2,
+                    spread_range: Some((Price::from_ticks(200), Price::from_ticks(200))),
+                }],
+            }
+        );
+        assert_eq!(convergence.time, Some(Duration::from_millis(100)));
+        assert!(convergence.last.converged());
+        assert_eq!(convergence.last.markets[0].distinct_tops, 1);
+    }
+
+    #[tokio::test(start_paused = true)]
+    /// Orders are matched in the sequence of their timestamps once settled, whatever the sequence
+    /// they were received in
+    async fn test_network_sequencing() {
+        let (running, mut report_rx) = sim_ring(NodeSettings {
+            sequencing: Sequencing::Timestamp {
+                settlement_delay: Duration::from_millis(200),
+            },
+            ..single_peer()
+        })
+        .await;
+        let market = MarketId::new(0);
+        let price = Price::from_ticks(15_000);
+        let ask = Order::new(1, market, Side::Ask, price, Quantity::from_lots(1));
+        let bid = Order::new(2, market, Side::Bid, price, Quantity::from_lots(1));
+        let now = hlc::physical_now();
+        // The bid is sent first, but stamped after the ask
+        let orders = [
+            (Action::Place(bid), Timestamp::new(now, 1)),
+            (Action::Place(ask), Timestamp::new(now, 0)),
+        ];
+        inject_stamped(&running.start_sender, &running.start_node_id, orders).await;
+
+        // Every node received both orders, none is settled yet
+        tokio::time::sleep(Duration::from_millis(150)).await;
+        for node in running.nodes.values() {
+            assert_eq!(node.num_fills(), 0);
+        }
+        assert_eq!(num_deliveries(&reported(&mut report_rx)), 0);
+
+        // The ask rests first, the bid takes it. Orders are delivered once applied, the settlement
+        // delay after their timestamp.
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        let deliveries: Vec<_> = reported(&mut report_rx)
+            .into_iter()
+            .filter(|e| e.delivered().is_some())
+            .collect();
+        assert_eq!(deliveries.len(), 6);
+        for delivery in &deliveries {
+            assert!(delivery.time() >= Duration::from_millis(now + 200));
+        }
+        for node in running.nodes.values() {
+            let fills = node.fills();
+            assert_eq!(fills.len(), 1);
+            assert_eq!(fills[0].maker_order_id, 1);
+            assert_eq!(fills[0].taker_order_id, 2);
+        }
+        assert_eq!(running.total_late_actions(), 0);
+    }
+
+    #[tokio::test(start_paused = true)]
+    /// A node stamps the actions it injects after every timestamp it received, even one from a
+    /// clock running ahead of its own
+    async fn test_network_clock_merge() {
+        let (running, mut report_rx) = sim_ring(single_peer()).await;
+        let ahead = Timestamp::new(hlc::physical_now() + 60_000, 7);
+        let order = Order::new(
+            1,
+            MarketId::new(0),
+            Side::Bid,
+            Price::from_ticks(15_000),
+            Quantity::from_lots(1),
+        );
+        inject_stamped(
+            &running.start_sender,
+            &running.start_node_id,
+            [(Action::Place(order), ahead)],
+        )
+        .await;
+
+        tokio::time::sleep(SIM_LATENCY * 3).await;
+        assert_eq!(num_deliveries(&reported(&mut report_rx)), 3);
+        for node in running.nodes.values() {
+            assert!(node.stamp() > ahead);
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    /// Orders are put in sequence by the committee, then gossiped and applied in that sequence
+    async fn test_network_committee() {
+        let (running, mut report_rx) = sim_ring(single_peer()).await;
+        let settings = CommitteeSettings {
+            members: vec![running.start_node_id.clone(), NodeId(1), NodeId(2)],
+            latency: SIM_LATENCY,
+            verification: Verification::None,
+            ttl: 3,
+            max_packet_size: None,
+        };
+        let committee = Committee::start(settings, BorshCodec, running.start_sender.clone());
+
+        let market = MarketId::new(0);
+        let price = Price::from_ticks(15_000);
+        let ask = Order::new(1, market, Side::Ask, price, Quantity::from_lots(1));
+        let bid = Order::new(2, market, Side::Bid, price, Quantity::from_lots(1));
+        let orders = [ask, bid].map(Action::Place);
+        inject(&committee.submit, &running.start_node_id, orders).await;
+
+        // Committed once a follower acknowledged them, then gossiped around the ring
+        tokio::time::sleep(Duration::from_millis(250)).await;
+        assert_eq!(
+            committee.sequencing_times(),
+            [Duration::from_millis(100), Duration::from_millis(100)]
+        );
+        assert_eq!(num_deliveries(&reported(&mut report_rx)), 6);
+        for node in running.nodes.values() {
+            let fills = node.fills();
+            assert_eq!(fills.len(), 1);
+            assert_eq!(fills[0].maker_order_id, 1);
+            assert_eq!(fills[0].taker_order_id, 2);
+        }
+
+        // From any other node, an order first takes the hop to the leader
+        let origin = running
+            .nodes
+            .keys()
+            .find(|id| **id != running.start_node_id)
+            .unwrap();
+        let order = Order::new(3, market, Side::Bid, price, Quantity::from_lots(1));
+        inject(&committee.submit, origin, [Action::Place(order)]).await;
+        tokio::time::sleep(SIM_LATENCY + Duration::from_millis(90)).await;
+        assert_eq!(committee.sequencing_times().len(), 2);
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        assert_eq!(committee.sequencing_times().len(), 3);
+    }
+
+    #[tokio::test]
+    /// Same as `test_network` but packets travel as datagrams over loopback
+    async fn test_network_udp() {
+        check_propagation(ring_network(), TransportKind::Udp).await;
+    }
+
+    #[tokio::test]
+    /// Same as `test_network` but packets travel as frames over loopback TCP connections
+    async fn test_network_tcp() {
+        check_propagation(ring_network(), TransportKind::Tcp).await;
+    }
+
+    #[tokio::test]
+    /// Same as `test_network` but packets travel over QUIC, on streams and as datagrams
+    async fn test_network_quic() {
+        check_propagation(ring_network(), TransportKind::QuicStream).await;
+        check_propagation(ring_network(), TransportKind::QuicDatagram).await;
+    }
+
+    #[tokio::test]
+    /// A packet too large for a datagram is dropped and reported, and the node keeps gossiping
+    async fn test_network_udp_too_large() {
+        let (report_tx, mut report_rx) = mpsc::channel::<NodeEvent>(REPORT_CHANNEL_SIZE);
+        let running = ring_network()
+            .run_network(
+                Duration::ZERO,
+                single_peer(),
+                TransportKind::Udp,
+                CodecKind::Borsh,
+                0.0,
+                &report_tx,
+            )
+            .await
+            .unwrap()
+            .unwrap();
+
+        let markets = MarketRegistry::default();
+        let batch = Batch {
+            first_index: 0,
+            entries: (0..40)
+                .map(|i| Entry {
+                    packet_id: PacketId::new(i),
+                    timestamp: Timestamp::new(1_000, 0),
+                    action: Action::Place(markets.random_order()),
+                })
+                .collect(),
+        };
+        let bytes = batch.encode(&BorshCodec);
+        assert!(bytes.len() > udp::MAX_DATAGRAM_SIZE);
+        let packet = SerialiedPacket::sequenced_batch(
+            PacketId::new(BATCH_ID_BASE),
+            &running.start_node_id,
+            3,
+            &bytes,
+        );
+        running.start_sender.send(packet).await.unwrap();
+        let dropped = loop {
+            let event = report_rx.recv().await.unwrap();
+            if let EventKind::Dropped { .. } = event.kind {
+                break event;
+            }
+        };
+        assert_eq!(dropped.node, running.start_node_id);
+        assert_eq!(
+            dropped.kind,
+            EventKind::Dropped {
+                packet_id: Some(PacketId::new(BATCH_ID_BASE)),
+                from: None,
+                reason: Rejection::TooLarge,
+            }
+        );
+        let start_counters = &running.nodes[&running.start_node_id].counters;
+        assert_eq!(start_counters.rejected(Rejection::TooLarge), 1);
+
+        // Orders still go around
+        let packet =
+            GossipPacket::new_with_random_order(PacketId::new(100), running.start_node_id, 3);
+        running
+            .start_sender
+            .send(BorshCodec.encode(&packet))
+            .await
+            .unwrap();
+        for _ in 0..3 {
+            let delivery = next_delivery(&mut report_rx).await.unwrap();
+            assert_eq!(delivery.delivered(), Some(PacketId::new(100)));
         }
     }
 }
@@ -1,85 +1,359 @@
 use std::{
     collections::{HashMap, HashSet},
-    process::exit,
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicU64, Ordering},
+    },
     time::Duration,
 };
 
+use borsh::{BorshDeserialize, BorshSerialize};
 use tokio::sync::mpsc;
 
 use crate::{
+    book::{Depth, Fill, OrderBook, TopOfBook},
+    codec::{Codec, DecodeError},
+    committee::{Batch, SequencedLog},
+    envelope::{EnvelopeError, MessageType},
+    event::{EventKind, NodeEvent},
+    hlc::{self, HybridClock, Timestamp},
+    market::MarketRegistry,
     network::NodeId,
-    order::Order,
-    packet::{GossipPacket, PacketId, SerialiedPacket},
+    order::{Action, MarketId},
+    order_state::{OrderState, Outcome},
+    packet::{PacketId, SerialiedPacket},
+    sequencer::{Sequencer, Sequencing},
+    signing::{SignatureError, Verification},
+    transport::{SendFailure, Transport, TransportError},
 };
 
 use rand::seq::IndexedRandom;
 
+/// Behavior shared by every node of the network
+#[derive(Debug, Clone, Default)]
+pub struct NodeSettings {
+    /// Number of neighbors each packet is gossiped to
+    pub num_peers: u64,
+    /// Signature check done before processing and forwarding a packet
+    pub verification: Verification,
+    /// Markets the orders are checked against
+    pub markets: Arc<MarketRegistry>,
+    /// Sequence in which the actions are applied to the books
+    pub sequencing: Sequencing,
+    /// TTL of the packets injected, to tell how many hops away from their origin they are
+    pub time_to_live: u64,
+}
+
 /// Node's async task. It listens for incoming messages and gossips them to its neighbors.
-pub async fn node_task(
+pub async fn node_task<T: Transport, C: Codec>(
     node_id: NodeId,
-    latency: Duration,
     neighbors: HashSet<NodeId>,
-    num_peers: u64,
-    mut receiver: mpsc::Receiver<SerialiedPacket>,
-    all_senders: HashMap<NodeId, mpsc::Sender<SerialiedPacket>>,
-    report_sender: mpsc::Sender<PacketId>,
+    settings: NodeSettings,
+    mut transport: T,
+    codec: C,
+    state: Arc<NodeState>,
+    report_sender: mpsc::Sender<NodeEvent>,
 ) {
     // A set to keep track of messages this node has already seen and gossiped.
     // This is crucial to prevent infinite message loops in the network (e.g., A->B->A).
     let mut seen_messages = HashSet::new();
 
-    let mut orders: Vec<Order> = Vec::new();
+    let counters = &state.counters;
+
+    let mut sequencer = match settings.sequencing {
+        Sequencing::Arrival => None,
+        Sequencing::Timestamp { settlement_delay } => Some(Sequencer::new(settlement_delay)),
+    };
+    // Sender and hop of the actions waiting in the sequencer, delivered once applied
+    let mut settling = HashMap::new();
+    let mut sequenced_log = SequencedLog::default();
+    let mut send_failures = transport.take_failures();
 
-    // Loop indefinitely, waiting for messages on the receiver channel.
-    while let Some(serialized_packet) = receiver.recv().await {
-        let packet = serialized_packet.borsh_deserialize();
-        let is_new_message = seen_messages.insert(packet.id);
+    // Loop indefinitely, waiting for messages from the transport.
+    loop {
+        let settlement = sequencer.as_ref().and_then(Sequencer::next_settlement);
+        let serialized_packet = tokio::select! {
+            packet = transport.recv() => match packet {
+                Some(packet) => packet,
+                None => break,
+            },
+            () = sleep_until_settlement(settlement) => {
+                let sequencer = sequencer.as_mut().expect("Settling without a sequencer");
+                for (packet_id, action) in sequencer.release(hlc::physical_now()) {
+                    state.apply(action);
+                    if let Some((from, hop)) = settling.remove(&packet_id) {
+                        let delivered = EventKind::Delivered { packet_id, from, hop };
+                        report(&report_sender, &node_id, delivered).await;
+                    }
+                }
+                continue;
+            }
+            Some(failure) = next_failure(&mut send_failures) => {
+                let SendFailure { packet_id, error, .. } = failure;
+                drop_unsent(counters, &report_sender, &node_id, packet_id, &error).await;
+                continue;
+            }
+        };
+
+        let message_type = match serialized_packet.envelope() {
+            Ok(envelope) if envelope.message_type.is_gossiped() => Ok(envelope.message_type),
+            // Message kinds this node doesn't take part in (yet), without a gossip header
+            Ok(_) => Err(Rejection::NotGossiped),
+            // Corrupted, malformed, or sent by a node running an incompatible version
+            Err(e) => Err(Rejection::from(&e)),
+        };
+        let message_type = match message_type {
+            Ok(message_type) => message_type,
+            Err(reason) => {
+                counters.reject(reason);
+                let dropped = EventKind::Dropped {
+                    packet_id: None,
+                    from: None,
+                    reason,
+                };
+                report(&report_sender, &node_id, dropped).await;
+                continue;
+            }
+        };
+
+        // Only the fixed-size header is read until we know the packet is new
+        let packet_id = serialized_packet.id();
+        let from = serialized_packet.source_id();
+        let is_new_message = seen_messages.insert(packet_id);
 
         // If we've already processed this message, ignore it.
         if !is_new_message {
+            let duplicate = EventKind::Duplicate { packet_id, from };
+            report(&report_sender, &node_id, duplicate).await;
+            continue;
+        }
+        let hop = settings
+            .time_to_live
+            .saturating_sub(serialized_packet.ttl());
+        let dropped = |reason| EventKind::Dropped {
+            packet_id: Some(packet_id),
+            from: Some(from.clone()),
+            reason,
+        };
+
+        // Actions already put in sequence by the committee, applied in the sequence of its log.
+        // The committee checked the signatures.
+        if message_type == MessageType::SequencedBatch {
+            let batch = serialized_packet
+                .action_bytes()
+                .map_err(DecodeError::from)
+                .and_then(|bytes| Batch::decode(&codec, &bytes));
+            let Ok(batch) = batch else {
+                seen_messages.remove(&packet_id);
+                counters.reject(Rejection::UndecodableOrder);
+                report(
+                    &report_sender,
+                    &node_id,
+                    dropped(Rejection::UndecodableOrder),
+                )
+                .await;
+                continue;
+            };
+            for entry in sequenced_log.push(batch) {
+                state.merge(entry.timestamp);
+                if settings.markets.check_action(&entry.action).is_err() {
+                    counters.reject(Rejection::InvalidOrder);
+                    let dropped = EventKind::Dropped {
+                        packet_id: Some(entry.packet_id),
+                        from: Some(from.clone()),
+                        reason: Rejection::InvalidOrder,
+                    };
+                    report(&report_sender, &node_id, dropped).await;
+                    continue;
+                }
+                state.apply(entry.action);
+                // Delivered once applied, i.e. visible in this node's books
+                let delivered = EventKind::Delivered {
+                    packet_id: entry.packet_id,
+                    from: from.clone(),
+                    hop,
+                };
+                report(&report_sender, &node_id, delivered).await;
+            }
+
+            forward(
+                &node_id,
+                &neighbors,
+                &settings,
+                &mut transport,
+                serialized_packet,
+                counters,
+                &report_sender,
+            )
+            .await;
+            continue;
+        }
+
+        // Checked after deduping, so that each packet is verified at most once per node
+        if let Err(e) = settings.verification.check(&serialized_packet) {
+            // Let a validly signed copy of the packet through if one arrives later
+            seen_messages.remove(&packet_id);
+            counters.reject(Rejection::from(&e));
+            report(&report_sender, &node_id, dropped(Rejection::from(&e))).await;
+            continue;
+        }
+
+        // -- Process the action --
+        // Orders are matched against this node's book of their market, the fills stay local
+        let action = serialized_packet
+            .action_bytes()
+            .map_err(DecodeError::from)
+            .and_then(|bytes| codec.decode_action(&bytes));
+        let (timestamp, action) = match action {
+            Ok(stamped) => stamped,
+            Err(_) => {
+                // Let a valid copy of the packet through if one arrives later
+                seen_messages.remove(&packet_id);
+                counters.reject(Rejection::UndecodableOrder);
+                report(
+                    &report_sender,
+                    &node_id,
+                    dropped(Rejection::UndecodableOrder),
+                )
+                .await;
+                continue;
+            }
+        };
+
+        state.merge(timestamp);
+
+        // Orders breaking their market's rules stop here, any copy of them would too
+        if settings.markets.check_action(&action).is_err() {
+            counters.reject(Rejection::InvalidOrder);
+            report(&report_sender, &node_id, dropped(Rejection::InvalidOrder)).await;
             continue;
         }
+        match sequencer.as_mut() {
+            // Delivered once settled and applied, the delay being part of the propagation time
+            Some(sequencer) => {
+                sequencer.push(timestamp, packet_id, action);
+                settling.insert(packet_id, (from, hop));
+                counters
+                    .late_actions
+                    .store(sequencer.late(), Ordering::Relaxed);
+            }
+            None => {
+                state.apply(action);
+                // Report back to main
+                let delivered = EventKind::Delivered {
+                    packet_id,
+                    from,
+                    hop,
+                };
+                report(&report_sender, &node_id, delivered).await;
+            }
+        }
+
+        forward(
+            &node_id,
+            &neighbors,
+            &settings,
+            &mut transport,
+            serialized_packet,
+            counters,
+            &report_sender,
+        )
+        .await;
+    }
+    println!(
+        "[{node_id:?}] ({}): Transport closed. Task shutting down.",
+        transport.local_addr()
+    );
+}
+
+/// Gossips a packet received from `source_id` to random neighbors, unless its TTL is reached
+async fn forward<T: Transport>(
+    node_id: &NodeId,
+    neighbors: &HashSet<NodeId>,
+    settings: &NodeSettings,
+    transport: &mut T,
+    serialized_packet: SerialiedPacket,
+    counters: &NodeCounters,
+    report_sender: &mpsc::Sender<NodeEvent>,
+) {
+    let packet_id = serialized_packet.id();
+    // Don't propagate order if TTL is reached
+    let ttl = serialized_packet.ttl();
+    if ttl == 0 {
+        report(report_sender, node_id, EventKind::TtlExpired { packet_id }).await;
+        return;
+    }
+
+    let mut considered_neighbors: HashSet<NodeId> = neighbors.clone();
+    considered_neighbors.remove(&serialized_packet.source_id());
 
-        // -- Process the order --
-        // We simply record it but the logic could be more complex (match against orders, send match result, allow different order types, allow cancellation, ...)
-        orders.push(packet.order.clone());
+    // Every neighbor receives the same bytes: the header is patched once and the buffer is shared
+    let packet_to_send = serialized_packet.forwarded(node_id, ttl.saturating_sub(1));
 
-        // Report back to main
-        let _ = report_sender.send(packet.id).await;
+    let neighbor_count = considered_neighbors.len().min(settings.num_peers as usize);
 
-        // Don't propagate order if TTL is reached
-        if packet.ttl == 0 {
+    // Iterate over the node's neighbors.
+    for neighbor_id in random_neighbors(&considered_neighbors, neighbor_count) {
+        // The transport applies the network delay without blocking the node's task, failures
+        // after the delay come back through `next_failure`
+        if let Err(e) = transport.send(neighbor_id, packet_to_send.clone()).await {
+            drop_unsent(counters, report_sender, node_id, packet_id, &e).await;
             continue;
         }
+        let forwarded = EventKind::Forwarded {
+            packet_id,
+            to: neighbor_id.clone(),
+        };
+        report(report_sender, node_id, forwarded).await;
+    }
+}
+
+/// Counts a packet the transport couldn't send to a neighbor and reports it as dropped. The other
+/// neighbors still receive it.
+async fn drop_unsent(
+    counters: &NodeCounters,
+    report_sender: &mpsc::Sender<NodeEvent>,
+    node_id: &NodeId,
+    packet_id: PacketId,
+    error: &TransportError,
+) {
+    let reason = Rejection::from(error);
+    counters.reject(reason);
+    let dropped = EventKind::Dropped {
+        packet_id: Some(packet_id),
+        from: None,
+        reason,
+    };
+    report(report_sender, node_id, dropped).await;
+}
+
+/// Next packet the transport failed to send, forever pending if it reports every failure from
+/// `send`
+async fn next_failure(
+    failures: &mut Option<mpsc::UnboundedReceiver<SendFailure>>,
+) -> Option<SendFailure> {
+    match failures {
+        Some(failures) => failures.recv().await,
+        None => std::future::pending().await,
+    }
+}
 
-        let mut considered_neighbors: HashSet<NodeId> = neighbors.clone();
-        considered_neighbors.remove(&packet.source_id);
-
-        let packet_to_send = GossipPacket::new(
-            packet.id,
-            node_id.clone(),
-            packet.ttl.saturating_sub(1),
-            packet.order,
-        );
-
-        let neighbor_count = considered_neighbors.len().min(num_peers as usize);
-
-        // Iterate over the node's neighbors.
-        for neighbor_id in random_neighbors(&considered_neighbors, neighbor_count) {
-            // In a real application, would handle the case where the sender is missing
-            let sender = all_senders.get(neighbor_id).unwrap();
-
-            // Spawn a new task to send packet to simulate network delay in the send without blocking the node's task.
-            tokio::spawn(send_gossip_packet_with_delay(
-                sender.clone(),
-                node_id.clone(),
-                neighbor_id.clone(),
-                packet_to_send.borsh_serialize(),
-                latency,
-            ));
+/// Reports an event of the node to main, which may have stopped listening
+async fn report(report_sender: &mpsc::Sender<NodeEvent>, node_id: &NodeId, kind: EventKind) {
+    let _ = report_sender
+        .send(NodeEvent::new(node_id.clone(), kind))
+        .await;
+}
+
+/// Waits until `settlement`, in milliseconds since the Unix epoch, forever if `None`
+async fn sleep_until_settlement(settlement: Option<u64>) {
+    match settlement {
+        Some(settlement) => {
+            let delay = settlement.saturating_sub(hlc::physical_now());
+            tokio::time::sleep(Duration::from_millis(delay)).await
         }
+        None => std::future::pending().await,
     }
-    println!("[{node_id:?}]: Channel closed. Task shutting down.");
 }
 
 fn random_neighbors(nodes: &HashSet<NodeId>, count: usize) -> Vec<&NodeId> {
@@ -92,18 +366,189 @@ fn random_neighbors(nodes: &HashSet<NodeId>, count: usize) -> Vec<&NodeId> {
         .collect()
 }
 
-async fn send_gossip_packet_with_delay(
-    sender: mpsc::Sender<SerialiedPacket>,
-    node_id: NodeId,
-    neighbor_id: NodeId,
-    packet: SerialiedPacket,
-    latency: Duration,
-) {
-    tokio::time::sleep(latency).await;
+/// Why a node rejected a packet
+#[derive(Debug, Clone, Copy, PartialEq, Eq, BorshDeserialize, BorshSerialize)]
+pub enum Rejection {
+    /// Truncated, bad magic bytes or wrong length
+    Malformed,
+    /// Sent by a node running an incompatible protocol version
+    UnsupportedVersion,
+    UnknownMessageType,
+    /// Valid message of a type nodes don't take part in yet, e.g. a pull digest
+    NotGossiped,
+    /// Corrupted on the way
+    BadChecksum,
+    /// Valid envelope but the order can't be decompressed or decoded
+    UndecodableOrder,
+    /// Signature required but missing
+    Unsigned,
+    /// The order was modified or not signed by the key it carries
+    BadSignature,
+    /// The order, or the amended one, breaks its market's rules, or its market is unknown or halted
+    InvalidOrder,
+    /// Too large for a frame or datagram of the transport, not sent to a neighbor
+    TooLarge,
+    /// Not sent to a neighbor: the link is closed or failed, or the neighbor is unknown
+    SendFailed,
+}
+
+impl Rejection {
+    pub const ALL: [Rejection; 11] = [
+        Rejection::Malformed,
+        Rejection::UnsupportedVersion,
+        Rejection::UnknownMessageType,
+        Rejection::NotGossiped,
+        Rejection::BadChecksum,
+        Rejection::UndecodableOrder,
+        Rejection::Unsigned,
+        Rejection::BadSignature,
+        Rejection::InvalidOrder,
+        Rejection::TooLarge,
+        Rejection::SendFailed,
+    ];
+
+    /// Position of this reason in [`Rejection::ALL`], to count rejections by reason
+    pub fn index(self) -> usize {
+        Rejection::ALL
+            .iter()
+            .position(|r| *r == self)
+            .expect("Every reason is listed in Rejection::ALL")
+    }
+}
+
+impl From<&EnvelopeError> for Rejection {
+    fn from(e: &EnvelopeError) -> Self {
+        match e {
+            EnvelopeError::Truncated { .. }
+            | EnvelopeError::BadMagic(_)
+            | EnvelopeError::LengthMismatch { .. } => Rejection::Malformed,
+            EnvelopeError::UnsupportedVersion(_) | EnvelopeError::UnknownFlags(_) => {
+                Rejection::UnsupportedVersion
+            }
+            EnvelopeError::UnknownMessageType(_) => Rejection::UnknownMessageType,
+            EnvelopeError::ChecksumMismatch { .. } => Rejection::BadChecksum,
+        }
+    }
+}
+
+impl From<&TransportError> for Rejection {
+    fn from(e: &TransportError) -> Self {
+        match e {
+            TransportError::TooLarge { .. } => Rejection::TooLarge,
+            TransportError::Closed | TransportError::UnknownPeer(_) | TransportError::Io(_) => {
+                Rejection::SendFailed
+            }
+        }
+    }
+}
+
+impl From<&SignatureError> for Rejection {
+    fn from(e: &SignatureError) -> Self {
+        match e {
+            SignatureError::Unsigned => Rejection::Unsigned,
+            SignatureError::InvalidPublicKey | SignatureError::Invalid => Rejection::BadSignature,
+        }
+    }
+}
+
+/// State of a node shared with the rest of the program, updated by its task and readable from
+/// anywhere
+#[derive(Debug)]
+pub struct NodeState {
+    pub counters: NodeCounters,
+    /// Stamps the actions injected at this node, after every timestamp it received
+    clock: Mutex<HybridClock>,
+    /// Only locked by the node's task to apply an action, and by readers
+    order_state: Mutex<OrderState>,
+}
+
+impl NodeState {
+    pub fn new(markets: Arc<MarketRegistry>) -> Self {
+        Self {
+            counters: NodeCounters::default(),
+            clock: Mutex::new(HybridClock::new()),
+            order_state: Mutex::new(OrderState::new(markets)),
+        }
+    }
+
+    /// Timestamp of an action injected at this node, see [`HybridClock::now`]
+    pub fn stamp(&self) -> Timestamp {
+        self.clock.lock().unwrap().now()
+    }
+
+    /// Merges the timestamp of a received action into this node's clock
+    fn merge(&self, timestamp: Timestamp) {
+        self.clock.lock().unwrap().update(timestamp);
+    }
+
+    /// Applies an action to this node's books
+    fn apply(&self, action: Action) {
+        let is_cancel = matches!(action, Action::Cancel { .. });
+        let outcome = self.order_state.lock().unwrap().apply(action);
+        if outcome == Outcome::Buffered && is_cancel {
+            self.counters
+                .buffered_cancels
+                .fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// Best bid and ask of a market in this node's book
+    pub fn top_of_book(&self, market: MarketId) -> Option<TopOfBook> {
+        let order_state = self.order_state.lock().unwrap();
+        order_state.book(market).map(OrderBook::top_of_book)
+    }
+
+    /// Up to `num_levels` levels of each side of a market in this node's book
+    pub fn depth(&self, market: MarketId, num_levels: usize) -> Option<Depth> {
+        let order_state = self.order_state.lock().unwrap();
+        order_state.book(market).map(|book| book.depth(num_levels))
+    }
+
+    /// Hash of this node's books, see [`OrderState::books_hash`]
+    pub fn books_hash(&self) -> u64 {
+        self.order_state.lock().unwrap().books_hash()
+    }
+
+    /// Number of trades that happened in this node's books
+    pub fn num_fills(&self) -> usize {
+        self.order_state.lock().unwrap().fills().len()
+    }
+
+    /// Trades that happened in this node's books, in the order they happened
+    pub fn fills(&self) -> Vec<Fill> {
+        self.order_state.lock().unwrap().fills().to_vec()
+    }
+}
+
+/// Counters of a node, updated by its task and readable from anywhere
+#[derive(Debug, Default)]
+pub struct NodeCounters {
+    rejected: [AtomicU64; Rejection::ALL.len()],
+    /// Cancels received before the order they refer to, which raced it through the network
+    buffered_cancels: AtomicU64,
+    /// Actions received too late to be applied in sequence, see [`Sequencer::late`]
+    late_actions: AtomicU64,
+}
+
+impl NodeCounters {
+    pub fn reject(&self, reason: Rejection) {
+        self.rejected[reason.index()].fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Number of packets rejected for `reason`
+    pub fn rejected(&self, reason: Rejection) -> u64 {
+        self.rejected[reason.index()].load(Ordering::Relaxed)
+    }
+
+    pub fn total_rejected(&self) -> u64 {
+        Rejection::ALL.iter().map(|r| self.rejected(*r)).sum()
+    }
+
+    pub fn buffered_cancels(&self) -> u64 {
+        self.buffered_cancels.load(Ordering::Relaxed)
+    }
 
-    // In a real application, you'd want to better handle potential errors sending the packet.
-    if let Err(e) = sender.send(packet).await {
-        eprintln!("[{node_id:?}]: Failed to send to {neighbor_id:?}: {e}");
-        exit(1)
+    pub fn late_actions(&self) -> u64 {
+        self.late_actions.load(Ordering::Relaxed)
     }
 }
@@ -1,54 +1,209 @@
 use bincode::{Decode, Encode};
 use borsh::{BorshDeserialize, BorshSerialize};
-use rand::{Rng, RngCore};
+use serde::{Deserialize, Serialize};
+
+use crate::fixed_point::{Price, Quantity};
 
 /// A simple place-order like struct for demonstration purposes.
-#[derive(Debug, Clone, Decode, Encode, PartialEq, BorshDeserialize, BorshSerialize)]
+#[derive(
+    Debug,
+    Clone,
+    Decode,
+    Encode,
+    PartialEq,
+    Eq,
+    Hash,
+    BorshDeserialize,
+    BorshSerialize,
+    Deserialize,
+    Serialize,
+)]
 pub struct Order {
     pub id: u64,
     pub market: MarketId,
     pub side: Side,
-    pub price: f64, // Would be better to have a specific type for price (maybe fixed point depending on market)
-    pub quantity: f64, // Would be better to have a specific type for quantity (maybe fixed point depending on base asset)
+    /// Limit price, 0 for market and stop orders
+    pub price: Price,
+    pub quantity: Quantity,
+    pub order_type: OrderType,
+    pub time_in_force: TimeInForce,
 }
 
 impl Order {
-    pub fn new(id: u64, market: MarketId, side: Side, price: f64, quantity: f64) -> Self {
+    /// Limit order, good till cancelled
+    pub fn new(id: u64, market: MarketId, side: Side, price: Price, quantity: Quantity) -> Self {
         Self {
             id,
             market,
             side,
             price,
             quantity,
+            order_type: OrderType::Limit,
+            time_in_force: TimeInForce::GoodTillCancel,
         }
     }
 
-    /// Create a random order
-    pub fn random_order() -> Self {
-        let mut rng = rand::rng();
+    pub fn with_type(self, order_type: OrderType, time_in_force: TimeInForce) -> Self {
+        Self {
+            order_type,
+            time_in_force,
+            ..self
+        }
+    }
+}
 
-        let side = if rng.random_bool(0.5) {
-            Side::Bid
-        } else {
-            Side::Ask
-        };
+/// What a trader asks the network to do, carried by a [`crate::packet::GossipPacket`]. Orders
+/// are referred to by their id, unique across markets.
+#[derive(
+    Debug,
+    Clone,
+    Decode,
+    Encode,
+    PartialEq,
+    BorshDeserialize,
+    BorshSerialize,
+    Deserialize,
+    Serialize,
+)]
+pub enum Action {
+    /// New order
+    Place(Order),
+    /// Removes what is left of an order
+    Cancel { market: MarketId, order_id: u64 },
+    /// Replaces the price and the quantity of an order, keeping its type
+    Amend {
+        market: MarketId,
+        order_id: u64,
+        price: Price,
+        quantity: Quantity,
+    },
+}
 
-        Self::new(
-            rng.next_u64(),
-            MarketId::SolUsd,
-            side,
-            rng.random::<f64>(),
-            rng.random::<f64>(),
-        )
+impl Action {
+    /// Id of the order placed or referred to
+    pub fn order_id(&self) -> u64 {
+        match self {
+            Action::Place(order) => order.id,
+            Action::Cancel { order_id, .. } | Action::Amend { order_id, .. } => *order_id,
+        }
+    }
+
+    pub fn market(&self) -> MarketId {
+        match self {
+            Action::Place(order) => order.market,
+            Action::Cancel { market, .. } | Action::Amend { market, .. } => *market,
+        }
     }
 }
 
-#[derive(Debug, Clone, Decode, Encode, PartialEq, BorshDeserialize, BorshSerialize)]
-pub enum MarketId {
-    SolUsd,
+/// How an order's price is determined
+#[derive(
+    Debug,
+    Clone,
+    Copy,
+    Decode,
+    Encode,
+    PartialEq,
+    Eq,
+    Hash,
+    BorshDeserialize,
+    BorshSerialize,
+    Deserialize,
+    Serialize,
+)]
+pub enum OrderType {
+    /// Trades at its price or better, the rest can stay in the book
+    Limit,
+    /// Trades at any price against the book, never rests
+    Market,
+    /// Becomes a market order once the market trades at `trigger` (at or above it for a bid, at
+    /// or below it for an ask)
+    Stop { trigger: Price },
+    /// Becomes a limit order at the order's price once the market trades at `trigger`
+    StopLimit { trigger: Price },
+}
+
+impl OrderType {
+    /// Whether the order carries a limit price
+    pub fn has_price(&self) -> bool {
+        matches!(self, OrderType::Limit | OrderType::StopLimit { .. })
+    }
+
+    pub fn trigger(&self) -> Option<Price> {
+        match self {
+            OrderType::Stop { trigger } | OrderType::StopLimit { trigger } => Some(*trigger),
+            OrderType::Limit | OrderType::Market => None,
+        }
+    }
+}
+
+/// How long an order stays active
+#[derive(
+    Debug,
+    Clone,
+    Copy,
+    Decode,
+    Encode,
+    PartialEq,
+    Eq,
+    Hash,
+    BorshDeserialize,
+    BorshSerialize,
+    Deserialize,
+    Serialize,
+)]
+pub enum TimeInForce {
+    /// What isn't filled right away rests in the book until cancelled
+    GoodTillCancel,
+    /// What isn't filled right away is cancelled (IOC)
+    ImmediateOrCancel,
+    /// Filled entirely right away, or cancelled entirely (FOK)
+    FillOrKill,
+    /// Only adds liquidity: cancelled if it would trade right away
+    PostOnly,
+}
+
+#[derive(
+    Debug,
+    Clone,
+    Copy,
+    Decode,
+    Encode,
+    PartialEq,
+    Eq,
+    Hash,
+    BorshDeserialize,
+    BorshSerialize,
+    Deserialize,
+    Serialize,
+)]
+/// Compact id of a market, its index in the [`crate::market::MarketRegistry`]
+pub struct MarketId(u16);
+
+impl MarketId {
+    pub const fn new(id: u16) -> Self {
+        Self(id)
+    }
+
+    pub fn value(&self) -> u16 {
+        self.0
+    }
 }
 
-#[derive(Debug, Clone, Decode, Encode, PartialEq, BorshDeserialize, BorshSerialize)]
+#[derive(
+    Debug,
+    Clone,
+    Copy,
+    Decode,
+    Encode,
+    PartialEq,
+    Eq,
+    Hash,
+    BorshDeserialize,
+    BorshSerialize,
+    Deserialize,
+    Serialize,
+)]
 pub enum Side {
     Bid,
     Ask,
@@ -0,0 +1,207 @@
+use std::{
+    collections::{HashMap, HashSet},
+    hash::{DefaultHasher, Hash, Hasher},
+    sync::Arc,
+};
+
+use crate::{
+    book::{Fill, OrderBook},
+    market::MarketRegistry,
+    order::{Action, MarketId, Order},
+};
+
+/// What applying an action did to a node's order state
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Outcome {
+    Applied,
+    /// The action refers to an order this node hasn't received yet. It is kept and applied once
+    /// the order arrives, as gossip doesn't preserve the order in which actions were sent.
+    Buffered,
+    /// The order is already placed, or no longer in the book (filled or cancelled), or the
+    /// amended order would break its market's rules
+    Ignored,
+}
+
+/// Orders known to a node: an order book per market, updated by the actions it receives
+#[derive(Debug, Clone)]
+pub struct OrderState {
+    markets: Arc<MarketRegistry>,
+    /// Indexed by market id
+    books: Vec<OrderBook>,
+    /// Ids of every order placed so far, so that late actions on them aren't buffered forever
+    placed: HashSet<u64>,
+    /// Actions received before the order they refer to, in the order they were received
+    pending: HashMap<u64, Vec<Action>>,
+    /// Trades of the orders placed on this node, in the order they happened
+    fills: Vec<Fill>,
+}
+
+impl OrderState {
+    pub fn new(markets: Arc<MarketRegistry>) -> Self {
+        let books = markets
+            .markets()
+            .iter()
+            .map(|market| OrderBook::new(market.id))
+            .collect();
+        Self {
+            markets,
+            books,
+            placed: HashSet::new(),
+            pending: HashMap::new(),
+            fills: Vec::new(),
+        }
+    }
+
+    /// Applies an action whose market is known, see [`MarketRegistry::check_action`]
+    pub fn apply(&mut self, action: Action) -> Outcome {
+        let order_id = action.order_id();
+        let Some(book) = self.books.get_mut(action.market().value() as usize) else {
+            return Outcome::Ignored;
+        };
+
+        match action {
+            Action::Place(order) => {
+                if !self.placed.insert(order_id) {
+                    return Outcome::Ignored;
+                }
+                self.fills.extend(book.submit(order));
+
+                for action in self.pending.remove(&order_id).unwrap_or_default() {
+                    self.apply(action);
+                }
+                Outcome::Applied
+            }
+            action if !self.placed.contains(&order_id) => {
+                self.pending.entry(order_id).or_default().push(action);
+                Outcome::Buffered
+            }
+            Action::Cancel { .. } => match book.cancel(order_id) {
+                Some(_) => Outcome::Applied,
+                None => Outcome::Ignored,
+            },
+            Action::Amend {
+                price, quantity, ..
+            } => {
+                let Some(order) = book.get(order_id) else {
+                    return Outcome::Ignored;
+                };
+                let amended = Order {
+                    price,
+                    quantity,
+                    ..order.clone()
+                };
+                if self.markets.check(&amended).is_err() {
+                    return Outcome::Ignored;
+                }
+                let fills = book
+                    .amend(amended)
+                    .expect("Order checked to be in the book");
+                self.fills.extend(fills);
+                Outcome::Applied
+            }
+        }
+    }
+
+    pub fn book(&self, market: MarketId) -> Option<&OrderBook> {
+        self.books.get(market.value() as usize)
+    }
+
+    pub fn books(&self) -> &[OrderBook] {
+        &self.books
+    }
+
+    pub fn fills(&self) -> &[Fill] {
+        &self.fills
+    }
+
+    /// Hash of every book, equal on two nodes whose books hold the same orders
+    pub fn books_hash(&self) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        self.books.hash(&mut hasher);
+        hasher.finish()
+    }
+
+    /// Number of actions waiting for their order
+    pub fn num_pending(&self) -> usize {
+        self.pending.values().map(Vec::len).sum()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        book::Depth,
+        fixed_point::{Price, Quantity},
+    };
+
+    #[test]
+    fn test_actions() {
+        let markets = Arc::new(MarketRegistry::default());
+        let order = markets.random_order();
+        let cancel = Action::Cancel {
+            market: order.market,
+            order_id: order.id,
+        };
+        let amend = |price, quantity| Action::Amend {
+            market: order.market,
+            order_id: order.id,
+            price: Price::from_ticks(price),
+            quantity: Quantity::from_lots(quantity),
+        };
+
+        // In order
+        let mut state = OrderState::new(markets.clone());
+        assert_eq!(state.apply(Action::Place(order.clone())), Outcome::Applied);
+        assert_eq!(state.apply(Action::Place(order.clone())), Outcome::Ignored);
+        assert_eq!(state.apply(amend(15_000, 2)), Outcome::Applied);
+        assert_eq!(
+            state
+                .book(order.market)
+                .unwrap()
+                .get(order.id)
+                .unwrap()
+                .price,
+            Price::from_ticks(15_000)
+        );
+        assert_eq!(
+            state
+                .book(order.market)
+                .unwrap()
+                .get(order.id)
+                .unwrap()
+                .quantity,
+            Quantity::from_lots(2)
+        );
+        // A limit order needs a price
+        assert_eq!(state.apply(amend(0, 2)), Outcome::Ignored);
+        assert_eq!(state.apply(cancel.clone()), Outcome::Applied);
+        assert_eq!(state.book(order.market).unwrap().get(order.id), None);
+        assert_eq!(state.apply(amend(15_000, 3)), Outcome::Ignored);
+        assert_eq!(state.apply(Action::Place(order.clone())), Outcome::Ignored);
+
+        // The cancel races the order: buffered, then applied when the order arrives
+        let mut state = OrderState::new(markets.clone());
+        assert_eq!(state.apply(amend(15_000, 2)), Outcome::Buffered);
+        assert_eq!(state.apply(cancel.clone()), Outcome::Buffered);
+        assert_eq!(state.num_pending(), 2);
+        assert_eq!(state.apply(Action::Place(order.clone())), Outcome::Applied);
+        assert_eq!(state.book(order.market).unwrap().get(order.id), None);
+        assert_eq!(state.num_pending(), 0);
+        assert_eq!(state.book(order.market).unwrap().depth(1), Depth::default());
+
+        // Only the amend raced it
+        let mut state = OrderState::new(markets);
+        assert_eq!(state.apply(amend(15_000, 2)), Outcome::Buffered);
+        assert_eq!(state.apply(Action::Place(order.clone())), Outcome::Applied);
+        assert_eq!(
+            state
+                .book(order.market)
+                .unwrap()
+                .get(order.id)
+                .unwrap()
+                .quantity,
+            Quantity::from_lots(2)
+        );
+    }
+}
@@ -0,0 +1,136 @@
+use rand::seq::IndexedRandom;
+use serde::{Deserialize, Serialize};
+
+use crate::network::{Network, NodeId};
+
+/// How the node injecting each run's packets is picked
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(tag = "policy", rename_all = "snake_case")]
+pub enum OriginPolicy {
+    /// Any node, with equal probability
+    #[default]
+    Random,
+    Fixed {
+        node: u64,
+    },
+    /// A node with the most nodes gossiping to it, the best connected one
+    HighestInDegree,
+    /// A node with the fewest nodes gossiping to it, the least connected one
+    LowestInDegree,
+    /// Any node of a region. The nodes are split into `num_regions` regions of consecutive ids, as
+    /// the network has no geography.
+    Region {
+        region: u64,
+        num_regions: u64,
+    },
+}
+
+impl OriginPolicy {
+    /// Nodes of the network the policy picks from, several when they are tied
+    pub fn candidates(&self, network: &Network) -> Vec<NodeId> {
+        let in_degrees = network.in_degrees();
+        let mut candidates: Vec<NodeId> = match *self {
+            OriginPolicy::Random => in_degrees.into_keys().collect(),
+            OriginPolicy::Fixed { node } => in_degrees
+                .into_keys()
+                .filter(|id| id.value() == node)
+                .collect(),
+            OriginPolicy::HighestInDegree | OriginPolicy::LowestInDegree => {
+                let degrees = in_degrees.values().copied();
+                let target = if *self == OriginPolicy::HighestInDegree {
+                    degrees.max()
+                } else {
+                    degrees.min()
+                };
+                in_degrees
+                    .into_iter()
+                    .filter(|(_, degree)| Some(*degree) == target)
+                    .map(|(id, _)| id)
+                    .collect()
+            }
+            OriginPolicy::Region {
+                region,
+                num_regions,
+            } => {
+                let num_nodes = in_degrees.len() as u64;
+                in_degrees
+                    .into_keys()
+                    .filter(|id| id.value() * num_regions / num_nodes == region)
+                    .collect()
+            }
+        };
+        candidates.sort_by_key(NodeId::value);
+        candidates
+    }
+}
+
+/// Picks the origin of each run among the candidates of a policy
+#[derive(Debug, Clone)]
+pub struct Origins {
+    candidates: Vec<NodeId>,
+}
+
+impl Origins {
+    /// `None` if no node of the network matches the policy
+    pub fn new(policy: OriginPolicy, network: &Network) -> Option<Self> {
+        let candidates = policy.candidates(network);
+        (!candidates.is_empty()).then_some(Self { candidates })
+    }
+
+    pub fn pick(&self) -> NodeId {
+        self.candidates
+            .choose(&mut rand::rng())
+            .cloned()
+            .expect("At least one candidate")
+    }
+
+    pub fn candidates(&self) -> &[NodeId] {
+        &self.candidates
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::{HashMap, HashSet};
+
+    use super::*;
+
+    #[test]
+    fn test_origin_policies() {
+        let ids = |ids: &[u64]| ids.iter().map(|id| NodeId::new(*id)).collect::<Vec<_>>();
+        // In-degrees: 0 has 3, 1 and 2 have 1, 3 has 0
+        let network = Network::new(HashMap::from([
+            (NodeId::new(0), HashSet::from_iter(ids(&[1, 2]))),
+            (NodeId::new(1), HashSet::from_iter(ids(&[0]))),
+            (NodeId::new(2), HashSet::from_iter(ids(&[0]))),
+            (NodeId::new(3), HashSet::from_iter(ids(&[0]))),
+        ]));
+
+        assert_eq!(
+            OriginPolicy::Random.candidates(&network),
+            ids(&[0, 1, 2, 3])
+        );
+        assert_eq!(
+            OriginPolicy::Fixed { node: 2 }.candidates(&network),
+            ids(&[2])
+        );
+        assert_eq!(OriginPolicy::Fixed { node: 4 }.candidates(&network), []);
+        assert_eq!(
+            OriginPolicy::HighestInDegree.candidates(&network),
+            ids(&[0])
+        );
+        assert_eq!(OriginPolicy::LowestInDegree.candidates(&network), ids(&[3]));
+        let region = |region| OriginPolicy::Region {
+            region,
+            num_regions: 2,
+        };
+        assert_eq!(region(0).candidates(&network), ids(&[0, 1]));
+        assert_eq!(region(1).candidates(&network), ids(&[2, 3]));
+
+        assert!(Origins::new(OriginPolicy::Fixed { node: 4 }, &network).is_none());
+        let origins = Origins::new(region(1), &network).unwrap();
+        for _ in 0..10 {
+            assert!(origins.candidates().contains(&origins.pick()));
+        }
+    }
+}
@@ -1,10 +1,34 @@
-use bincode::{Decode, Encode, config::Configuration};
+use bincode::{Decode, Encode};
 use borsh::{BorshDeserialize, BorshSerialize};
+use bytes::{BufMut, Bytes, BytesMut};
+use serde::{Deserialize, Serialize};
 
-use crate::{network::NodeId, order::Order};
+use crate::{
+    compression::{Compression, DecompressError},
+    envelope::{Envelope, EnvelopeError, Flags, MessageType},
+    hlc::Timestamp,
+    market::MarketRegistry,
+    network::NodeId,
+    order::Action,
+    signing::{self, SIGNATURE_BLOCK_SIZE},
+};
+use ed25519_dalek::SigningKey;
 
 #[derive(
-    Debug, Clone, Copy, Hash, PartialEq, Eq, Decode, Encode, BorshDeserialize, BorshSerialize,
+    Debug,
+    Clone,
+    Copy,
+    Hash,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    Decode,
+    Encode,
+    BorshDeserialize,
+    BorshSerialize,
+    Deserialize,
+    Serialize,
 )]
 /// Unique identifier for the packet, could be a UUID or a sequence number
 pub struct PacketId(u64);
@@ -13,82 +37,362 @@ impl PacketId {
     pub fn new(id: u64) -> Self {
         Self(id)
     }
+
+    pub fn value(&self) -> u64 {
+        self.0
+    }
 }
 
+/// Encoded packet, wrapped in an [`Envelope`] of type [`MessageType::GossipOrder`], or
+/// [`MessageType::SequencedBatch`] for a batch of the sequencer committee (see
+/// [`crate::committee::Batch`]). Whatever the codec, the payload starts with a fixed-size header so
+/// that a node can dedupe and forward a packet without decoding it:
+///
+/// | offset | size | field                                                  |
+/// | ------ | ---- | ------------------------------------------------------ |
+/// | 0      | 13   | envelope                                               |
+/// | 13     | 8    | id (little-endian)                                     |
+/// | 21     | 8    | source_id (little-endian)                              |
+/// | 29     | 8    | ttl (little-endian)                                    |
+/// | 37     | 96   | trader's public key and signature, if [`Flags::SIGNED`] |
+/// | 37/133 | ..   | action, encoded with the network's codec and compressed |
+///
+/// The action (placing, cancelling or amending an order) is preceded by its timestamp, and both
+/// are compressed if one of the [`Flags::LZ4`] or [`Flags::ZSTD`] flags is set. Only the action
+/// and its timestamp are signed, as sent on the wire: the source and TTL change at every hop.
+///
+/// The header accessors expect a packet whose [`SerialiedPacket::envelope`] is valid.
+/// The bytes are reference counted: cloning a packet to send it to several neighbors doesn't copy it.
 #[derive(Debug, Clone)]
-pub struct SerialiedPacket(Vec<u8>);
+pub struct SerialiedPacket(Bytes);
 
 impl SerialiedPacket {
-    pub fn bincode_deserialize(&self, config: Configuration) -> GossipPacket {
-        bincode::decode_from_slice(&self.0, config).unwrap().0
+    /// Size of the gossip header, inside the envelope's payload
+    const GOSSIP_HEADER_SIZE: usize = 24;
+
+    /// Size of the envelope and gossip header preceding the order payload
+    pub const HEADER_SIZE: usize = Envelope::SIZE + Self::GOSSIP_HEADER_SIZE;
+
+    const ID_OFFSET: usize = Envelope::SIZE;
+    const SOURCE_ID_OFFSET: usize = Envelope::SIZE + 8;
+    const TTL_OFFSET: usize = Envelope::SIZE + 16;
+
+    /// Writes the envelope and header of a packet followed by its encoded order
+    pub fn new(id: PacketId, source_id: &NodeId, ttl: u64, payload: &[u8]) -> Self {
+        Self::with_options(id, source_id, ttl, payload, &EncodeOptions::default())
+    }
+
+    /// Same as [`SerialiedPacket::new`], with the order signed by the trader's `key`
+    pub fn new_signed(
+        id: PacketId,
+        source_id: &NodeId,
+        ttl: u64,
+        payload: &[u8],
+        key: &SigningKey,
+    ) -> Self {
+        let options = EncodeOptions {
+            trader_key: Some(key),
+            ..Default::default()
+        };
+        Self::with_options(id, source_id, ttl, payload, &options)
+    }
+
+    /// Same as [`SerialiedPacket::new`], with the order compressed and signed as set in `options`
+    pub fn with_options(
+        id: PacketId,
+        source_id: &NodeId,
+        ttl: u64,
+        order: &[u8],
+        options: &EncodeOptions,
+    ) -> Self {
+        Self::build(MessageType::GossipOrder, id, source_id, ttl, order, options)
+    }
+
+    /// Packet carrying an encoded [`crate::committee::Batch`], neither compressed nor signed
+    pub fn sequenced_batch(id: PacketId, source_id: &NodeId, ttl: u64, batch: &[u8]) -> Self {
+        Self::build(
+            MessageType::SequencedBatch,
+            id,
+            source_id,
+            ttl,
+            batch,
+            &EncodeOptions::default(),
+        )
+    }
+
+    fn build(
+        message_type: MessageType,
+        id: PacketId,
+        source_id: &NodeId,
+        ttl: u64,
+        order: &[u8],
+        options: &EncodeOptions,
+    ) -> Self {
+        let (compression, payload) = options
+            .compression
+            .compress(order, options.compression_threshold);
+        let signature_block = options.trader_key.map(|key| signing::sign(key, &payload));
+
+        let signature_len = signature_block.map_or(0, |block| block.len());
+        let flags = if signature_block.is_some() {
+            Flags::SIGNED | compression.flag()
+        } else {
+            compression.flag()
+        };
+        let envelope = Envelope::new(
+            message_type,
+            (Self::GOSSIP_HEADER_SIZE + signature_len + payload.len()) as u32,
+        )
+        .with_flags(flags);
+
+        let mut bytes = BytesMut::with_capacity(Self::HEADER_SIZE + signature_len + payload.len());
+        envelope.write(&mut bytes);
+        bytes.put_u64_le(id.value());
+        bytes.put_u64_le(source_id.value());
+        bytes.put_u64_le(ttl);
+        if let Some(block) = signature_block {
+            bytes.put_slice(&block);
+        }
+        bytes.put_slice(&payload);
+        Envelope::seal(&mut bytes);
+        Self(bytes.freeze())
     }
 
-    pub fn borsh_deserialize(&self) -> GossipPacket {
-        borsh::from_slice(&self.0).unwrap()
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.0
+    }
+
+    pub fn into_bytes(self) -> Bytes {
+        self.0
+    }
+
+    /// Validates the envelope. A gossip order or batch must also be long enough for its header
+    /// and signature.
+    pub fn envelope(&self) -> Result<Envelope, EnvelopeError> {
+        let envelope = Envelope::parse(&self.0)?;
+
+        if envelope.message_type.is_gossiped() && self.0.len() < self.payload_offset() {
+            return Err(EnvelopeError::Truncated {
+                expected: self.payload_offset(),
+                actual: self.0.len(),
+            });
+        }
+
+        Ok(envelope)
+    }
+
+    pub fn id(&self) -> PacketId {
+        PacketId::new(self.header_field(Self::ID_OFFSET))
+    }
+
+    pub fn source_id(&self) -> NodeId {
+        NodeId::new(self.header_field(Self::SOURCE_ID_OFFSET))
+    }
+
+    pub fn ttl(&self) -> u64 {
+        self.header_field(Self::TTL_OFFSET)
+    }
+
+    /// Trader's public key followed by the signature of the order, if the packet is signed
+    pub fn signature_block(&self) -> Option<&[u8]> {
+        self.is_signed()
+            .then(|| &self.0[Self::HEADER_SIZE..Self::HEADER_SIZE + SIGNATURE_BLOCK_SIZE])
+    }
+
+    /// Action as sent on the wire, possibly compressed. Shared with the packet's buffer.
+    pub fn payload(&self) -> Bytes {
+        self.0.slice(self.payload_offset()..)
+    }
+
+    /// Encoded action, decompressed if needed. Only copied when the packet is compressed.
+    pub fn action_bytes(&self) -> Result<Bytes, DecompressError> {
+        match Compression::from_flags(Envelope::flags_of(&self.0)) {
+            Compression::None => Ok(self.payload()),
+            compression => compression.decompress(&self.payload()).map(Bytes::from),
+        }
+    }
+
+    fn is_signed(&self) -> bool {
+        Envelope::flags_of(&self.0).contains(Flags::SIGNED)
+    }
+
+    fn payload_offset(&self) -> usize {
+        if self.is_signed() {
+            Self::HEADER_SIZE + SIGNATURE_BLOCK_SIZE
+        } else {
+            Self::HEADER_SIZE
+        }
+    }
+
+    /// Same packet as sent by `source_id` with the given `ttl`. The header is patched in place when
+    /// this is the only handle to the buffer, the order payload is never decoded nor copied. Only
+    /// the checksum has to be computed again.
+    pub fn forwarded(self, source_id: &NodeId, ttl: u64) -> Self {
+        let mut bytes = self
+            .0
+            .try_into_mut()
+            .unwrap_or_else(|shared| BytesMut::from(&shared[..]));
+
+        bytes[Self::SOURCE_ID_OFFSET..Self::SOURCE_ID_OFFSET + 8]
+            .copy_from_slice(&source_id.value().to_le_bytes());
+        bytes[Self::TTL_OFFSET..Self::TTL_OFFSET + 8].copy_from_slice(&ttl.to_le_bytes());
+        Envelope::seal(&mut bytes);
+
+        Self(bytes.freeze())
+    }
+
+    fn header_field(&self, offset: usize) -> u64 {
+        let bytes = self.0[offset..offset + 8]
+            .try_into()
+            .expect("Packet shorter than its header");
+        u64::from_le_bytes(bytes)
+    }
+}
+
+impl From<Vec<u8>> for SerialiedPacket {
+    fn from(bytes: Vec<u8>) -> Self {
+        Self(Bytes::from(bytes))
     }
 }
 
-#[derive(Debug, Clone, Encode, Decode, PartialEq, BorshDeserialize, BorshSerialize)]
+impl From<Bytes> for SerialiedPacket {
+    fn from(bytes: Bytes) -> Self {
+        Self(bytes)
+    }
+}
+
+/// How the originator encodes the packets it sends
+#[derive(Debug, Clone, Copy, Default)]
+pub struct EncodeOptions<'a> {
+    /// Key signing the orders, unsigned when `None`
+    pub trader_key: Option<&'a SigningKey>,
+    pub compression: Compression,
+    /// Orders smaller than this (in bytes, once encoded) are sent uncompressed
+    pub compression_threshold: usize,
+}
+
+#[derive(
+    Debug,
+    Clone,
+    Encode,
+    Decode,
+    PartialEq,
+    BorshDeserialize,
+    BorshSerialize,
+    Deserialize,
+    Serialize,
+)]
 pub struct GossipPacket {
     pub id: PacketId,
     pub source_id: NodeId, // ID of the node that sent the packet
     pub ttl: u64,
-    pub order: Order,
+    /// Stamped by the originator's hybrid logical clock, the default one if not stamped
+    pub timestamp: Timestamp,
+    pub action: Action,
 }
 
 impl GossipPacket {
-    pub fn new(id: PacketId, source_id: NodeId, ttl: u64, order: Order) -> Self {
+    pub fn new(id: PacketId, source_id: NodeId, ttl: u64, action: Action) -> Self {
         Self {
             id,
             source_id,
             ttl,
-            order,
+            timestamp: Timestamp::default(),
+            action,
         }
     }
 
-    pub fn new_with_random_order(id: PacketId, source_id: NodeId, ttl: u64) -> Self {
-        GossipPacket::new(id, source_id, ttl, Order::random_order())
+    pub fn with_timestamp(self, timestamp: Timestamp) -> Self {
+        Self { timestamp, ..self }
     }
 
-    pub fn bincode_serialize(&self, config: Configuration) -> SerialiedPacket {
-        SerialiedPacket(bincode::encode_to_vec(self, config).unwrap())
-    }
-
-    pub fn borsh_serialize(&self) -> SerialiedPacket {
-        SerialiedPacket(borsh::to_vec(self).unwrap())
+    /// Packet placing a random order on the default market
+    pub fn new_with_random_order(id: PacketId, source_id: NodeId, ttl: u64) -> Self {
+        let order = MarketRegistry::default().random_order();
+        GossipPacket::new(id, source_id, ttl, Action::Place(order))
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use bincode::config;
-
     use super::*;
 
-    /// Num runs for test
-    const NUM_RUNS: u64 = 100;
+    #[test]
+    /// Forwarding only rewrites the source and TTL of the header
+    fn test_forwarded() {
+        let payload = [1, 2, 3, 4];
+        let packet = SerialiedPacket::new(PacketId::new(7), &NodeId::new(1), 5, &payload);
+        assert_eq!(packet.as_bytes().len(), SerialiedPacket::HEADER_SIZE + 4);
+
+        // Unique handle: patched in place
+        assert_eq!(
+            packet.envelope(),
+            Ok(Envelope::new(MessageType::GossipOrder, 24 + 4))
+        );
+
+        let ptr = packet.as_bytes().as_ptr();
+        let forwarded = packet.forwarded(&NodeId::new(2), 4);
+        assert_eq!(forwarded.as_bytes().as_ptr(), ptr);
+        assert_eq!(forwarded.id(), PacketId::new(7));
+        assert_eq!(forwarded.source_id(), NodeId::new(2));
+        assert_eq!(forwarded.ttl(), 4);
+        assert_eq!(&forwarded.payload()[..], &payload);
+        assert!(forwarded.envelope().is_ok());
+
+        // Shared handle: copied, the other handle is untouched
+        let shared = forwarded.clone();
+        let forwarded_again = forwarded.forwarded(&NodeId::new(3), 3);
+        assert_eq!(shared.source_id(), NodeId::new(2));
+        assert_eq!(shared.ttl(), 4);
+        assert_eq!(forwarded_again.source_id(), NodeId::new(3));
+        assert_eq!(forwarded_again.ttl(), 3);
+        assert_eq!(&forwarded_again.payload()[..], &payload);
+    }
 
     #[test]
-    /// Property test for bincode codec
-    fn test_bincode_codec() {
-        let config = config::standard();
-
-        for i in 0..NUM_RUNS {
-            let packet = GossipPacket::new_with_random_order(PacketId::new(i), NodeId::new(i), i);
-            let serialized = packet.bincode_serialize(config);
-            let deserialized = serialized.bincode_deserialize(config);
-            assert_eq!(packet, deserialized);
-        }
+    /// Compressed orders are flagged in the envelope and still forwarded without being decoded
+    fn test_compressed() {
+        let order = [42; 100];
+        let options = EncodeOptions {
+            compression: Compression::Lz4,
+            compression_threshold: 64,
+            ..Default::default()
+        };
+        let packet =
+            SerialiedPacket::with_options(PacketId::new(7), &NodeId::new(1), 5, &order, &options);
+        let envelope = packet.envelope().unwrap();
+        assert!(envelope.flags.contains(Flags::LZ4));
+        assert!(packet.payload().len() < order.len());
+        assert_eq!(&packet.action_bytes().unwrap()[..], &order);
+
+        let forwarded = packet.forwarded(&NodeId::new(2), 4);
+        assert!(forwarded.envelope().is_ok());
+        assert_eq!(&forwarded.action_bytes().unwrap()[..], &order);
+
+        // Below the threshold
+        let packet = SerialiedPacket::with_options(
+            PacketId::new(7),
+            &NodeId::new(1),
+            5,
+            &order[..10],
+            &options,
+        );
+        assert_eq!(packet.envelope().unwrap().flags, Flags::EMPTY);
+        assert_eq!(&packet.action_bytes().unwrap()[..], &order[..10]);
     }
 
     #[test]
-    /// Property test for borsh codec
-    fn test_borsch_codec() {
-        for i in 0..NUM_RUNS {
-            let packet = GossipPacket::new_with_random_order(PacketId::new(i), NodeId::new(i), i);
-            let serialized = packet.borsh_serialize();
-            let deserialized = serialized.borsh_deserialize();
-            assert_eq!(packet, deserialized);
-        }
+    /// A gossip envelope too short for the gossip header is rejected
+    fn test_truncated_gossip_header() {
+        let mut bytes = BytesMut::new();
+        Envelope::new(MessageType::GossipOrder, 4).write(&mut bytes);
+        bytes.put_u32_le(0);
+        Envelope::seal(&mut bytes);
+
+        let packet = SerialiedPacket::from(bytes.freeze());
+        assert!(matches!(
+            packet.envelope(),
+            Err(EnvelopeError::Truncated { .. })
+        ));
     }
 }
@@ -0,0 +1,263 @@
+use std::{
+    collections::{HashMap, HashSet},
+    io,
+    net::{Ipv4Addr, SocketAddr},
+    path::Path,
+    process::Stdio,
+    sync::Arc,
+    time::Duration,
+};
+
+use borsh::{BorshDeserialize, BorshSerialize};
+use tokio::{
+    io::{AsyncRead, AsyncWrite},
+    net::{TcpListener, TcpStream},
+    process::{Child, Command},
+    sync::mpsc,
+};
+
+use crate::{
+    codec::CodecKind,
+    event::NodeEvent,
+    network::NodeId,
+    node::{self, NodeSettings, NodeState},
+    packet::SerialiedPacket,
+    topology::Topology,
+    transport::{TransportKind, socket::SocketBinding, tcp},
+};
+
+/// Maximum time to wait for the next node process to be ready
+const STARTUP_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Messages exchanged between the launcher and the node processes on their control connection
+#[derive(Debug, PartialEq, BorshDeserialize, BorshSerialize)]
+pub enum ControlMessage {
+    /// First message sent by a node once it is ready to receive packets
+    Hello(NodeId),
+    /// Something the node did with a packet
+    Report(NodeEvent),
+    /// Packet the node must process as if it was received from the network
+    Inject(Vec<u8>),
+}
+
+impl ControlMessage {
+    pub async fn write<W: AsyncWrite + Unpin>(&self, writer: &mut W) -> io::Result<()> {
+        tcp::write_frame(writer, &borsh::to_vec(self)?).await
+    }
+
+    /// Next message, or `None` once the connection is closed
+    pub async fn read<R: AsyncRead + Unpin>(reader: &mut R) -> io::Result<Option<Self>> {
+        match tcp::read_frame(reader).await? {
+            Some(frame) => Ok(Some(borsh::from_slice(&frame)?)),
+            None => Ok(None),
+        }
+    }
+}
+
+/// Runs the node `node_id` of `topology` in the current process until the launcher listening on
+/// `control_addr` closes the control connection.
+pub async fn run_node(
+    node_id: NodeId,
+    topology: &Topology,
+    control_addr: SocketAddr,
+    latency: Duration,
+    settings: NodeSettings,
+    transport: TransportKind,
+    codec: CodecKind,
+) -> io::Result<()> {
+    let entry = topology.node(&node_id).ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("{node_id:?} is not in the topology"),
+        )
+    })?;
+
+    let (inbox_tx, inbox_rx) = mpsc::channel::<SerialiedPacket>(32);
+    let identity = topology.quic_identity();
+    let binding =
+        SocketBinding::bind(transport, entry.addr, identity.as_ref(), inbox_tx.clone()).await?;
+    let transport = binding.into_transport(latency, inbox_rx, topology.peers(&node_id));
+
+    let control = TcpStream::connect(control_addr).await?;
+    control.set_nodelay(true)?;
+    let (mut control_rx, mut control_tx) = control.into_split();
+    ControlMessage::Hello(node_id.clone())
+        .write(&mut control_tx)
+        .await?;
+
+    // Forward the node's reports to the launcher
+    let (report_tx, mut report_rx) = mpsc::channel::<NodeEvent>(32);
+    tokio::spawn(async move {
+        while let Some(event) = report_rx.recv().await {
+            if ControlMessage::Report(event)
+                .write(&mut control_tx)
+                .await
+                .is_err()
+            {
+                break;
+            }
+        }
+    });
+
+    let neighbors: HashSet<NodeId> = entry.neighbors.iter().cloned().collect();
+    let state = Arc::new(NodeState::new(settings.markets.clone()));
+
+    tokio::select! {
+        _ = node::node_task(
+            node_id,
+            neighbors,
+            settings,
+            transport,
+            codec,
+            state,
+            report_tx,
+        ) => Ok(()),
+        result = async {
+            while let Some(message) = ControlMessage::read(&mut control_rx).await? {
+                if let ControlMessage::Inject(bytes) = message {
+                    let _ = inbox_tx.send(SerialiedPacket::from(bytes)).await;
+                }
+            }
+            Ok(())
+        } => result,
+    }
+}
+
+/// Node processes started by the launcher. They are killed when the cluster is dropped.
+#[derive(Debug)]
+pub struct Cluster {
+    _children: Vec<Child>,
+    injectors: HashMap<NodeId, mpsc::Sender<SerialiedPacket>>,
+}
+
+impl Cluster {
+    /// Starts one process of the current executable per node of the topology saved at
+    /// `topology_path`, and waits for all of them to be ready. Every node's reports are sent to
+    /// `report_tx`.
+    pub async fn launch(
+        topology_path: &Path,
+        topology: &Topology,
+        report_tx: mpsc::Sender<NodeEvent>,
+    ) -> io::Result<Self> {
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).await?;
+        let control_addr = listener.local_addr()?;
+        let executable = std::env::current_exe()?;
+
+        let children = topology
+            .nodes
+            .iter()
+            .map(|node| {
+                Command::new(&executable)
+                    .arg("node")
+                    .arg("--id")
+                    .arg(node.id.value().to_string())
+                    .arg("--topology")
+                    .arg(topology_path)
+                    .arg("--control")
+                    .arg(control_addr.to_string())
+                    .stdout(Stdio::null())
+                    .kill_on_drop(true)
+                    .spawn()
+            })
+            .collect::<io::Result<Vec<_>>>()?;
+
+        let mut injectors = HashMap::new();
+        while injectors.len() < topology.nodes.len() {
+            // A node that fails to start (e.g. its port is taken) never connects
+            let (stream, _) = tokio::time::timeout(STARTUP_TIMEOUT, listener.accept())
+                .await
+                .map_err(|_| {
+                    io::Error::new(
+                        io::ErrorKind::TimedOut,
+                        format!(
+                            "Only {} of {} nodes started",
+                            injectors.len(),
+                            topology.nodes.len()
+                        ),
+                    )
+                })??;
+            stream.set_nodelay(true)?;
+            let (mut reader, mut writer) = stream.into_split();
+
+            let node_id = match ControlMessage::read(&mut reader).await? {
+                Some(ControlMessage::Hello(node_id)) => node_id,
+                message => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("Expected hello from node, got {message:?}"),
+                    ));
+                }
+            };
+
+            let report_tx = report_tx.clone();
+            tokio::spawn(async move {
+                while let Ok(Some(message)) = ControlMessage::read(&mut reader).await {
+                    if let ControlMessage::Report(event) = message
+                        && report_tx.send(event).await.is_err()
+                    {
+                        break;
+                    }
+                }
+            });
+
+            let (inject_tx, mut inject_rx) = mpsc::channel::<SerialiedPacket>(32);
+            tokio::spawn(async move {
+                while let Some(packet) = inject_rx.recv().await {
+                    let message = ControlMessage::Inject(packet.as_bytes().to_vec());
+                    if message.write(&mut writer).await.is_err() {
+                        break;
+                    }
+                }
+            });
+
+            injectors.insert(node_id, inject_tx);
+        }
+
+        Ok(Self {
+            _children: children,
+            injectors,
+        })
+    }
+
+    /// Senders injecting packets at every node, as `Network::run_network` returns for in-process
+    /// nodes
+    pub fn injectors(&self) -> &HashMap<NodeId, mpsc::Sender<SerialiedPacket>> {
+        &self.injectors
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{event::EventKind, packet::PacketId};
+
+    #[tokio::test]
+    /// Control messages survive a round trip through a connection
+    async fn test_control_messages() {
+        let (mut client, mut server) = tokio::io::duplex(64);
+        let messages = [
+            ControlMessage::Hello(NodeId::new(3)),
+            ControlMessage::Report(NodeEvent::new(
+                NodeId::new(3),
+                EventKind::Forwarded {
+                    packet_id: PacketId::new(7),
+                    to: NodeId::new(4),
+                },
+            )),
+            ControlMessage::Inject(vec![1, 2, 3]),
+        ];
+
+        for message in &messages {
+            message.write(&mut client).await.unwrap();
+        }
+        drop(client);
+
+        for message in messages {
+            assert_eq!(
+                ControlMessage::read(&mut server).await.unwrap(),
+                Some(message)
+            );
+        }
+        assert_eq!(ControlMessage::read(&mut server).await.unwrap(), None);
+    }
+}
@@ -0,0 +1,368 @@
+use std::{
+    fmt, fs,
+    io::{self, BufRead, BufReader},
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    fixed_point::{Price, Quantity},
+    market::MarketRegistry,
+    order::{Action, Order, Side},
+};
+
+/// Recorded order flow to replay, read from the `[replay]` table of the config
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct ReplayConfig {
+    /// CSV file with a header row, or JSONL file with one record per line, told apart by their
+    /// extension
+    pub file: PathBuf,
+    /// How many times faster than recorded the actions are injected
+    #[serde(default = "ReplayConfig::default_speedup")]
+    pub speedup: f64,
+    /// Nodes injecting the records without an `origin`, in turn. The start node if empty.
+    #[serde(default)]
+    pub origins: Vec<u64>,
+}
+
+impl ReplayConfig {
+    fn default_speedup() -> f64 {
+        1.0
+    }
+
+    /// Delay of an event from the start of the replay
+    pub fn delay(&self, event: &ReplayEvent) -> Duration {
+        event.time.div_f64(self.speedup)
+    }
+}
+
+/// What a record does, placing a new order by default
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RecordAction {
+    #[default]
+    Place,
+    Cancel,
+    Amend,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RecordSide {
+    #[serde(alias = "buy")]
+    Bid,
+    #[serde(alias = "sell")]
+    Ask,
+}
+
+/// Row of a recorded order log. Prices and sizes are decimal strings in the market's quote and
+/// base assets, as in the config, so that they never go through `f64`.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct ReplayRecord {
+    /// Time the action was recorded, in milliseconds from any origin
+    pub time_ms: u64,
+    #[serde(default)]
+    pub action: RecordAction,
+    pub order_id: u64,
+    /// Symbol of the order's market, as configured in `[[markets]]`
+    pub symbol: String,
+    /// Only for placements
+    pub side: Option<RecordSide>,
+    /// Limit price, for placements and amends
+    pub price: Option<String>,
+    /// For placements and amends
+    pub size: Option<String>,
+    /// Id of the node injecting the action
+    pub origin: Option<u64>,
+}
+
+impl ReplayRecord {
+    /// Action recorded, on the market of the registry with the record's symbol. Placements are
+    /// good-till-cancel limit orders.
+    pub fn to_action(&self, markets: &MarketRegistry) -> Result<Action, String> {
+        let market = markets
+            .by_symbol(&self.symbol)
+            .ok_or_else(|| format!("unknown market {}", self.symbol))?;
+        let price = || {
+            let price = self.price.as_deref().ok_or("missing price")?;
+            Price::parse(price, market.tick_size).map_err(|e| format!("price: {e}"))
+        };
+        let quantity = || {
+            let size = self.size.as_deref().ok_or("missing size")?;
+            Quantity::parse(size, market.lot_size).map_err(|e| format!("size: {e}"))
+        };
+
+        Ok(match self.action {
+            RecordAction::Place => {
+                let side = match self.side.ok_or("missing side")? {
+                    RecordSide::Bid => Side::Bid,
+                    RecordSide::Ask => Side::Ask,
+                };
+                Action::Place(Order::new(
+                    self.order_id,
+                    market.id,
+                    side,
+                    price()?,
+                    quantity()?,
+                ))
+            }
+            RecordAction::Cancel => Action::Cancel {
+                market: market.id,
+                order_id: self.order_id,
+            },
+            RecordAction::Amend => Action::Amend {
+                market: market.id,
+                order_id: self.order_id,
+                price: price()?,
+                quantity: quantity()?,
+            },
+        })
+    }
+}
+
+#[derive(Debug)]
+pub enum ReplayError {
+    Io(io::Error),
+    /// Neither a `.csv` nor a `.jsonl` file
+    UnknownFormat(PathBuf),
+    /// Record that can't be read or mapped to an action, `line` counting from 1 with the header
+    InvalidRecord {
+        line: usize,
+        reason: String,
+    },
+}
+
+impl fmt::Display for ReplayError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ReplayError::Io(e) => write!(f, "{e}"),
+            ReplayError::UnknownFormat(path) => write!(
+                f,
+                "can't tell the format of {}, expected a .csv or .jsonl file",
+                path.display()
+            ),
+            ReplayError::InvalidRecord { line, reason } => {
+                write!(f, "invalid record on line {line}: {reason}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for ReplayError {}
+
+impl From<io::Error> for ReplayError {
+    fn from(e: io::Error) -> Self {
+        ReplayError::Io(e)
+    }
+}
+
+/// Recorded action, at its time relative to the first record
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReplayEvent {
+    pub time: Duration,
+    pub origin: Option<u64>,
+    pub action: Action,
+}
+
+/// Format of a recorded order log
+enum Format {
+    Csv,
+    Jsonl,
+}
+
+/// Reads a recorded order log, and returns its actions in the order they were recorded
+pub fn load(path: &Path, markets: &MarketRegistry) -> Result<Vec<ReplayEvent>, ReplayError> {
+    // Told by the extension, before the file is opened
+    let format = match path.extension().and_then(|extension| extension.to_str()) {
+        Some("csv") => Format::Csv,
+        Some("jsonl" | "ndjson") => Format::Jsonl,
+        _ => return Err(ReplayError::UnknownFormat(path.to_path_buf())),
+    };
+    let reader = BufReader::new(fs::File::open(path)?);
+    let records = match format {
+        Format::Csv => read_csv(reader)?,
+        Format::Jsonl => read_jsonl(reader)?,
+    };
+
+    let start = records.iter().map(|(_, record)| record.time_ms).min();
+    let mut events = records
+        .into_iter()
+        .map(|(line, record)| {
+            let action = record
+                .to_action(markets)
+                .map_err(|reason| ReplayError::InvalidRecord { line, reason })?;
+            Ok(ReplayEvent {
+                time: Duration::from_millis(record.time_ms - start.unwrap_or_default()),
+                origin: record.origin,
+                action,
+            })
+        })
+        .collect::<Result<Vec<_>, ReplayError>>()?;
+    // Stable: records with the same time keep their order in the file
+    events.sort_by_key(|event| event.time);
+    Ok(events)
+}
+
+fn read_csv(reader: impl io::Read) -> Result<Vec<(usize, ReplayRecord)>, ReplayError> {
+    let mut reader = csv::ReaderBuilder::new()
+        .trim(csv::Trim::All)
+        .from_reader(reader);
+    reader
+        .deserialize()
+        .enumerate()
+        .map(|(index, record)| {
+            // After the header
+            let line = index + 2;
+            record
+                .map(|record| (line, record))
+                .map_err(|e| ReplayError::InvalidRecord {
+                    line,
+                    reason: e.to_string(),
+                })
+        })
+        .collect()
+}
+
+fn read_jsonl(reader: impl BufRead) -> Result<Vec<(usize, ReplayRecord)>, ReplayError> {
+    let mut records = Vec::new();
+    for (index, line) in reader.lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let record = serde_json::from_str(&line).map_err(|e| ReplayError::InvalidRecord {
+            line: index + 1,
+            reason: e.to_string(),
+        })?;
+        records.push((index + 1, record));
+    }
+    Ok(records)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::market::MarketConfig;
+
+    #[test]
+    fn test_load() {
+        let mut eth_usd = MarketConfig::sol_usd();
+        eth_usd.symbol = "ETH/USD".to_string();
+        let markets = MarketRegistry::new(&[MarketConfig::sol_usd(), eth_usd]).unwrap();
+        let dir = std::env::temp_dir().join(format!("replay-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+
+        let csv = dir.join("orders.csv");
+        fs::write(
+            &csv,
+            "time_ms,action,order_id,symbol,side,price,size,origin\n\
+             1700000000250,place,2,ETH/USD,sell,151.5,2,\n\
+             1700000000000,place,1,SOL/USD,buy,150.25,0.5,7\n\
+             1700000000250,cancel,1,SOL/USD,,,,\n\
+             1700000001000,amend,2,ETH/USD,,151,1,3\n",
+        )
+        .unwrap();
+        let events = load(&csv, &markets).unwrap();
+
+        let sol = markets.by_symbol("SOL/USD").unwrap().id;
+        let eth = markets.by_symbol("ETH/USD").unwrap().id;
+        assert_eq!(
+            events,
+            [
+                ReplayEvent {
+                    time: Duration::ZERO,
+                    origin: Some(7),
+                    action: Action::Place(Order::new(
+                        1,
+                        sol,
+                        Side::Bid,
+                        Price::from_ticks(15_025),
+                        Quantity::from_lots(500)
+                    )),
+                },
+                ReplayEvent {
+                    time: Duration::from_millis(250),
+                    origin: None,
+                    action: Action::Place(Order::new(
+                        2,
+                        eth,
+                        Side::Ask,
+                        Price::from_ticks(15_150),
+                        Quantity::from_lots(2_000)
+                    )),
+                },
+                ReplayEvent {
+                    time: Duration::from_millis(250),
+                    origin: None,
+                    action: Action::Cancel {
+                        market: sol,
+                        order_id: 1
+                    },
+                },
+                ReplayEvent {
+                    time: Duration::from_secs(1),
+                    origin: Some(3),
+                    action: Action::Amend {
+                        market: eth,
+                        order_id: 2,
+                        price: Price::from_ticks(15_100),
+                        quantity: Quantity::from_lots(1_000)
+                    },
+                },
+            ]
+        );
+
+        // Same log in JSONL
+        let jsonl = dir.join("orders.jsonl");
+        fs::write(
+            &jsonl,
+            r#"{"time_ms": 1700000000250, "order_id": 2, "symbol": "ETH/USD", "side": "ask", "price": "151.5", "size": "2"}
+{"time_ms": 1700000000000, "order_id": 1, "symbol": "SOL/USD", "side": "bid", "price": "150.25", "size": "0.5", "origin": 7}
+
+{"time_ms": 1700000000250, "action": "cancel", "order_id": 1, "symbol": "SOL/USD"}
+{"time_ms": 1700000001000, "action": "amend", "order_id": 2, "symbol": "ETH/USD", "price": "151", "size": "1", "origin": 3}
+"#,
+        )
+        .unwrap();
+        assert_eq!(load(&jsonl, &markets).unwrap(), events);
+
+        let speedup = ReplayConfig {
+            file: jsonl.clone(),
+            speedup: 4.0,
+            origins: vec![],
+        };
+        assert_eq!(speedup.delay(&events[3]), Duration::from_millis(250));
+
+        // Invalid records
+        fs::write(
+            &jsonl,
+            r#"{"time_ms": 0, "order_id": 1, "symbol": "BTC/USD", "side": "bid", "price": "1", "size": "1"}"#,
+        )
+        .unwrap();
+        assert_eq!(
+            load(&jsonl, &markets).unwrap_err().to_string(),
+            "invalid record on line 1: unknown market BTC/USD"
+        );
+        fs::write(
+            &csv,
+            "time_ms,order_id,symbol,side,price,size\n0,1,SOL/USD,bid,150.255,1\n",
+        )
+        .unwrap();
+        assert_eq!(
+            load(&csv, &markets).unwrap_err().to_string(),
+            "invalid record on line 2: price: 150.255 is not a multiple of 0.01"
+        );
+        assert!(matches!(
+            load(&dir.join("orders.txt"), &markets),
+            Err(ReplayError::UnknownFormat(_))
+        ));
+        assert!(matches!(
+            load(&dir.join("missing.csv"), &markets),
+            Err(ReplayError::Io(_))
+        ));
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,183 @@
+use std::{collections::BTreeMap, time::Duration};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{hlc::Timestamp, order::Action, packet::PacketId};
+
+/// Sequence in which nodes apply the actions they receive, selected in the config
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SequencingMode {
+    /// Each action is applied as soon as it is received
+    #[default]
+    Arrival,
+    /// Actions are applied by timestamp, once the settlement delay has passed
+    Timestamp,
+}
+
+/// Sequence in which a node applies the actions it receives
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Sequencing {
+    /// In the sequence they were received, which differs from node to node
+    #[default]
+    Arrival,
+    /// By timestamp, then by order id. An action is held until `settlement_delay` after its
+    /// timestamp, so that actions stamped before it but still on their way can be applied first.
+    /// Nodes that receive every action before its settlement apply them all in the same sequence
+    /// and end up with the same books.
+    Timestamp { settlement_delay: Duration },
+}
+
+/// Position of an action in the deterministic sequence. The packet id only breaks the tie between
+/// actions on the same order stamped at the same time by different clocks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+struct SequenceKey {
+    timestamp: Timestamp,
+    order_id: u64,
+    packet_id: PacketId,
+}
+
+impl SequenceKey {
+    fn settlement(&self, settlement_delay: Duration) -> u64 {
+        self.timestamp.physical + settlement_delay.as_millis() as u64
+    }
+}
+
+/// Actions received by a node and waiting for their settlement, see [`Sequencing::Timestamp`]
+#[derive(Debug, Clone)]
+pub struct Sequencer {
+    settlement_delay: Duration,
+    queue: BTreeMap<SequenceKey, Action>,
+    /// Key of the last action released
+    last_released: Option<SequenceKey>,
+    /// Number of actions received after a later one was released
+    late: u64,
+}
+
+impl Sequencer {
+    pub fn new(settlement_delay: Duration) -> Self {
+        Self {
+            settlement_delay,
+            queue: BTreeMap::new(),
+            last_released: None,
+            late: 0,
+        }
+    }
+
+    /// Queues an action until its settlement. An action arriving after a later one was released
+    /// can't take its place in the sequence anymore: it is released as soon as possible and
+    /// counted as late.
+    pub fn push(&mut self, timestamp: Timestamp, packet_id: PacketId, action: Action) {
+        let key = SequenceKey {
+            timestamp,
+            order_id: action.order_id(),
+            packet_id,
+        };
+        if self.last_released.is_some_and(|last| key < last) {
+            self.late += 1;
+        }
+        self.queue.insert(key, action);
+    }
+
+    /// Physical time, in milliseconds since the Unix epoch, at which the next action settles
+    pub fn next_settlement(&self) -> Option<u64> {
+        self.queue
+            .first_key_value()
+            .map(|(key, _)| key.settlement(self.settlement_delay))
+    }
+
+    /// Actions settled at `now` (in milliseconds since the Unix epoch), in sequence, with the id of
+    /// the packet that carried them
+    pub fn release(&mut self, now: u64) -> Vec<(PacketId, Action)> {
+        let mut released = Vec::new();
+        while let Some(entry) = self.queue.first_entry() {
+            if entry.key().settlement(self.settlement_delay) > now {
+                break;
+            }
+            let (key, action) = entry.remove_entry();
+            self.last_released = self.last_released.max(Some(key));
+            released.push((key.packet_id, action));
+        }
+        released
+    }
+
+    /// Number of actions received too late to be applied in sequence
+    pub fn late(&self) -> u64 {
+        self.late
+    }
+
+    pub fn len(&self) -> usize {
+        self.queue.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.queue.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{fixed_point::Price, market::MarketRegistry, order::Order};
+
+    #[test]
+    fn test_sequencer() {
+        let markets = MarketRegistry::default();
+        let order = |id| Order {
+            id,
+            price: Price::from_ticks(15_000),
+            ..markets.random_order()
+        };
+        let ids = |released: Vec<(PacketId, Action)>| -> Vec<u64> {
+            released
+                .iter()
+                .map(|(_, action)| action.order_id())
+                .collect()
+        };
+
+        let mut sequencer = Sequencer::new(Duration::from_millis(100));
+        assert_eq!(sequencer.next_settlement(), None);
+
+        // Received in a different sequence than they were stamped
+        sequencer.push(
+            Timestamp::new(1_000, 1),
+            PacketId::new(1),
+            Action::Place(order(1)),
+        );
+        sequencer.push(
+            Timestamp::new(1_000, 0),
+            PacketId::new(2),
+            Action::Place(order(2)),
+        );
+        sequencer.push(
+            Timestamp::new(1_050, 0),
+            PacketId::new(3),
+            Action::Place(order(3)),
+        );
+        // Same timestamp as the first one, from another clock: the order id decides
+        sequencer.push(
+            Timestamp::new(1_000, 1),
+            PacketId::new(4),
+            Action::Place(order(0)),
+        );
+        assert_eq!(sequencer.len(), 4);
+        assert_eq!(sequencer.next_settlement(), Some(1_100));
+
+        assert!(sequencer.release(1_099).is_empty());
+        let released = sequencer.release(1_100);
+        assert_eq!(released[0].0, PacketId::new(2));
+        assert_eq!(ids(released), [2, 0, 1]);
+        assert_eq!(sequencer.next_settlement(), Some(1_150));
+
+        // Stamped before a released action: can't be applied in sequence anymore
+        sequencer.push(
+            Timestamp::new(999, 0),
+            PacketId::new(5),
+            Action::Place(order(5)),
+        );
+        assert_eq!(sequencer.late(), 1);
+        assert_eq!(ids(sequencer.release(1_150)), [5, 3]);
+        assert!(sequencer.is_empty());
+        assert_eq!(sequencer.late(), 1);
+    }
+}
@@ -0,0 +1,142 @@
+use std::fmt;
+
+use ed25519_dalek::{Signature, Signer, SigningKey, Verifier, VerifyingKey};
+use serde::{Deserialize, Serialize};
+
+use crate::packet::SerialiedPacket;
+
+/// Size of the block carried by signed packets: the trader's public key followed by the signature
+pub const SIGNATURE_BLOCK_SIZE: usize = PUBLIC_KEY_SIZE + SIGNATURE_SIZE;
+
+const PUBLIC_KEY_SIZE: usize = ed25519_dalek::PUBLIC_KEY_LENGTH;
+const SIGNATURE_SIZE: usize = ed25519_dalek::SIGNATURE_LENGTH;
+
+/// Key of the trader placing the orders. Traders are identified by their public key, carried in
+/// every packet next to the signature, so nodes don't need to know them in advance.
+pub fn generate_trader_key() -> SigningKey {
+    SigningKey::from_bytes(&rand::random())
+}
+
+/// Signs an encoded order, returns the block to write in the packet
+pub fn sign(key: &SigningKey, order: &[u8]) -> [u8; SIGNATURE_BLOCK_SIZE] {
+    let mut block = [0; SIGNATURE_BLOCK_SIZE];
+    block[..PUBLIC_KEY_SIZE].copy_from_slice(key.verifying_key().as_bytes());
+    block[PUBLIC_KEY_SIZE..].copy_from_slice(&key.sign(order).to_bytes());
+    block
+}
+
+/// Checks the signature of a packet whose envelope is valid
+pub fn verify(packet: &SerialiedPacket) -> Result<(), SignatureError> {
+    let block = packet.signature_block().ok_or(SignatureError::Unsigned)?;
+
+    let public_key = VerifyingKey::from_bytes(block[..PUBLIC_KEY_SIZE].try_into().unwrap())
+        .map_err(|_| SignatureError::InvalidPublicKey)?;
+    let signature = Signature::from_bytes(block[PUBLIC_KEY_SIZE..].try_into().unwrap());
+
+    public_key
+        .verify(&packet.payload(), &signature)
+        .map_err(|_| SignatureError::Invalid)
+}
+
+/// Which packets a node checks the signature of, selected in the config
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum VerificationMode {
+    /// Signatures are ignored
+    #[default]
+    None,
+    /// Every new packet is verified
+    All,
+    /// A random sample of the new packets is verified
+    Sample,
+}
+
+/// Verification done by a node before processing and forwarding a packet
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum Verification {
+    #[default]
+    None,
+    All,
+    /// Each new packet is verified with this probability. Unsigned packets are always rejected.
+    Sample(f64),
+}
+
+impl Verification {
+    pub fn check(&self, packet: &SerialiedPacket) -> Result<(), SignatureError> {
+        match self {
+            Verification::None => Ok(()),
+            Verification::All => verify(packet),
+            Verification::Sample(rate) => {
+                if packet.signature_block().is_none() {
+                    Err(SignatureError::Unsigned)
+                } else if rand::random_bool(*rate) {
+                    verify(packet)
+                } else {
+                    Ok(())
+                }
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SignatureError {
+    /// The packet doesn't carry a signature
+    Unsigned,
+    /// The bytes of the public key aren't a valid Ed25519 point
+    InvalidPublicKey,
+    /// The order was not signed by this key, or it was modified
+    Invalid,
+}
+
+impl fmt::Display for SignatureError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SignatureError::Unsigned => write!(f, "unsigned packet"),
+            SignatureError::InvalidPublicKey => write!(f, "invalid public key"),
+            SignatureError::Invalid => write!(f, "invalid signature"),
+        }
+    }
+}
+
+impl std::error::Error for SignatureError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{network::NodeId, packet::PacketId};
+
+    #[test]
+    fn test_signature() {
+        let key = generate_trader_key();
+        let order = [1, 2, 3, 4];
+        let packet =
+            SerialiedPacket::new_signed(PacketId::new(1), &NodeId::new(1), 3, &order, &key);
+        assert!(packet.envelope().is_ok());
+        assert_eq!(verify(&packet), Ok(()));
+
+        // Forwarding keeps the signature valid
+        let forwarded = packet.clone().forwarded(&NodeId::new(2), 2);
+        assert_eq!(verify(&forwarded), Ok(()));
+
+        // A node tampering with the order is detected
+        let mut tampered = packet.as_bytes().to_vec();
+        *tampered.last_mut().unwrap() ^= 1;
+        let tampered = SerialiedPacket::from(tampered).forwarded(&NodeId::new(2), 2);
+        assert!(tampered.envelope().is_ok());
+        assert_eq!(verify(&tampered), Err(SignatureError::Invalid));
+
+        let unsigned = SerialiedPacket::new(PacketId::new(1), &NodeId::new(1), 3, &order);
+        assert_eq!(verify(&unsigned), Err(SignatureError::Unsigned));
+        assert_eq!(Verification::None.check(&unsigned), Ok(()));
+        assert_eq!(
+            Verification::Sample(0.0).check(&unsigned),
+            Err(SignatureError::Unsigned)
+        );
+        assert_eq!(Verification::Sample(0.0).check(&tampered), Ok(()));
+        assert_eq!(
+            Verification::Sample(1.0).check(&tampered),
+            Err(SignatureError::Invalid)
+        );
+    }
+}
@@ -0,0 +1,148 @@
+use std::{
+    collections::HashMap,
+    fs,
+    io::{self, Write},
+    net::{Ipv4Addr, SocketAddr},
+    path::Path,
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    network::{Network, NodeId},
+    transport::quic::QuicIdentity,
+};
+
+/// Description of a network shared by all the processes running its nodes: where each node
+/// listens and who its neighbors are.
+#[derive(Debug, PartialEq, Deserialize, Serialize)]
+pub struct Topology {
+    pub nodes: Vec<NodeEntry>,
+    /// Certificate shared by every QUIC endpoint, if the network runs over QUIC
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub quic_identity: Option<QuicIdentityEntry>,
+}
+
+#[derive(Debug, PartialEq, Deserialize, Serialize)]
+pub struct NodeEntry {
+    pub id: NodeId,
+    pub addr: SocketAddr,
+    pub neighbors: Vec<NodeId>,
+}
+
+/// DER encoded self-signed certificate and its PKCS #8 private key
+#[derive(Debug, PartialEq, Deserialize, Serialize)]
+pub struct QuicIdentityEntry {
+    cert: Vec<u8>,
+    key: Vec<u8>,
+}
+
+impl Topology {
+    /// Assigns consecutive loopback ports, starting at `base_port`, to the nodes of `network`
+    pub fn new(
+        network: &Network,
+        base_port: u16,
+        quic_identity: Option<&QuicIdentity>,
+    ) -> io::Result<Self> {
+        let mut node_ids: Vec<NodeId> = network.nodes().into_iter().collect();
+        node_ids.sort_by_key(NodeId::value);
+
+        let nodes = node_ids
+            .into_iter()
+            .enumerate()
+            .map(|(i, id)| {
+                let port = u16::try_from(i)
+                    .ok()
+                    .and_then(|i| base_port.checked_add(i))
+                    .ok_or_else(|| {
+                        io::Error::new(
+                            io::ErrorKind::InvalidInput,
+                            format!("Not enough ports after {base_port} for every node"),
+                        )
+                    })?;
+                let mut neighbors: Vec<NodeId> =
+                    network.neighbors(id.clone()).into_iter().collect();
+                neighbors.sort_by_key(NodeId::value);
+
+                Ok(NodeEntry {
+                    id,
+                    addr: SocketAddr::from((Ipv4Addr::LOCALHOST, port)),
+                    neighbors,
+                })
+            })
+            .collect::<io::Result<_>>()?;
+
+        Ok(Self {
+            nodes,
+            quic_identity: quic_identity.map(|identity| QuicIdentityEntry {
+                cert: identity.cert_der().to_vec(),
+                key: identity.key_der().to_vec(),
+            }),
+        })
+    }
+
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let file = fs::File::open(path)?;
+        serde_json::from_reader(io::BufReader::new(file)).map_err(io::Error::other)
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let mut writer = io::BufWriter::new(fs::File::create(path)?);
+        serde_json::to_writer(&mut writer, self).map_err(io::Error::other)?;
+        // Dropping the writer would flush it too, but swallow the error
+        writer.flush()
+    }
+
+    pub fn node(&self, id: &NodeId) -> Option<&NodeEntry> {
+        self.nodes.iter().find(|node| &node.id == id)
+    }
+
+    /// Listening addresses of the neighbors of `id`
+    pub fn peers(&self, id: &NodeId) -> HashMap<NodeId, SocketAddr> {
+        let addrs: HashMap<&NodeId, SocketAddr> = self
+            .nodes
+            .iter()
+            .map(|node| (&node.id, node.addr))
+            .collect();
+
+        self.node(id)
+            .map(|node| {
+                node.neighbors
+                    .iter()
+                    .filter_map(|n| Some((n.clone(), *addrs.get(n)?)))
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    pub fn quic_identity(&self) -> Option<QuicIdentity> {
+        self.quic_identity
+            .as_ref()
+            .map(|entry| QuicIdentity::from_der(entry.cert.clone(), entry.key.clone()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    /// A topology survives a round trip through its file
+    fn test_topology_file() {
+        let network = Network::generate_network(10, 3);
+        let identity = QuicIdentity::generate().unwrap();
+        let topology = Topology::new(&network, 40_000, Some(&identity)).unwrap();
+
+        assert_eq!(topology.nodes.len(), 10);
+        assert_eq!(topology.peers(&NodeId::new(0)).len(), 3);
+        assert_eq!(topology.quic_identity(), Some(identity));
+
+        let path = std::env::temp_dir().join("order-propagation-test-topology.json");
+        topology.save(&path).unwrap();
+        assert_eq!(Topology::load(&path).unwrap(), topology);
+        fs::remove_file(path).unwrap();
+
+        // Ports must not overflow
+        assert!(Topology::new(&network, u16::MAX - 5, None).is_err());
+    }
+}
@@ -0,0 +1,203 @@
+use std::{fmt, future::Future, io, net::SocketAddr, time::Duration};
+
+use serde::{Deserialize, Serialize};
+use tokio::sync::mpsc;
+
+use crate::{
+    network::NodeId,
+    packet::{PacketId, SerialiedPacket},
+};
+
+pub mod memory;
+pub mod quic;
+pub mod sim;
+pub mod socket;
+pub mod tcp;
+pub mod udp;
+
+/// Backend used to carry packets between nodes
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum TransportKind {
+    /// In-process tokio channels
+    #[default]
+    Mpsc,
+    /// In-process links driven by a scheduler on a virtual clock
+    Sim,
+    /// One UDP socket per node on the loopback interface
+    Udp,
+    /// One persistent, length-prefixed TCP connection per neighbor on the loopback interface
+    Tcp,
+    /// QUIC connections between neighbors, one unidirectional stream per packet
+    QuicStream,
+    /// QUIC connections between neighbors, one datagram per packet
+    QuicDatagram,
+}
+
+impl TransportKind {
+    /// Whether the transport expects tokio's clock to be paused, so that time only advances when
+    /// every task is idle
+    pub fn uses_virtual_clock(&self) -> bool {
+        matches!(self, TransportKind::Sim)
+    }
+
+    /// Largest packet the transport sends, or `None` for the in-process ones, which have no limit
+    pub fn max_packet_size(&self) -> Option<usize> {
+        match self {
+            TransportKind::Mpsc | TransportKind::Sim => None,
+            TransportKind::Udp => Some(udp::MAX_DATAGRAM_SIZE),
+            TransportKind::Tcp => Some(tcp::MAX_FRAME_SIZE),
+            TransportKind::QuicStream => Some(quic::MAX_STREAM_SIZE),
+            TransportKind::QuicDatagram => Some(quic::MIN_DATAGRAM_SIZE),
+        }
+    }
+}
+
+/// Everything a node needs to exchange packets with its neighbors. The gossip logic only goes
+/// through this trait, so it runs unchanged on every backend.
+pub trait Transport: Send + 'static {
+    /// Queues `packet` for delivery to `peer`. The link's latency is applied by the transport,
+    /// this returns without waiting for the packet to be delivered.
+    fn send(
+        &self,
+        peer: &NodeId,
+        packet: SerialiedPacket,
+    ) -> impl Future<Output = Result<(), TransportError>> + Send;
+
+    /// Next packet received by the node, or `None` once the transport is closed
+    fn recv(&mut self) -> impl Future<Output = Option<SerialiedPacket>> + Send;
+
+    /// Packets the transport couldn't send after [`Transport::send`] returned, once the link's
+    /// latency elapsed. Only the first call returns them, and transports that report every failure
+    /// from `send` return `None`.
+    fn take_failures(&mut self) -> Option<mpsc::UnboundedReceiver<SendFailure>> {
+        None
+    }
+
+    /// Address neighbors use to reach this node
+    fn local_addr(&self) -> LocalAddr;
+}
+
+/// Address of a node on its transport
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LocalAddr {
+    /// Node living in the same process, addressed by its id
+    InProcess(NodeId),
+    Socket(SocketAddr),
+}
+
+impl fmt::Display for LocalAddr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LocalAddr::InProcess(node_id) => write!(f, "in-process {node_id:?}"),
+            LocalAddr::Socket(addr) => write!(f, "{addr}"),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum TransportError {
+    /// The receiving end is gone
+    Closed,
+    /// The peer isn't a neighbor of this node
+    UnknownPeer(NodeId),
+    /// The packet doesn't fit in a single frame/datagram of the transport
+    TooLarge {
+        size: usize,
+        max: usize,
+    },
+    Io(io::Error),
+}
+
+impl fmt::Display for TransportError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TransportError::Closed => write!(f, "channel closed"),
+            TransportError::UnknownPeer(node_id) => write!(f, "unknown peer {node_id:?}"),
+            TransportError::TooLarge { size, max } => {
+                write!(
+                    f,
+                    "packet of {size} bytes exceeds the maximum of {max} bytes"
+                )
+            }
+            TransportError::Io(e) => write!(f, "I/O error: {e}"),
+        }
+    }
+}
+
+impl std::error::Error for TransportError {}
+
+impl From<io::Error> for TransportError {
+    fn from(e: io::Error) -> Self {
+        TransportError::Io(e)
+    }
+}
+
+/// Packet a transport couldn't send to a neighbor
+#[derive(Debug)]
+pub struct SendFailure {
+    pub peer: NodeId,
+    pub packet_id: PacketId,
+    pub error: TransportError,
+}
+
+/// Reports the packets a link to `peer` dropped after [`Transport::send`] returned, e.g. from the
+/// task writing to its connection
+#[derive(Debug, Clone)]
+pub struct FailureSender {
+    peer: NodeId,
+    tx: mpsc::UnboundedSender<SendFailure>,
+}
+
+impl FailureSender {
+    pub fn send(&self, packet_id: PacketId, error: TransportError) {
+        let _ = self.tx.send(SendFailure {
+            peer: self.peer.clone(),
+            packet_id,
+            error,
+        });
+    }
+}
+
+/// Failures of the sends running after their delay, handed back to the node that sent the
+/// packets
+#[derive(Debug)]
+struct SendFailures {
+    tx: mpsc::UnboundedSender<SendFailure>,
+    rx: Option<mpsc::UnboundedReceiver<SendFailure>>,
+}
+
+impl SendFailures {
+    fn new() -> Self {
+        let (tx, rx) = mpsc::unbounded_channel();
+        Self { tx, rx: Some(rx) }
+    }
+
+    /// Reports the failures of the link to `peer`
+    fn sender(&self, peer: NodeId) -> FailureSender {
+        FailureSender {
+            peer,
+            tx: self.tx.clone(),
+        }
+    }
+
+    /// Spawns a task that waits for `latency` and then runs `send`, so that the emulated network
+    /// delay doesn't block the node. A failed send drops the packet and is queued for
+    /// [`Transport::take_failures`].
+    fn send_with_delay<F>(&self, peer: NodeId, packet_id: PacketId, latency: Duration, send: F)
+    where
+        F: Future<Output = Result<(), TransportError>> + Send + 'static,
+    {
+        let failures = self.sender(peer);
+        tokio::spawn(async move {
+            tokio::time::sleep(latency).await;
+            if let Err(error) = send.await {
+                failures.send(packet_id, error);
+            }
+        });
+    }
+
+    fn take(&mut self) -> Option<mpsc::UnboundedReceiver<SendFailure>> {
+        self.rx.take()
+    }
+}
@@ -0,0 +1,69 @@
+use std::{collections::HashMap, time::Duration};
+
+use tokio::sync::mpsc;
+
+use crate::{
+    network::NodeId,
+    packet::SerialiedPacket,
+    transport::{LocalAddr, SendFailure, SendFailures, Transport, TransportError},
+};
+
+/// In-process transport: neighbors' inboxes are tokio channels, the latency is emulated by
+/// sleeping before writing to them.
+#[derive(Debug)]
+pub struct MemoryTransport {
+    node_id: NodeId,
+    latency: Duration,
+    inbox: mpsc::Receiver<SerialiedPacket>,
+    peers: HashMap<NodeId, mpsc::Sender<SerialiedPacket>>,
+    failures: SendFailures,
+}
+
+impl MemoryTransport {
+    pub fn new(
+        node_id: NodeId,
+        latency: Duration,
+        inbox: mpsc::Receiver<SerialiedPacket>,
+        peers: HashMap<NodeId, mpsc::Sender<SerialiedPacket>>,
+    ) -> Self {
+        Self {
+            node_id,
+            latency,
+            inbox,
+            peers,
+            failures: SendFailures::new(),
+        }
+    }
+}
+
+impl Transport for MemoryTransport {
+    async fn send(&self, peer: &NodeId, packet: SerialiedPacket) -> Result<(), TransportError> {
+        let sender = self
+            .peers
+            .get(peer)
+            .ok_or_else(|| TransportError::UnknownPeer(peer.clone()))?
+            .clone();
+
+        let packet_id = packet.id();
+        self.failures
+            .send_with_delay(peer.clone(), packet_id, self.latency, async move {
+                sender
+                    .send(packet)
+                    .await
+                    .map_err(|_| TransportError::Closed)
+            });
+        Ok(())
+    }
+
+    async fn recv(&mut self) -> Option<SerialiedPacket> {
+        self.inbox.recv().await
+    }
+
+    fn take_failures(&mut self) -> Option<mpsc::UnboundedReceiver<SendFailure>> {
+        self.failures.take()
+    }
+
+    fn local_addr(&self) -> LocalAddr {
+        LocalAddr::InProcess(self.node_id.clone())
+    }
+}
@@ -0,0 +1,276 @@
+use std::{io, net::SocketAddr, sync::Arc, time::Duration};
+
+use quinn::{
+    ClientConfig, Connection, Endpoint, ServerConfig,
+    rustls::{
+        RootCertStore,
+        pki_types::{CertificateDer, PrivateKeyDer, PrivatePkcs8KeyDer},
+    },
+};
+use tokio::sync::mpsc;
+
+use crate::{
+    packet::SerialiedPacket,
+    transport::{FailureSender, TransportError},
+};
+
+/// Largest packet accepted on a unidirectional stream
+pub const MAX_STREAM_SIZE: usize = 64 * 1024;
+
+/// Largest datagram a connection always accepts: quinn's initial MTU of 1200 bytes, minus the
+/// QUIC headers, the encryption tag and the datagram frame header. Path MTU discovery can raise it.
+pub const MIN_DATAGRAM_SIZE: usize = 1162;
+
+/// Name the self-signed certificate is issued for, and that clients expect
+const SERVER_NAME: &str = "localhost";
+
+/// Number of times a writer tries to (re)connect before dropping a packet
+const MAX_CONNECT_ATTEMPTS: u32 = 5;
+
+/// Delay before the first reconnection attempt, doubled after each failure
+const INITIAL_BACKOFF: Duration = Duration::from_millis(10);
+
+/// Number of packets that can be queued on a connection before senders wait
+const OUTBOX_SIZE: usize = 32;
+
+/// How a gossip packet travels on a QUIC connection
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum QuicMode {
+    /// Each packet on its own unidirectional stream (reliable, no head-of-line blocking between packets)
+    Stream,
+    /// Each packet as an unreliable datagram
+    Datagram,
+}
+
+/// Self-signed certificate generated at startup, shared by every node of the network
+#[derive(Debug, PartialEq)]
+pub struct QuicIdentity {
+    cert: CertificateDer<'static>,
+    key: PrivatePkcs8KeyDer<'static>,
+}
+
+impl QuicIdentity {
+    pub fn generate() -> io::Result<Self> {
+        let certified = rcgen::generate_simple_self_signed(vec![SERVER_NAME.to_string()])
+            .map_err(io::Error::other)?;
+
+        Ok(Self {
+            cert: certified.cert.der().clone(),
+            key: PrivatePkcs8KeyDer::from(certified.signing_key.serialize_der()),
+        })
+    }
+
+    /// Rebuilds an identity from its DER encoded certificate and PKCS #8 private key
+    pub fn from_der(cert: Vec<u8>, key: Vec<u8>) -> Self {
+        Self {
+            cert: CertificateDer::from(cert),
+            key: PrivatePkcs8KeyDer::from(key),
+        }
+    }
+
+    pub fn cert_der(&self) -> &[u8] {
+        &self.cert
+    }
+
+    pub fn key_der(&self) -> &[u8] {
+        self.key.secret_pkcs8_der()
+    }
+
+    fn server_config(&self) -> io::Result<ServerConfig> {
+        ServerConfig::with_single_cert(
+            vec![self.cert.clone()],
+            PrivateKeyDer::Pkcs8(self.key.clone_key()),
+        )
+        .map_err(io::Error::other)
+    }
+
+    fn client_config(&self) -> io::Result<ClientConfig> {
+        let mut roots = RootCertStore::empty();
+        roots.add(self.cert.clone()).map_err(io::Error::other)?;
+        ClientConfig::with_root_certificates(Arc::new(roots)).map_err(io::Error::other)
+    }
+}
+
+/// A node's endpoint, acting both as server for its neighbors and as client towards them
+pub fn bind(identity: &QuicIdentity, addr: SocketAddr) -> io::Result<Endpoint> {
+    let mut endpoint = Endpoint::server(identity.server_config()?, addr)?;
+    endpoint.set_default_client_config(identity.client_config()?);
+    Ok(endpoint)
+}
+
+/// Accepts connections from neighbors and forwards their packets, received either on streams or
+/// as datagrams, to the node's inbox.
+pub async fn accept_loop(endpoint: Endpoint, inbox: mpsc::Sender<SerialiedPacket>) {
+    while let Some(incoming) = endpoint.accept().await {
+        let inbox = inbox.clone();
+        tokio::spawn(async move {
+            match incoming.await {
+                Ok(connection) => {
+                    tokio::spawn(stream_reader(connection.clone(), inbox.clone()));
+                    tokio::spawn(datagram_reader(connection, inbox));
+                }
+                Err(e) => eprintln!("Failed to accept connection: {e}"),
+            }
+        });
+    }
+}
+
+async fn stream_reader(connection: Connection, inbox: mpsc::Sender<SerialiedPacket>) {
+    while let Ok(mut stream) = connection.accept_uni().await {
+        let inbox = inbox.clone();
+        tokio::spawn(async move {
+            match stream.read_to_end(MAX_STREAM_SIZE).await {
+                Ok(payload) => {
+                    let _ = inbox.send(SerialiedPacket::from(payload)).await;
+                }
+                Err(e) => eprintln!("Failed to read stream: {e}"),
+            }
+        });
+    }
+}
+
+async fn datagram_reader(connection: Connection, inbox: mpsc::Sender<SerialiedPacket>) {
+    while let Ok(datagram) = connection.read_datagram().await {
+        if inbox.send(SerialiedPacket::from(datagram)).await.is_err() {
+            break;
+        }
+    }
+}
+
+/// Sending half of a QUIC link. Packets are queued to a task owning the connection to the
+/// neighbor, which is opened with the first packet and kept for the following ones.
+#[derive(Debug, Clone)]
+pub struct QuicPeer {
+    outbox: mpsc::Sender<SerialiedPacket>,
+}
+
+impl QuicPeer {
+    /// Spawns the connection task, which reports the packets it drops to `failures`
+    pub fn connect(
+        endpoint: Endpoint,
+        addr: SocketAddr,
+        mode: QuicMode,
+        failures: FailureSender,
+    ) -> Self {
+        let (outbox, rx) = mpsc::channel(OUTBOX_SIZE);
+        tokio::spawn(connection_writer(endpoint, addr, mode, rx, failures));
+        Self { outbox }
+    }
+
+    pub async fn send(&self, packet: SerialiedPacket) -> Result<(), TransportError> {
+        let size = packet.as_bytes().len();
+        if size > MAX_STREAM_SIZE {
+            return Err(TransportError::TooLarge {
+                size,
+                max: MAX_STREAM_SIZE,
+            });
+        }
+
+        self.outbox
+            .send(packet)
+            .await
+            .map_err(|_| TransportError::Closed)
+    }
+}
+
+async fn connection_writer(
+    endpoint: Endpoint,
+    addr: SocketAddr,
+    mode: QuicMode,
+    mut outbox: mpsc::Receiver<SerialiedPacket>,
+    failures: FailureSender,
+) {
+    let mut connection: Option<Connection> = None;
+
+    while let Some(packet) = outbox.recv().await {
+        let mut backoff = INITIAL_BACKOFF;
+        let mut error = None;
+
+        for attempt in 1..=MAX_CONNECT_ATTEMPTS {
+            let conn = match connection.as_ref() {
+                Some(conn) if conn.close_reason().is_none() => conn,
+                _ => match connect(&endpoint, addr).await {
+                    Ok(conn) => connection.insert(conn),
+                    Err(e) => {
+                        eprintln!("Failed to connect to {addr} (attempt {attempt}): {e}");
+                        error = Some(TransportError::Io(e));
+                        tokio::time::sleep(backoff).await;
+                        backoff *= 2;
+                        continue;
+                    }
+                },
+            };
+
+            match send_packet(conn, mode, packet.clone(), &failures) {
+                Ok(()) => {
+                    error = None;
+                    break;
+                }
+                // Retrying on another connection wouldn't make it fit
+                Err(e @ TransportError::TooLarge { .. }) => {
+                    error = Some(e);
+                    break;
+                }
+                Err(e) => {
+                    eprintln!("Failed to send to {addr}: {e}");
+                    error = Some(e);
+                    connection = None;
+                }
+            }
+        }
+
+        if let Some(e) = error {
+            eprintln!("Dropping packet to {addr}: {e}");
+            failures.send(packet.id(), e);
+        }
+    }
+}
+
+async fn connect(endpoint: &Endpoint, addr: SocketAddr) -> io::Result<Connection> {
+    let connecting = endpoint
+        .connect(addr, SERVER_NAME)
+        .map_err(io::Error::other)?;
+    connecting.await.map_err(io::Error::other)
+}
+
+/// Sends one packet without waiting for it to be delivered, so that packets don't block each other.
+/// A stream failing once opened reports its packet to `failures`.
+fn send_packet(
+    connection: &Connection,
+    mode: QuicMode,
+    packet: SerialiedPacket,
+    failures: &FailureSender,
+) -> Result<(), TransportError> {
+    match mode {
+        QuicMode::Stream => {
+            let connection = connection.clone();
+            let failures = failures.clone();
+            tokio::spawn(async move {
+                let packet_id = packet.id();
+                let result = async {
+                    let mut stream = connection.open_uni().await.map_err(io::Error::other)?;
+                    stream.write_all(packet.as_bytes()).await?;
+                    stream.finish().map_err(io::Error::other)
+                }
+                .await;
+
+                if let Err(e) = result {
+                    eprintln!("Failed to send packet on stream: {e}");
+                    failures.send(packet_id, TransportError::Io(e));
+                }
+            });
+            Ok(())
+        }
+        QuicMode::Datagram => {
+            let size = packet.as_bytes().len();
+            let max = connection.max_datagram_size().unwrap_or(0);
+            if size > max {
+                return Err(TransportError::TooLarge { size, max });
+            }
+
+            connection
+                .send_datagram(packet.into_bytes())
+                .map_err(|e| TransportError::Io(io::Error::other(e)))
+        }
+    }
+}
@@ -0,0 +1,179 @@
+use std::{
+    cmp::Ordering,
+    collections::{BinaryHeap, HashMap, HashSet},
+    time::Duration,
+};
+
+use tokio::{sync::mpsc, time::Instant};
+
+use crate::{
+    network::NodeId,
+    packet::SerialiedPacket,
+    transport::{LocalAddr, Transport, TransportError},
+};
+
+/// Simulated network: a single scheduler task delivers every packet to its destination once the
+/// link's latency has elapsed.
+///
+/// It is meant to run with tokio's clock paused (`start_paused`): time then only advances when
+/// every node is idle, so processing takes no (virtual) time and only the link latency is measured.
+///
+/// Links can also corrupt packets: each delivered packet has one random bit flipped with
+/// probability `bit_flip_probability`.
+#[derive(Debug, Clone)]
+pub struct SimNetwork {
+    latency: Duration,
+    scheduler: mpsc::UnboundedSender<Scheduled>,
+}
+
+impl SimNetwork {
+    /// Starts the scheduler delivering packets into the nodes' `inboxes`
+    pub fn start(
+        latency: Duration,
+        bit_flip_probability: f64,
+        inboxes: HashMap<NodeId, mpsc::Sender<SerialiedPacket>>,
+    ) -> Self {
+        let (scheduler, rx) = mpsc::unbounded_channel();
+        tokio::spawn(scheduler_task(rx, bit_flip_probability, inboxes));
+        Self { latency, scheduler }
+    }
+
+    fn schedule(&self, to: NodeId, packet: SerialiedPacket) -> Result<(), TransportError> {
+        self.scheduler
+            .send(Scheduled {
+                deliver_at: Instant::now() + self.latency,
+                seq: 0,
+                to,
+                packet,
+            })
+            .map_err(|_| TransportError::Closed)
+    }
+}
+
+/// A packet waiting in the scheduler
+#[derive(Debug)]
+struct Scheduled {
+    deliver_at: Instant,
+    /// Tie-breaker so that packets due at the same instant are delivered in the order they were sent
+    seq: u64,
+    to: NodeId,
+    packet: SerialiedPacket,
+}
+
+impl PartialEq for Scheduled {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Scheduled {}
+
+impl PartialOrd for Scheduled {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Scheduled {
+    /// Reversed so that the max-heap pops the earliest packet first
+    fn cmp(&self, other: &Self) -> Ordering {
+        (other.deliver_at, other.seq).cmp(&(self.deliver_at, self.seq))
+    }
+}
+
+async fn scheduler_task(
+    mut rx: mpsc::UnboundedReceiver<Scheduled>,
+    bit_flip_probability: f64,
+    inboxes: HashMap<NodeId, mpsc::Sender<SerialiedPacket>>,
+) {
+    let mut queue = BinaryHeap::new();
+    let mut next_seq = 0;
+
+    loop {
+        let next_delivery = queue.peek().map(|s: &Scheduled| s.deliver_at);
+
+        tokio::select! {
+            scheduled = rx.recv() => match scheduled {
+                Some(mut scheduled) => {
+                    scheduled.seq = next_seq;
+                    next_seq += 1;
+                    queue.push(scheduled);
+                }
+                None => break,
+            },
+            _ = tokio::time::sleep_until(next_delivery.unwrap_or_else(Instant::now)), if next_delivery.is_some() => {
+                while let Some(scheduled) = queue.peek() {
+                    if scheduled.deliver_at > Instant::now() {
+                        break;
+                    }
+
+                    let scheduled = queue.pop().unwrap();
+                    let packet = if rand::random_bool(bit_flip_probability) {
+                        flip_random_bit(scheduled.packet)
+                    } else {
+                        scheduled.packet
+                    };
+                    if let Some(inbox) = inboxes.get(&scheduled.to) {
+                        // The node may have shut down, the packet is then lost
+                        let _ = inbox.send(packet).await;
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Corrupts the packet like a noisy link would. Other receivers of the same packet are unaffected.
+fn flip_random_bit(packet: SerialiedPacket) -> SerialiedPacket {
+    let mut bytes = packet.as_bytes().to_vec();
+    if bytes.is_empty() {
+        return packet;
+    }
+
+    let bit = rand::random_range(0..bytes.len() * 8);
+    bytes[bit / 8] ^= 1 << (bit % 8);
+    SerialiedPacket::from(bytes)
+}
+
+/// Node's side of the simulated network
+#[derive(Debug)]
+pub struct SimTransport {
+    node_id: NodeId,
+    neighbors: HashSet<NodeId>,
+    inbox: mpsc::Receiver<SerialiedPacket>,
+    network: SimNetwork,
+}
+
+impl SimTransport {
+    pub fn new(
+        node_id: NodeId,
+        neighbors: HashSet<NodeId>,
+        inbox: mpsc::Receiver<SerialiedPacket>,
+        network: SimNetwork,
+    ) -> Self {
+        Self {
+            node_id,
+            neighbors,
+            inbox,
+            network,
+        }
+    }
+}
+
+impl Transport for SimTransport {
+    async fn send(&self, peer: &NodeId, packet: SerialiedPacket) -> Result<(), TransportError> {
+        if !self.neighbors.contains(peer) {
+            return Err(TransportError::UnknownPeer(peer.clone()));
+        }
+
+        self.network.schedule(peer.clone(), packet)
+    }
+
+    async fn recv(&mut self) -> Option<SerialiedPacket> {
+        self.inbox.recv().await
+    }
+
+    fn local_addr(&self) -> LocalAddr {
+        LocalAddr::InProcess(self.node_id.clone())
+    }
+}
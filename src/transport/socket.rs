@@ -0,0 +1,193 @@
+use std::{collections::HashMap, io, net::SocketAddr, sync::Arc, time::Duration};
+
+use tokio::sync::mpsc;
+
+use crate::{
+    network::NodeId,
+    packet::SerialiedPacket,
+    transport::{
+        LocalAddr, SendFailure, SendFailures, Transport, TransportError, TransportKind,
+        quic::{self, QuicIdentity, QuicMode},
+        tcp, udp,
+    },
+};
+
+/// A node's socket once bound, with the task receiving packets from it already running.
+/// Binding all nodes before connecting them lets nodes use ephemeral ports.
+#[derive(Debug)]
+pub struct SocketBinding {
+    local_addr: SocketAddr,
+    socket: BoundSocket,
+}
+
+#[derive(Debug)]
+enum BoundSocket {
+    Udp(Arc<tokio::net::UdpSocket>),
+    /// Incoming connections are handled by the accept loop, outgoing ones are opened by the peers
+    Tcp,
+    Quic(quinn::Endpoint, QuicMode),
+}
+
+impl SocketBinding {
+    /// Binds `addr` for the given transport and forwards every packet received on it to `inbox`.
+    /// QUIC transports need the network's shared `identity`.
+    pub async fn bind(
+        kind: TransportKind,
+        addr: SocketAddr,
+        identity: Option<&QuicIdentity>,
+        inbox: mpsc::Sender<SerialiedPacket>,
+    ) -> io::Result<Self> {
+        let quic_identity = || {
+            identity.ok_or_else(|| {
+                io::Error::new(io::ErrorKind::InvalidInput, "QUIC needs an identity")
+            })
+        };
+
+        let (local_addr, socket) = match kind {
+            TransportKind::Udp => {
+                let socket = udp::bind(addr).await?;
+                tokio::spawn(udp::receive_loop(socket.clone(), inbox));
+                (socket.local_addr()?, BoundSocket::Udp(socket))
+            }
+            TransportKind::Tcp => {
+                let listener = tcp::bind(addr).await?;
+                let local_addr = listener.local_addr()?;
+                tokio::spawn(tcp::accept_loop(listener, inbox));
+                (local_addr, BoundSocket::Tcp)
+            }
+            TransportKind::QuicStream | TransportKind::QuicDatagram => {
+                let endpoint = quic::bind(quic_identity()?, addr)?;
+                tokio::spawn(quic::accept_loop(endpoint.clone(), inbox));
+                let mode = if kind == TransportKind::QuicStream {
+                    QuicMode::Stream
+                } else {
+                    QuicMode::Datagram
+                };
+                (endpoint.local_addr()?, BoundSocket::Quic(endpoint, mode))
+            }
+            TransportKind::Mpsc | TransportKind::Sim => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("{kind:?} is not a socket transport"),
+                ));
+            }
+        };
+
+        Ok(Self { local_addr, socket })
+    }
+
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
+
+    /// Builds the node's transport towards its neighbors listening on `peers`. Packets received on
+    /// the socket are read back from `inbox`.
+    pub fn into_transport(
+        self,
+        latency: Duration,
+        inbox: mpsc::Receiver<SerialiedPacket>,
+        peers: HashMap<NodeId, SocketAddr>,
+    ) -> SocketTransport {
+        // The connection tasks report the packets they drop to the node too
+        let failures = SendFailures::new();
+        let peers = peers
+            .into_iter()
+            .map(|(node_id, addr)| {
+                let peer = match &self.socket {
+                    BoundSocket::Udp(socket) => {
+                        PeerSender::Udp(udp::UdpPeer::new(socket.clone(), addr))
+                    }
+                    BoundSocket::Tcp => PeerSender::Tcp(tcp::TcpPeer::connect(
+                        addr,
+                        failures.sender(node_id.clone()),
+                    )),
+                    BoundSocket::Quic(endpoint, mode) => PeerSender::Quic(quic::QuicPeer::connect(
+                        endpoint.clone(),
+                        addr,
+                        *mode,
+                        failures.sender(node_id.clone()),
+                    )),
+                };
+                (node_id, peer)
+            })
+            .collect();
+
+        SocketTransport::new(self.local_addr, latency, inbox, peers, failures)
+    }
+}
+
+/// Sending half of a link to one neighbor over a socket
+#[derive(Debug, Clone)]
+pub enum PeerSender {
+    Udp(udp::UdpPeer),
+    Tcp(tcp::TcpPeer),
+    Quic(quic::QuicPeer),
+}
+
+impl PeerSender {
+    pub async fn send(&self, packet: SerialiedPacket) -> Result<(), TransportError> {
+        match self {
+            PeerSender::Udp(peer) => peer.send(packet).await,
+            PeerSender::Tcp(peer) => peer.send(packet).await,
+            PeerSender::Quic(peer) => peer.send(packet).await,
+        }
+    }
+}
+
+/// Transport over real sockets. The latency is emulated on the sender side, before the packet is
+/// handed to the socket. Packets read from the socket by the receiving tasks land in `inbox`.
+#[derive(Debug)]
+pub struct SocketTransport {
+    local_addr: SocketAddr,
+    latency: Duration,
+    inbox: mpsc::Receiver<SerialiedPacket>,
+    peers: HashMap<NodeId, PeerSender>,
+    failures: SendFailures,
+}
+
+impl SocketTransport {
+    fn new(
+        local_addr: SocketAddr,
+        latency: Duration,
+        inbox: mpsc::Receiver<SerialiedPacket>,
+        peers: HashMap<NodeId, PeerSender>,
+        failures: SendFailures,
+    ) -> Self {
+        Self {
+            local_addr,
+            latency,
+            inbox,
+            peers,
+            failures,
+        }
+    }
+}
+
+impl Transport for SocketTransport {
+    async fn send(&self, peer: &NodeId, packet: SerialiedPacket) -> Result<(), TransportError> {
+        let sender = self
+            .peers
+            .get(peer)
+            .ok_or_else(|| TransportError::UnknownPeer(peer.clone()))?
+            .clone();
+
+        let packet_id = packet.id();
+        self.failures
+            .send_with_delay(peer.clone(), packet_id, self.latency, async move {
+                sender.send(packet).await
+            });
+        Ok(())
+    }
+
+    async fn recv(&mut self) -> Option<SerialiedPacket> {
+        self.inbox.recv().await
+    }
+
+    fn take_failures(&mut self) -> Option<mpsc::UnboundedReceiver<SendFailure>> {
+        self.failures.take()
+    }
+
+    fn local_addr(&self) -> LocalAddr {
+        LocalAddr::Socket(self.local_addr)
+    }
+}
@@ -0,0 +1,276 @@
+use std::{io, net::SocketAddr, time::Duration};
+
+use tokio::{
+    io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+    sync::mpsc,
+};
+
+use crate::{
+    packet::SerialiedPacket,
+    transport::{FailureSender, TransportError},
+};
+
+/// Largest frame payload accepted on a connection. Anything bigger is treated as a protocol error.
+pub const MAX_FRAME_SIZE: usize = 64 * 1024;
+
+/// Number of times a writer tries to (re)connect and write a packet before dropping it, along with
+/// the packets queued behind it
+const MAX_CONNECT_ATTEMPTS: u32 = 5;
+
+/// Delay before the second attempt, doubled after each failure
+const INITIAL_BACKOFF: Duration = Duration::from_millis(10);
+
+/// Number of packets that can be queued on a connection before senders wait
+const OUTBOX_SIZE: usize = 32;
+
+/// Writes one frame: a big-endian `u32` length prefix followed by the payload.
+pub async fn write_frame<W: AsyncWrite + Unpin>(writer: &mut W, payload: &[u8]) -> io::Result<()> {
+    if payload.len() > MAX_FRAME_SIZE {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("frame of {} bytes exceeds {MAX_FRAME_SIZE}", payload.len()),
+        ));
+    }
+
+    writer.write_u32(payload.len() as u32).await?;
+    writer.write_all(payload).await?;
+    writer.flush().await
+}
+
+/// Reads one frame. Short reads are retried until the whole frame is available.
+///
+/// Returns `Ok(None)` if the connection was closed cleanly between two frames, and an
+/// `UnexpectedEof` error if it was closed in the middle of one.
+pub async fn read_frame<R: AsyncRead + Unpin>(reader: &mut R) -> io::Result<Option<Vec<u8>>> {
+    let mut len_bytes = [0u8; 4];
+    let mut read = 0;
+
+    while read < len_bytes.len() {
+        match reader.read(&mut len_bytes[read..]).await? {
+            0 if read == 0 => return Ok(None),
+            0 => return Err(io::ErrorKind::UnexpectedEof.into()),
+            n => read += n,
+        }
+    }
+
+    let len = u32::from_be_bytes(len_bytes) as usize;
+    if len > MAX_FRAME_SIZE {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("frame of {len} bytes exceeds {MAX_FRAME_SIZE}"),
+        ));
+    }
+
+    let mut payload = vec![0u8; len];
+    reader.read_exact(&mut payload).await?;
+    Ok(Some(payload))
+}
+
+/// A node's listening socket
+pub async fn bind(addr: SocketAddr) -> io::Result<TcpListener> {
+    TcpListener::bind(addr).await
+}
+
+/// Accepts connections from neighbors and forwards their frames to the node's inbox.
+pub async fn accept_loop(listener: TcpListener, inbox: mpsc::Sender<SerialiedPacket>) {
+    loop {
+        match listener.accept().await {
+            Ok((stream, _)) => {
+                tokio::spawn(connection_reader(stream, inbox.clone()));
+            }
+            Err(e) => eprintln!("Failed to accept connection: {e}"),
+        }
+
+        if inbox.is_closed() {
+            break;
+        }
+    }
+}
+
+async fn connection_reader(mut stream: TcpStream, inbox: mpsc::Sender<SerialiedPacket>) {
+    loop {
+        match read_frame(&mut stream).await {
+            Ok(Some(payload)) => {
+                if inbox.send(SerialiedPacket::from(payload)).await.is_err() {
+                    break;
+                }
+            }
+            Ok(None) => break,
+            Err(e) => {
+                eprintln!("Closing connection: {e}");
+                break;
+            }
+        }
+    }
+}
+
+/// Sending half of a TCP link. Packets are queued to a task owning the persistent connection to
+/// the neighbor, so all packets to one neighbor share a single ordered stream.
+#[derive(Debug, Clone)]
+pub struct TcpPeer {
+    outbox: mpsc::Sender<SerialiedPacket>,
+}
+
+impl TcpPeer {
+    /// Spawns the connection task. The connection itself is only opened with the first packet.
+    /// Packets the task gives up on are reported to `failures`.
+    pub fn connect(addr: SocketAddr, failures: FailureSender) -> Self {
+        let (outbox, rx) = mpsc::channel(OUTBOX_SIZE);
+        tokio::spawn(connection_writer(addr, rx, failures));
+        Self { outbox }
+    }
+
+    pub async fn send(&self, packet: SerialiedPacket) -> Result<(), TransportError> {
+        let size = packet.as_bytes().len();
+        if size > MAX_FRAME_SIZE {
+            return Err(TransportError::TooLarge {
+                size,
+                max: MAX_FRAME_SIZE,
+            });
+        }
+
+        self.outbox
+            .send(packet)
+            .await
+            .map_err(|_| TransportError::Closed)
+    }
+}
+
+async fn connection_writer(
+    addr: SocketAddr,
+    mut outbox: mpsc::Receiver<SerialiedPacket>,
+    failures: FailureSender,
+) {
+    let mut stream: Option<TcpStream> = None;
+
+    while let Some(packet) = outbox.recv().await {
+        let mut backoff = INITIAL_BACKOFF;
+        let mut error = None;
+
+        for attempt in 1..=MAX_CONNECT_ATTEMPTS {
+            let written = match stream.as_mut() {
+                Some(connection) => write_frame(connection, packet.as_bytes()).await,
+                None => match connect(addr).await {
+                    Ok(connection) => {
+                        write_frame(stream.insert(connection), packet.as_bytes()).await
+                    }
+                    Err(e) => Err(e),
+                },
+            };
+
+            match written {
+                Ok(()) => {
+                    error = None;
+                    break;
+                }
+                Err(e) => {
+                    // The connection is unusable, reconnect and resend the whole frame
+                    eprintln!("Failed to send to {addr} (attempt {attempt}): {e}");
+                    error = Some(e);
+                    stream = None;
+                    if attempt < MAX_CONNECT_ATTEMPTS {
+                        tokio::time::sleep(backoff).await;
+                        backoff *= 2;
+                    }
+                }
+            }
+        }
+
+        // Every attempt failed, the packet is lost. The peer is down: the packets queued behind it
+        // are dropped right away rather than each waiting through the same attempts.
+        if let Some(e) = error {
+            let queued = std::iter::from_fn(|| outbox.try_recv().ok());
+            for lost in std::iter::once(packet).chain(queued) {
+                let e = io::Error::new(e.kind(), e.to_string());
+                failures.send(lost.id(), TransportError::Io(e));
+            }
+        }
+    }
+}
+
+async fn connect(addr: SocketAddr) -> io::Result<TcpStream> {
+    let stream = TcpStream::connect(addr).await?;
+    // Packets are small and latency sensitive, don't wait to coalesce them
+    stream.set_nodelay(true)?;
+    Ok(stream)
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::io::duplex;
+
+    use super::*;
+    use crate::{
+        codec::{BorshCodec, Codec},
+        network::NodeId,
+        packet::{GossipPacket, PacketId},
+        transport::SendFailures,
+    };
+
+    #[tokio::test]
+    /// Frames written one byte at a time are reassembled
+    async fn test_partial_reads() {
+        let (mut client, mut server) = duplex(1);
+        let payloads = [vec![1, 2, 3], vec![], vec![42; 300]];
+
+        let expected = payloads.clone();
+        let writer = tokio::spawn(async move {
+            for payload in payloads {
+                write_frame(&mut client, &payload).await.unwrap();
+            }
+        });
+
+        for payload in expected {
+            assert_eq!(read_frame(&mut server).await.unwrap(), Some(payload));
+        }
+        writer.await.unwrap();
+        assert_eq!(read_frame(&mut server).await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    /// Oversized frames are rejected on both sides, truncated frames are errors
+    async fn test_invalid_frames() {
+        let (mut client, mut server) = duplex(1024);
+        let too_large = vec![0; MAX_FRAME_SIZE + 1];
+        assert!(write_frame(&mut client, &too_large).await.is_err());
+
+        client.write_u32(MAX_FRAME_SIZE as u32 + 1).await.unwrap();
+        let e = read_frame(&mut server).await.unwrap_err();
+        assert_eq!(e.kind(), io::ErrorKind::InvalidData);
+
+        let (mut client, mut server) = duplex(1024);
+        client.write_u32(10).await.unwrap();
+        client.write_all(&[0; 5]).await.unwrap();
+        drop(client);
+        let e = read_frame(&mut server).await.unwrap_err();
+        assert_eq!(e.kind(), io::ErrorKind::UnexpectedEof);
+    }
+
+    #[tokio::test(start_paused = true)]
+    /// Packets to a neighbor nobody listens for anymore are reported once every attempt for the
+    /// first one failed, the queued ones without retrying
+    async fn test_dead_peer() {
+        let listener = bind("127.0.0.1:0".parse().unwrap()).await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        drop(listener);
+
+        let mut failures = SendFailures::new();
+        let peer = TcpPeer::connect(addr, failures.sender(NodeId::new(1)));
+        let mut failures = failures.take().unwrap();
+        let start = tokio::time::Instant::now();
+        for id in 3..6 {
+            let packet = GossipPacket::new_with_random_order(PacketId::new(id), NodeId::new(0), 3);
+            peer.send(BorshCodec.encode(&packet)).await.unwrap();
+        }
+
+        // Backing off 10, 20, 40 then 80 ms between the attempts
+        for id in 3..6 {
+            let failure = failures.recv().await.unwrap();
+            assert_eq!(failure.peer, NodeId::new(1));
+            assert_eq!(failure.packet_id, PacketId::new(id));
+            assert!(matches!(failure.error, TransportError::Io(_)));
+            assert_eq!(start.elapsed(), Duration::from_millis(150));
+        }
+    }
+}
@@ -0,0 +1,69 @@
+use std::{io, net::SocketAddr, sync::Arc};
+
+use tokio::{net::UdpSocket, sync::mpsc};
+
+use crate::{packet::SerialiedPacket, transport::TransportError};
+
+/// Largest payload that fits in a single datagram without IP fragmentation on a 1500 bytes MTU
+/// link (1500 - 20 bytes IPv4 header - 8 bytes UDP header).
+pub const MAX_DATAGRAM_SIZE: usize = 1472;
+
+/// A node's own UDP socket
+pub async fn bind(addr: SocketAddr) -> io::Result<Arc<UdpSocket>> {
+    Ok(Arc::new(UdpSocket::bind(addr).await?))
+}
+
+/// Sending half of a UDP link: the node's socket and the neighbor's address
+#[derive(Debug, Clone)]
+pub struct UdpPeer {
+    socket: Arc<UdpSocket>,
+    addr: SocketAddr,
+}
+
+impl UdpPeer {
+    pub fn new(socket: Arc<UdpSocket>, addr: SocketAddr) -> Self {
+        Self { socket, addr }
+    }
+
+    pub async fn send(&self, packet: SerialiedPacket) -> Result<(), TransportError> {
+        let bytes = packet.as_bytes();
+        if bytes.len() > MAX_DATAGRAM_SIZE {
+            return Err(TransportError::TooLarge {
+                size: bytes.len(),
+                max: MAX_DATAGRAM_SIZE,
+            });
+        }
+
+        self.socket.send_to(bytes, self.addr).await?;
+        Ok(())
+    }
+}
+
+/// Reads datagrams from the socket and hands them to the node's inbox until the inbox is closed.
+pub async fn receive_loop(socket: Arc<UdpSocket>, inbox: mpsc::Sender<SerialiedPacket>) {
+    // One extra byte so that oversized datagrams are detected instead of silently truncated
+    let mut buffer = [0u8; MAX_DATAGRAM_SIZE + 1];
+
+    loop {
+        let len = match socket.recv_from(&mut buffer).await {
+            Ok((len, _)) => len,
+            Err(e) => {
+                eprintln!("Failed to receive datagram: {e}");
+                continue;
+            }
+        };
+
+        if len > MAX_DATAGRAM_SIZE {
+            eprintln!("Dropping datagram larger than {MAX_DATAGRAM_SIZE} bytes");
+            continue;
+        }
+
+        if inbox
+            .send(SerialiedPacket::from(buffer[..len].to_vec()))
+            .await
+            .is_err()
+        {
+            break;
+        }
+    }
+}